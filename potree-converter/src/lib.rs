@@ -4,12 +4,13 @@ use serde_json::from_slice;
 use rusty_potree_converter::model::vector3::Vector3;
 use crate::utils::set_panic_hook;
 use std::error::Error;
-use serde_json::Value;
-use rusty_potree_converter::model::hierarchy::create_hierarchy;
-use rusty_potree_converter::writer::{Writer, WriteError, create_metadata};
-use rusty_potree_converter::potree::Potree;
+use rusty_potree_converter::archive::Compression;
+use rusty_potree_converter::model::metadata::Metadata;
+use rusty_potree_converter::writer::{write_potree_to_buffers, PotreeFiles};
+use rusty_potree_converter::point_reader::Registry;
+use rusty_potree_converter::potree::{BuildOptions, Potree};
 use wasm_bindgen::prelude::*;
-use std::io::Write;
+use std::io::Cursor;
 
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
 // allocator.
@@ -18,11 +19,14 @@ use std::io::Write;
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
 
+/// Converts a point file, `file_type` being its name or extension for formats that are
+/// not recognized by their first bytes.
 #[wasm_bindgen]
 pub fn process_array_buffer(file_type: &str, buffer: &[u8]) -> Result<PotreeData, JsError> {
 	set_panic_hook();
 
-	let potree = rusty_potree_converter::pcd_reader::from_pcd(buffer).expect("To parse pcd");
+	let mut reader = Registry::default().open(file_type, buffer)?;
+	let potree = Potree::from_reader(reader.as_mut(), &BuildOptions::default())?;
 	
 	let potree_data = write_potree_to_struct(potree).expect("To Get Potree Struct");
 	std::mem::forget(&potree_data);
@@ -31,43 +35,38 @@ pub fn process_array_buffer(file_type: &str, buffer: &[u8]) -> Result<PotreeData
 
 #[wasm_bindgen]
 pub struct PotreeData {
-    octree: Vec<u8>,
-    hierarchy: Vec<u8>,
-    metadata: Vec<u8>,
+    files: PotreeFiles,
 }
 
 #[wasm_bindgen]
 impl PotreeData {
-	pub fn get_metadata(&self) -> Result<JsValue, JsError> {
-		let untyped_json: Value = serde_json::from_slice::<Value>(&self.metadata)?;
-		let res = JsValue::from_serde(&untyped_json)?;
-		Ok(res)
+	pub fn get_metadata(&self) -> Metadata {
+		self.files.metadata.clone()
 	}
 	pub fn get_hierarchy(&self) -> js_sys::Uint8Array {
-		return js_sys::Uint8Array::from(&self.hierarchy[..]);
+		return js_sys::Uint8Array::from(&self.files.hierarchy[..]);
 	}
 	pub fn get_octree(&self) -> js_sys::Uint8Array {
-		return js_sys::Uint8Array::from(&self.octree[..]);
+		return js_sys::Uint8Array::from(&self.files.octree[..]);
+	}
+	/// Packs `metadata.json`, `hierarchy.bin` and `octree.bin` into a ZIP archive.
+	pub fn to_zip(&self, deflate: bool) -> Result<js_sys::Uint8Array, JsError> {
+		let archive = self.write_zip(deflate)?;
+		Ok(js_sys::Uint8Array::from(&archive[..]))
 	}
 }
 
-pub fn write_potree_to_struct(potree: Potree) -> Result<PotreeData, Box<dyn Error>> {
-    let mut octree_data: Vec<u8> = Vec::new();
-    let mut writer = Writer::new(&mut octree_data);
-    writer.write(&potree);
-
-    let mut hierarchy_data: Vec<u8> = Vec::new();
-    let hierarchy = create_hierarchy(&potree.root, writer.node_hierarchy);
-    hierarchy_data.write(&hierarchy.buffer)?;
-
-    let mut metadata_data: Vec<u8> = Vec::new();
-    let metadata = create_metadata(&potree, &hierarchy);
-    serde_json::to_writer(&mut metadata_data, &metadata)?;
+impl PotreeData {
+	fn write_zip(&self, deflate: bool) -> std::io::Result<Vec<u8>> {
+		let compression = if deflate { Compression::Deflate } else { Compression::Stored };
+		let cursor = self.files.write_archive(Cursor::new(Vec::new()), compression)?;
+		Ok(cursor.into_inner())
+	}
+}
 
+pub fn write_potree_to_struct(potree: Potree) -> Result<PotreeData, Box<dyn Error>> {
     Ok(PotreeData {
-        hierarchy: hierarchy_data,
-        metadata: metadata_data,
-        octree: octree_data,
+        files: write_potree_to_buffers(&potree),
     })
 }
 
@@ -77,40 +76,43 @@ mod tests {
 
     use serde_json::Value;
 use crate::write_potree_to_struct;
-use rusty_potree_converter::model::metadata::Metadata;
 	use rusty_potree_converter::model::vector3::Vector3;
-    use rusty_potree_converter::potree::Potree;
-    use byteorder::LittleEndian;
-    use byteorder::ReadBytesExt;
+use rusty_potree_converter::potree::Potree;
+    use rusty_potree_converter::raw_reader::{read_raw, RawLayout};
     use std::fs;
-    use std::io::Cursor;
 
     #[test]
     fn test_write_to_struct() {
         let buffer = fs::read("../resources/points.bin").unwrap();
-        let mut cursor = Cursor::new(buffer);
-        let mut points: Vec<Vector3> = Vec::new();
         let num_points = 100;
 
 		let record_size = 8 * 3;
-        while cursor.position() < (num_points * record_size) as u64 {
-            points.push(Vector3 {
-                x: cursor.read_f64::<LittleEndian>().unwrap(),
-                y: cursor.read_f64::<LittleEndian>().unwrap(),
-                z: cursor.read_f64::<LittleEndian>().unwrap(),
-            })
-        }
-        let potree = Potree::new(points, 20000);
+        let potree = read_raw(&buffer[..num_points * record_size], &RawLayout::xyz_f64())
+            .unwrap()
+            .into_potree();
 
         let potree_data = write_potree_to_struct(potree).unwrap();
 
-        let points_written = potree_data.octree.len() / (3 * 4);
+        let points_written = potree_data.files.octree.len() / (3 * 4);
         assert_eq!(num_points as usize, points_written);
-		let metadata = serde_json::from_slice::<Metadata>(&potree_data.metadata).unwrap();
+		let metadata = potree_data.get_metadata();
 		
 		assert_eq!(metadata.points, 100);
 		assert_eq!(metadata.version, "2.0");
     }
 
+    #[test]
+    fn test_write_zip() {
+        let points = (0..100)
+            .map(|i| Vector3 { x: i as f64, y: (i % 10) as f64, z: 0.0 })
+            .collect();
+        let potree_data = write_potree_to_struct(Potree::new(points, 20000)).unwrap();
+
+        for deflate in [false, true] {
+            let archive = potree_data.write_zip(deflate).unwrap();
+            assert_eq!(&archive[0..4], b"PK\x03\x04");
+        }
+    }
+
 	
 }
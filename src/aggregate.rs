@@ -0,0 +1,389 @@
+use core::fmt;
+
+use serde_json::Value;
+
+use crate::model::attributes::{Attribute, AttributeType, Attributes};
+use crate::model::point::Point;
+use crate::model::point_cloud::PointCloud;
+use crate::model::vector3::Vector3;
+use crate::potree::{BuildOptions, Potree};
+
+/// Sensor to world transform of one frame.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pose {
+    pub timestamp: f64,
+    /// Row-major 4x4 homogeneous matrix.
+    pub matrix: [[f64; 4]; 4],
+}
+
+impl Pose {
+    pub fn identity(timestamp: f64) -> Pose {
+        let mut matrix = [[0.0; 4]; 4];
+        for (i, row) in matrix.iter_mut().enumerate() {
+            row[i] = 1.0;
+        }
+        Pose { timestamp, matrix }
+    }
+
+    /// Builds a pose from 12 (3x4) or 16 (4x4) row-major values.
+    fn from_values(timestamp: f64, values: &[f64]) -> Option<Pose> {
+        if values.len() != 12 && values.len() != 16 {
+            return None;
+        }
+        let mut pose = Pose::identity(timestamp);
+        for (i, value) in values.iter().enumerate() {
+            pose.matrix[i / 4][i % 4] = *value;
+        }
+        Some(pose)
+    }
+
+    pub fn transform(&self, point: &Vector3) -> Vector3 {
+        let m = &self.matrix;
+        let [x, y, z] = [0, 1, 2]
+            .map(|row| m[row][0] * point.x + m[row][1] * point.y + m[row][2] * point.z + m[row][3]);
+        Vector3 { x, y, z }
+    }
+}
+
+#[derive(Debug)]
+pub enum PoseReadError {
+    Json(serde_json::Error),
+    InvalidPose { index: usize, msg: String },
+    InvalidValue { line: usize, value: String },
+    NoPoses,
+}
+
+impl fmt::Display for PoseReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PoseReadError::Json(error) => write!(f, "Could not parse pose file: {}", error),
+            PoseReadError::InvalidPose { index, msg } => write!(f, "Pose {}: {}", index, msg),
+            PoseReadError::InvalidValue { line, value } => {
+                write!(f, "Line {}: could not parse '{}' as a number", line, value)
+            }
+            PoseReadError::NoPoses => write!(f, "No poses in file"),
+        }
+    }
+}
+
+impl std::error::Error for PoseReadError {}
+
+impl From<serde_json::Error> for PoseReadError {
+    fn from(error: serde_json::Error) -> Self {
+        PoseReadError::Json(error)
+    }
+}
+
+/// Flattens a matrix given either as 16 numbers or as 4 rows of 4 numbers.
+fn matrix_values(value: &Value) -> Option<Vec<f64>> {
+    let mut values = Vec::new();
+    for element in value.as_array()? {
+        match element {
+            Value::Array(row) => {
+                for value in row {
+                    values.push(value.as_f64()?);
+                }
+            }
+            value => values.push(value.as_f64()?),
+        }
+    }
+    Some(values)
+}
+
+/// Reads poses from a JSON array, one entry per frame.
+///
+/// An entry is either a bare matrix or an object with the matrix under `pose` or
+/// `matrix` and an optional `timestamp` in seconds. Matrices are row-major, as 16
+/// numbers, 12 numbers for the upper 3x4 part or nested rows. Entries without a
+/// timestamp use their index.
+pub fn read_poses_json(buf: &[u8]) -> Result<Vec<Pose>, PoseReadError> {
+    let json: Value = serde_json::from_slice(buf)?;
+    let entries = json
+        .as_array()
+        .or_else(|| json.get("poses").and_then(Value::as_array))
+        .ok_or(PoseReadError::InvalidPose {
+            index: 0,
+            msg: "expected an array of poses".to_string(),
+        })?;
+
+    let mut poses = Vec::with_capacity(entries.len());
+    for (index, entry) in entries.iter().enumerate() {
+        let (timestamp, matrix) = match entry {
+            Value::Object(object) => (
+                object.get("timestamp").and_then(Value::as_f64),
+                object.get("pose").or_else(|| object.get("matrix")),
+            ),
+            matrix => (None, Some(matrix)),
+        };
+        let pose = matrix
+            .and_then(matrix_values)
+            .and_then(|values| Pose::from_values(timestamp.unwrap_or(index as f64), &values))
+            .ok_or(PoseReadError::InvalidPose {
+                index,
+                msg: "expected a 3x4 or 4x4 matrix".to_string(),
+            })?;
+        poses.push(pose);
+    }
+    if poses.is_empty() {
+        return Err(PoseReadError::NoPoses);
+    }
+    Ok(poses)
+}
+
+/// Reads poses from a delimited text file with one frame per line.
+///
+/// Lines hold a row-major 3x4 or 4x4 matrix, optionally preceded by a timestamp in
+/// seconds, so KITTI odometry `poses.txt` files are read as is. Values may be separated
+/// by whitespace, commas or semicolons. A non-numeric first line is skipped as a header.
+pub fn read_poses_csv(buf: &[u8]) -> Result<Vec<Pose>, PoseReadError> {
+    let text = String::from_utf8_lossy(buf);
+    let mut poses = Vec::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let fields: Vec<&str> = line
+            .split(|c: char| c.is_whitespace() || c == ',' || c == ';')
+            .filter(|field| !field.is_empty())
+            .collect();
+        if fields.is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+        let values: Result<Vec<f64>, _> = fields.iter().map(|field| field.parse::<f64>()).collect();
+        let values = match values {
+            Ok(values) => values,
+            Err(_) if poses.is_empty() && i == 0 => continue,
+            Err(_) => {
+                let value = fields.iter().find(|field| field.parse::<f64>().is_err());
+                return Err(PoseReadError::InvalidValue {
+                    line: i + 1,
+                    value: value.unwrap_or(&"").to_string(),
+                });
+            }
+        };
+
+        let index = poses.len();
+        let pose = match values.len() {
+            13 | 17 => Pose::from_values(values[0], &values[1..]),
+            _ => Pose::from_values(index as f64, &values),
+        };
+        poses.push(pose.ok_or(PoseReadError::InvalidPose {
+            index,
+            msg: format!("expected 12, 13, 16 or 17 values, found {}", values.len()),
+        })?);
+    }
+    if poses.is_empty() {
+        return Err(PoseReadError::NoPoses);
+    }
+    Ok(poses)
+}
+
+#[derive(Default)]
+pub struct AggregateOptions {
+    /// Drops points closer than this to the sensor origin of their frame, removing hits
+    /// on the ego vehicle.
+    pub ego_radius: Option<f64>,
+}
+
+#[derive(Debug)]
+pub enum AggregateError {
+    FrameCount { frames: usize, poses: usize },
+    SchemaMismatch { frame: usize },
+    NoPoints,
+}
+
+impl fmt::Display for AggregateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AggregateError::FrameCount { frames, poses } => {
+                write!(f, "Got {} frames but {} poses", frames, poses)
+            }
+            AggregateError::SchemaMismatch { frame } => {
+                write!(
+                    f,
+                    "Frame {} has different attributes than the first frame",
+                    frame
+                )
+            }
+            AggregateError::NoPoints => write!(f, "No points left after aggregation"),
+        }
+    }
+}
+
+impl std::error::Error for AggregateError {}
+
+fn is_aggregate_attribute(attribute: &Attribute) -> bool {
+    attribute.name == "frame_index" || attribute.name == "timestamp"
+}
+
+fn same_schema(a: &Attributes, b: &Attributes) -> bool {
+    a.list.len() == b.list.len()
+        && a.list.iter().zip(&b.list).all(|(a, b)| {
+            a.name == b.name && a.r#type == b.r#type && a.num_elements == b.num_elements
+        })
+}
+
+/// Merges lidar frames into one octree in the world frame given by their poses.
+///
+/// Frames come from any reader and must share the same attributes. Each point is
+/// transformed by the pose of its frame and tagged with a uint32 `frame_index` and the
+/// pose timestamp as a double `timestamp`, replacing attributes of those names. The
+/// frames are indexed once, as a whole, with `build`.
+pub fn aggregate_frames(
+    frames: Vec<PointCloud>,
+    poses: &[Pose],
+    options: &AggregateOptions,
+    build: &BuildOptions,
+) -> Result<Potree, AggregateError> {
+    if frames.len() != poses.len() {
+        return Err(AggregateError::FrameCount {
+            frames: frames.len(),
+            poses: poses.len(),
+        });
+    }
+    let schema = match frames.first() {
+        Some(frame) => frame.attributes.clone(),
+        None => return Err(AggregateError::NoPoints),
+    };
+
+    // Byte ranges of the attributes kept from the frames.
+    let mut kept = Vec::new();
+    let mut offset = 0;
+    for attribute in &schema.list {
+        if !is_aggregate_attribute(attribute) {
+            kept.push(offset..offset + attribute.size as usize);
+        }
+        offset += attribute.size as usize;
+    }
+    let mut attribute_list: Vec<Attribute> = schema
+        .list
+        .iter()
+        .filter(|attribute| !is_aggregate_attribute(attribute))
+        .map(|attribute| Attribute::new(&attribute.name, attribute.r#type, attribute.num_elements))
+        .collect();
+    attribute_list.push(Attribute::new("frame_index", AttributeType::UINT32, 1));
+    attribute_list.push(Attribute::new("timestamp", AttributeType::DOUBLE, 1));
+
+    let squared_radius = options.ego_radius.map(|radius| radius * radius);
+    let mut points = Vec::new();
+    for (frame_index, (frame, pose)) in frames.into_iter().zip(poses).enumerate() {
+        if !same_schema(&frame.attributes, &schema) {
+            return Err(AggregateError::SchemaMismatch { frame: frame_index });
+        }
+        for point in frame.points {
+            let p = &point.position;
+            if squared_radius.is_some_and(|r| p.x * p.x + p.y * p.y + p.z * p.z < r) {
+                continue;
+            }
+            let mut attributes = Vec::with_capacity(point.attributes.len() + 12);
+            for range in &kept {
+                attributes.extend_from_slice(&point.attributes[range.clone()]);
+            }
+            AttributeType::UINT32.write_f64(frame_index as f64, &mut attributes);
+            AttributeType::DOUBLE.write_f64(pose.timestamp, &mut attributes);
+            points.push(Point::with_attributes(pose.transform(p), attributes));
+        }
+    }
+    if points.is_empty() {
+        return Err(AggregateError::NoPoints);
+    }
+
+    let mut potree = Potree::with_attributes(
+        points,
+        Attributes::from_attributes(attribute_list),
+        build.point_per_leaf_node_limit,
+    );
+    potree.crs = build.crs.clone();
+    Ok(potree)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::aggregate::{
+        aggregate_frames, read_poses_csv, read_poses_json, AggregateError, AggregateOptions,
+    };
+    use crate::potree::BuildOptions;
+    use crate::raw_reader::{read_raw, RawLayout};
+
+    fn kitti_frame(points: &[[f32; 4]]) -> Vec<u8> {
+        points
+            .iter()
+            .flatten()
+            .flat_map(|value| value.to_le_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn test_read_poses() -> Result<(), Box<dyn std::error::Error>> {
+        let kitti = read_poses_csv(b"1 0 0 10 0 1 0 0 0 0 1 0\n1 0 0 20 0 1 0 0 0 0 1 0\n")?;
+        assert_eq!(kitti.len(), 2);
+        assert_eq!((kitti[1].timestamp, kitti[1].matrix[0][3]), (1.0, 20.0));
+        assert_eq!(kitti[1].matrix[3], [0.0, 0.0, 0.0, 1.0]);
+
+        let csv = read_poses_csv(
+            b"t,m00,m01,m02,m03,m10,m11,m12,m13,m20,m21,m22,m23,m30,m31,m32,m33\n\
+              0.5,1,0,0,1,0,1,0,2,0,0,1,3,0,0,0,1\n",
+        )?;
+        assert_eq!(csv[0].timestamp, 0.5);
+        assert_eq!(csv[0].matrix[2][3], 3.0);
+
+        let json = read_poses_json(
+            br#"[{"timestamp": 2.0, "pose": [[0, -1, 0, 0], [1, 0, 0, 0], [0, 0, 1, 0], [0, 0, 0, 1]]}]"#,
+        )?;
+        assert_eq!(json[0].timestamp, 2.0);
+        assert_eq!(json[0].matrix[0][1], -1.0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_aggregate_frames() -> Result<(), Box<dyn std::error::Error>> {
+        let frame = kitti_frame(&[
+            [0.5, 0.0, 0.0, 0.1],
+            [5.0, 0.0, 0.0, 0.2],
+            [0.0, 5.0, 1.0, 0.3],
+        ]);
+        let frames = vec![
+            read_raw(&frame, &RawLayout::kitti())?,
+            read_raw(&frame, &RawLayout::kitti())?,
+        ];
+        let poses = read_poses_json(
+            br#"[{"timestamp": 10.0, "pose": [1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0]},
+                 {"timestamp": 10.1, "pose": [1, 0, 0, 100, 0, 1, 0, 0, 0, 0, 1, 0]}]"#,
+        )?;
+        let options = AggregateOptions {
+            ego_radius: Some(1.0),
+        };
+        let build = BuildOptions {
+            point_per_leaf_node_limit: 2,
+            ..BuildOptions::default()
+        };
+        let potree = aggregate_frames(frames, &poses, &options, &build)?;
+        assert!(!potree.root.is_leaf_node());
+
+        assert_eq!(potree.size, 4);
+        assert_eq!((potree.bounds.lx, potree.bounds.ux), (0.0, 105.0));
+        let names: Vec<&str> = potree
+            .attributes
+            .list
+            .iter()
+            .map(|a| a.name.as_str())
+            .collect();
+        assert_eq!(names, ["intensity", "frame_index", "timestamp"]);
+        let timestamp = potree.attributes.get("timestamp").unwrap();
+        assert_eq!((timestamp.min.x, timestamp.max.x), (10.0, 10.1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_aggregate_frame_count() {
+        let frame = kitti_frame(&[[1.0, 2.0, 3.0, 0.0]]);
+        let frames = vec![read_raw(&frame, &RawLayout::kitti()).unwrap()];
+        let options = AggregateOptions::default();
+        match aggregate_frames(frames, &[], &options, &BuildOptions::default()) {
+            Err(AggregateError::FrameCount { frames, poses }) => {
+                assert_eq!((frames, poses), (1, 0))
+            }
+            _ => panic!("Expected frame count error"),
+        }
+    }
+}
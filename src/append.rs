@@ -0,0 +1,415 @@
+use core::fmt;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::dataset::{DatasetError, PotreeDataset};
+use crate::model::bounds::Bounds;
+use crate::model::hierarchy::create_hierarchy;
+use crate::model::metadata::{Attribute, Hierarchy};
+use crate::model::node::{empty_child_node_array, Node};
+use crate::model::point_cloud::PointCloud;
+use crate::model::vector3::Vector3;
+use crate::potree::BuildOptions;
+use crate::writer::Writer;
+
+#[derive(Debug)]
+pub enum AppendError {
+    Dataset(DatasetError),
+    Io(io::Error),
+    /// The points do not carry the attributes of the dataset.
+    AttributeMismatch,
+    /// Points outside the bounding box of the dataset cannot be added to its octree.
+    OutsideCube {
+        count: usize,
+    },
+    /// The octree writer uses one scale for all axes.
+    UnsupportedScale,
+    /// `octree.bin` would outgrow the 32 bit offsets of the writer.
+    OctreeTooLarge,
+}
+
+impl fmt::Display for AppendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppendError::Dataset(error) => write!(f, "{}", error),
+            AppendError::Io(error) => write!(f, "{}", error),
+            AppendError::AttributeMismatch => {
+                write!(f, "The points do not have the attributes of the dataset")
+            }
+            AppendError::OutsideCube { count } => {
+                write!(
+                    f,
+                    "{} points are outside the bounding box of the dataset",
+                    count
+                )
+            }
+            AppendError::UnsupportedScale => {
+                write!(
+                    f,
+                    "Datasets with different scales per axis cannot be appended to"
+                )
+            }
+            AppendError::OctreeTooLarge => write!(f, "octree.bin would be larger than 4 GiB"),
+        }
+    }
+}
+
+impl std::error::Error for AppendError {}
+
+impl From<DatasetError> for AppendError {
+    fn from(error: DatasetError) -> AppendError {
+        AppendError::Dataset(error)
+    }
+}
+
+impl From<io::Error> for AppendError {
+    fn from(error: io::Error) -> AppendError {
+        AppendError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for AppendError {
+    fn from(error: serde_json::Error) -> AppendError {
+        AppendError::Dataset(DatasetError::Json(error))
+    }
+}
+
+fn root_bounds(dataset: &PotreeDataset) -> Bounds {
+    let (min, max) = (
+        dataset.metadata.bounding_box.min,
+        dataset.metadata.bounding_box.max,
+    );
+    Bounds::new(max[0], max[1], max[2], min[0], min[1], min[2])
+}
+
+fn find_node_mut<'a>(root: &'a mut Node, name: &str) -> Option<&'a mut Node> {
+    let mut node = root;
+    for digit in name[1..].bytes() {
+        node = node
+            .children
+            .get_mut(digit.checked_sub(b'0')? as usize)?
+            .as_deref_mut()?;
+    }
+    Some(node)
+}
+
+/// Rebuilds the octree of the dataset with every node empty.
+fn skeleton(dataset: &PotreeDataset, max_points_per_leaf_node: u32) -> Result<Node, AppendError> {
+    let mut root = Node::new(
+        "r".to_string(),
+        dataset.metadata.spacing,
+        root_bounds(dataset),
+        empty_child_node_array(),
+        max_points_per_leaf_node,
+    );
+    let mut nodes: Vec<_> = dataset
+        .nodes
+        .iter()
+        .filter(|node| node.level() > 0)
+        .collect();
+    nodes.sort_by_key(|node| node.level());
+    for node in nodes {
+        let (parent_name, index) = node.name.split_at(node.name.len() - 1);
+        let invalid = || DatasetError::InvalidHierarchy {
+            msg: format!("node {} has no parent", node.name),
+        };
+        let index: usize = index.parse().map_err(|_| invalid())?;
+        let parent = find_node_mut(&mut root, parent_name).ok_or_else(invalid)?;
+        let spacing = dataset.metadata.spacing / 2f64.powi(node.level() as i32);
+        parent.children[index] = Some(Box::new(Node::new(
+            node.name.clone(),
+            spacing,
+            node.bounds.clone(),
+            empty_child_node_array(),
+            max_points_per_leaf_node,
+        )));
+    }
+    Ok(root)
+}
+
+/// Writes `hierarchy.bin` for the octree below `root` and updates `metadata.json` to match.
+fn write_index(
+    dataset: &mut PotreeDataset,
+    root: &Node,
+    node_hierarchy: BTreeMap<String, (u32, u32, u32)>,
+) -> Result<(), AppendError> {
+    let hierarchy = create_hierarchy(root, node_hierarchy);
+    fs::write(dataset.dir().join("hierarchy.bin"), &hierarchy.buffer)?;
+
+    dataset.metadata.hierarchy = Hierarchy {
+        first_chunk_size: hierarchy.first_chunk_size as u16,
+        step_size: hierarchy.step_size,
+        depth: hierarchy.depth,
+    };
+    let file = File::create(dataset.dir().join("metadata.json"))?;
+    serde_json::to_writer(file, &dataset.metadata)?;
+    Ok(())
+}
+
+/// Adds points to the dataset in `dir` without converting it again. Only the nodes on the
+/// way of a new point are read and inserted into, with the usual sampling of inner nodes.
+/// Their new contents are written at the end of `octree.bin`, so the bytes they used before
+/// stay behind as dead space until [`compact_dataset`] is run.
+///
+/// Every point has to lie within the bounding box of the dataset and carry its attributes.
+pub fn append_points(
+    dir: &Path,
+    cloud: PointCloud,
+    options: &BuildOptions,
+) -> Result<(), AppendError> {
+    let mut dataset = PotreeDataset::open(dir)?;
+    let same_attributes = cloud.attributes.list.len() == dataset.attributes.list.len()
+        && cloud
+            .attributes
+            .list
+            .iter()
+            .zip(&dataset.attributes.list)
+            .all(|(a, b)| {
+                a.name == b.name && a.r#type == b.r#type && a.num_elements == b.num_elements
+            });
+    if !same_attributes {
+        return Err(AppendError::AttributeMismatch);
+    }
+    let bounds = root_bounds(&dataset);
+    let outside = cloud
+        .points
+        .iter()
+        .filter(|point| !bounds.contains(&point.position))
+        .count();
+    if outside > 0 {
+        return Err(AppendError::OutsideCube { count: outside });
+    }
+    let scale = dataset.metadata.scale;
+    if scale[1] != scale[0] || scale[2] != scale[0] {
+        return Err(AppendError::UnsupportedScale);
+    }
+    if cloud.is_empty() {
+        return Ok(());
+    }
+
+    let mut root = skeleton(&dataset, options.point_per_leaf_node_limit)?;
+
+    // A point can only end up in the nodes covering its position, down to the first
+    // octant the dataset has no node for.
+    let mut affected = HashSet::new();
+    for point in &cloud.points {
+        let mut node = &root;
+        loop {
+            affected.insert(node.name.clone());
+            let index = Node::find_grid_index(&point.position, &node.bounds);
+            match &node.children[index] {
+                Some(child) => node = child,
+                None => break,
+            }
+        }
+    }
+    for node in dataset
+        .nodes
+        .iter()
+        .filter(|node| affected.contains(&node.name))
+    {
+        let points = dataset.read_node(node)?;
+        if let Some(target) = find_node_mut(&mut root, &node.name) {
+            target.restore_points(points);
+        }
+    }
+
+    let position = &mut dataset.metadata.attributes[0];
+    for point in &cloud.points {
+        let values = [point.position.x, point.position.y, point.position.z];
+        for (axis, value) in values.into_iter().enumerate() {
+            position.min[axis] = position.min[axis].min(value);
+            position.max[axis] = position.max[axis].max(value);
+        }
+    }
+    dataset.metadata.points += cloud.len() as u64;
+    for point in &cloud.points {
+        dataset.attributes.update_ranges(&point.attributes);
+    }
+    dataset.metadata.attributes.truncate(1);
+    dataset.metadata.attributes.extend(
+        dataset
+            .attributes
+            .list
+            .iter()
+            .map(Attribute::from_attribute),
+    );
+
+    for point in cloud.points {
+        root.add_point(point);
+    }
+
+    // Nodes that were read or created are rewritten, breadth first.
+    let existing: HashMap<&str, _> = dataset
+        .nodes
+        .iter()
+        .map(|node| (node.name.as_str(), node))
+        .collect();
+    let mut rewritten = Vec::new();
+    let mut queue = VecDeque::from([&root]);
+    while let Some(node) = queue.pop_front() {
+        if affected.contains(&node.name) || !existing.contains_key(node.name.as_str()) {
+            rewritten.push(node);
+        }
+        queue.extend(node.children.iter().flatten().map(|child| child.as_ref()));
+    }
+
+    let bytes_per_point = 12 + dataset.attributes.bytes as u64;
+    let octree_path = dir.join("octree.bin");
+    let octree_len = fs::metadata(&octree_path)?.len();
+    let added: u64 = rewritten
+        .iter()
+        .map(|node| node.num_points() as u64 * bytes_per_point)
+        .sum();
+    if octree_len + added > u32::MAX as u64 {
+        return Err(AppendError::OctreeTooLarge);
+    }
+
+    let offset = dataset.metadata.offset;
+    let offset = Vector3 {
+        x: offset[0],
+        y: offset[1],
+        z: offset[2],
+    };
+    let mut file = BufWriter::new(OpenOptions::new().append(true).open(&octree_path)?);
+    let mut writer = Writer::appending(&mut file, octree_len as u32, bytes_per_point as u32);
+    for node in rewritten.iter().filter(|node| node.num_points() > 0) {
+        writer.write_points(node, scale[0], &offset);
+    }
+    let mut node_hierarchy = writer.node_hierarchy;
+    file.flush()?;
+
+    for (name, node) in existing {
+        if !affected.contains(name) && node.num_points > 0 {
+            node_hierarchy.insert(
+                name.to_string(),
+                (
+                    node.byte_size as u32,
+                    node.byte_offset as u32,
+                    node.num_points,
+                ),
+            );
+        }
+    }
+    write_index(&mut dataset, &root, node_hierarchy)
+}
+
+/// Rewrites `octree.bin` of the dataset in `dir` without the dead space left behind by
+/// [`append_points`], with the nodes in hierarchy order.
+pub fn compact_dataset(dir: &Path) -> Result<(), AppendError> {
+    let mut dataset = PotreeDataset::open(dir)?;
+    let root = skeleton(&dataset, BuildOptions::default().point_per_leaf_node_limit)?;
+
+    let octree_path = dir.join("octree.bin");
+    let compacted_path = dir.join("octree.bin.compact");
+    let mut octree = File::open(&octree_path)?;
+    let mut compacted = BufWriter::new(File::create(&compacted_path)?);
+    let mut node_hierarchy = BTreeMap::new();
+    let mut byte_offset = 0u32;
+    let mut buf = Vec::new();
+    for node in dataset.nodes.iter().filter(|node| node.byte_size > 0) {
+        buf.resize(node.byte_size as usize, 0);
+        octree.seek(SeekFrom::Start(node.byte_offset))?;
+        octree.read_exact(&mut buf)?;
+        compacted.write_all(&buf)?;
+        node_hierarchy.insert(
+            node.name.clone(),
+            (node.byte_size as u32, byte_offset, node.num_points),
+        );
+        byte_offset += node.byte_size as u32;
+    }
+    compacted.flush()?;
+    drop(compacted);
+    fs::rename(&compacted_path, &octree_path)?;
+
+    write_index(&mut dataset, &root, node_hierarchy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dataset::Query;
+    use crate::model::attributes::{Attribute, AttributeType, Attributes};
+    use crate::model::point::Point;
+    use crate::potree::Potree;
+    use crate::writer::write_potree;
+
+    fn points(range: std::ops::Range<u32>) -> Vec<Point> {
+        range
+            .map(|i| {
+                let position = Vector3 {
+                    x: (i % 40) as f64,
+                    y: (i / 40 % 25) as f64,
+                    z: (i % 9) as f64 + (i / 1000 % 3) as f64 * 0.25,
+                };
+                Point::with_attributes(position, i.to_le_bytes().to_vec())
+            })
+            .collect()
+    }
+
+    fn attributes() -> Attributes {
+        Attributes::from_attributes(vec![Attribute::new("index", AttributeType::UINT32, 1)])
+    }
+
+    fn indices(dataset: &PotreeDataset) -> Vec<u32> {
+        let cloud = dataset.query(&Query::default()).unwrap();
+        let mut indices: Vec<u32> = cloud
+            .points
+            .iter()
+            .map(|point| u32::from_le_bytes(point.attributes[..4].try_into().unwrap()))
+            .collect();
+        indices.sort();
+        indices
+    }
+
+    #[test]
+    fn test_append_and_compact() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = std::env::temp_dir().join("potree-append-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir)?;
+        write_potree(
+            Potree::with_attributes(points(0..3000), attributes(), 400),
+            &dir,
+        )?;
+        let options = BuildOptions {
+            point_per_leaf_node_limit: 400,
+            ..BuildOptions::default()
+        };
+
+        let before = PotreeDataset::open(&dir)?;
+        append_points(
+            &dir,
+            PointCloud::new(points(3000..5000), attributes()),
+            &options,
+        )?;
+        let dataset = PotreeDataset::open(&dir)?;
+        assert_eq!(dataset.metadata.points, 5000);
+        assert_eq!(indices(&dataset), (0..5000).collect::<Vec<_>>());
+        assert!(dataset.nodes.len() >= before.nodes.len());
+        let index = dataset.attributes.get("index").unwrap();
+        assert_eq!((index.min.x, index.max.x), (0.0, 4999.0));
+        let octree_len = fs::metadata(dir.join("octree.bin"))?.len();
+        assert!(octree_len > 5000 * 16);
+
+        compact_dataset(&dir)?;
+        let compacted = PotreeDataset::open(&dir)?;
+        assert_eq!(fs::metadata(dir.join("octree.bin"))?.len(), 5000 * 16);
+        assert_eq!(indices(&compacted), (0..5000).collect::<Vec<_>>());
+
+        let outside = vec![Point::with_attributes(
+            Vector3 {
+                x: 100.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            vec![0; 4],
+        )];
+        assert!(matches!(
+            append_points(&dir, PointCloud::new(outside, attributes()), &options),
+            Err(AppendError::OutsideCube { count: 1 })
+        ));
+        Ok(())
+    }
+}
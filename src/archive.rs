@@ -0,0 +1,68 @@
+use std::io::{Seek, Write};
+
+use zip::result::ZipResult;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+#[derive(Copy, Clone)]
+pub enum Compression {
+    Stored,
+    Deflate,
+}
+
+impl Compression {
+    fn method(&self) -> CompressionMethod {
+        match self {
+            Compression::Stored => CompressionMethod::Stored,
+            Compression::Deflate => CompressionMethod::Deflated,
+        }
+    }
+}
+
+/// Packs `files` as `(name, contents)` pairs into a single ZIP archive written to `out`.
+pub fn write_archive<W: Write + Seek>(
+    out: W,
+    files: &[(&str, &[u8])],
+    compression: Compression,
+) -> ZipResult<W> {
+    let mut zip = ZipWriter::new(out);
+    let options = FileOptions::default()
+        .compression_method(compression.method())
+        .large_file(files.iter().any(|(_, data)| data.len() as u64 >= u32::MAX as u64));
+
+    for (name, data) in files {
+        zip.start_file(*name, options)?;
+        zip.write_all(data)?;
+    }
+
+    zip.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use zip::ZipArchive;
+
+    use crate::archive::{write_archive, Compression};
+
+    #[test]
+    fn test_write_archive() {
+        let octree: Vec<u8> = (0..1000).map(|i| (i % 7) as u8).collect();
+        let files: [(&str, &[u8]); 2] = [("metadata.json", b"{}"), ("octree.bin", &octree)];
+
+        for compression in [Compression::Stored, Compression::Deflate] {
+            let cursor = write_archive(Cursor::new(Vec::new()), &files, compression).unwrap();
+            let mut archive = ZipArchive::new(cursor).unwrap();
+
+            assert_eq!(archive.len(), 2);
+            let mut contents = Vec::new();
+            archive
+                .by_name("octree.bin")
+                .unwrap()
+                .read_to_end(&mut contents)
+                .unwrap();
+            assert_eq!(contents, octree);
+        }
+    }
+}
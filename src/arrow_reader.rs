@@ -0,0 +1,527 @@
+use core::fmt;
+use std::collections::HashMap;
+use std::io::Cursor;
+
+use arrow_array::cast::AsArray;
+use arrow_array::types::{Float64Type, Int64Type};
+use arrow_array::{Array, ArrayRef, RecordBatchReader};
+use arrow_ipc::reader::{FileReader, StreamReader};
+use arrow_schema::{ArrowError, DataType, Schema, TimeUnit};
+use bytes::Bytes;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::ProjectionMask;
+use parquet::errors::ParquetError;
+
+use crate::model::attributes::{Attribute, AttributeType, Attributes};
+use crate::model::bounds::Bounds;
+use crate::model::point::Point;
+use crate::point_reader::{PointHeader, PointReader, ReadError};
+use crate::model::vector3::Vector3;
+
+const ARROW_FILE_MAGIC: &[u8] = b"ARROW1";
+
+/// How a column is stored, overriding its name and the type matching its data type.
+#[derive(Clone, Debug)]
+pub enum ColumnOverride {
+    Skip,
+    Attribute { name: String, r#type: AttributeType },
+}
+
+pub struct ArrowOptions {
+    pub x: String,
+    pub y: String,
+    pub z: String,
+    /// Overrides keyed by column name, such as storing `label` as a uint8 `classification`.
+    pub overrides: HashMap<String, ColumnOverride>,
+    /// Rows per record batch when reading Parquet.
+    pub batch_size: usize,
+}
+
+impl Default for ArrowOptions {
+    fn default() -> ArrowOptions {
+        ArrowOptions {
+            x: "x".to_string(),
+            y: "y".to_string(),
+            z: "z".to_string(),
+            overrides: HashMap::new(),
+            batch_size: 65536,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ArrowReadError {
+    Arrow(ArrowError),
+    Parquet(ParquetError),
+    MissingColumn { column: String },
+    UnsupportedColumn { column: String, data_type: String },
+    NoPoints,
+}
+
+impl fmt::Display for ArrowReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArrowReadError::Arrow(error) => write!(f, "Could not read Arrow data: {}", error),
+            ArrowReadError::Parquet(error) => write!(f, "Could not read Parquet file: {}", error),
+            ArrowReadError::MissingColumn { column } => write!(f, "Missing column '{}'", column),
+            ArrowReadError::UnsupportedColumn { column, data_type } => write!(
+                f,
+                "Column '{}' of type {} is not numeric",
+                column, data_type
+            ),
+            ArrowReadError::NoPoints => write!(f, "No points in table"),
+        }
+    }
+}
+
+impl std::error::Error for ArrowReadError {}
+
+impl From<ArrowError> for ArrowReadError {
+    fn from(error: ArrowError) -> Self {
+        ArrowReadError::Arrow(error)
+    }
+}
+
+impl From<ParquetError> for ArrowReadError {
+    fn from(error: ParquetError) -> Self {
+        ArrowReadError::Parquet(error)
+    }
+}
+
+/// The attribute type matching an Arrow data type, `None` for non-numeric types.
+fn attribute_type(data_type: &DataType) -> Option<AttributeType> {
+    match data_type {
+        DataType::Boolean | DataType::UInt8 => Some(AttributeType::UINT8),
+        DataType::Int8 => Some(AttributeType::INT8),
+        DataType::Int16 => Some(AttributeType::INT16),
+        DataType::UInt16 => Some(AttributeType::UINT16),
+        DataType::Int32 => Some(AttributeType::INT32),
+        DataType::UInt32 => Some(AttributeType::UINT32),
+        DataType::Int64 => Some(AttributeType::INT64),
+        DataType::UInt64 => Some(AttributeType::UINT64),
+        DataType::Float16 | DataType::Float32 => Some(AttributeType::FLOAT),
+        DataType::Float64 | DataType::Timestamp(_, _) => Some(AttributeType::DOUBLE),
+        _ => None,
+    }
+}
+
+/// Converts a column to doubles, timestamps to seconds since the epoch and nulls to NaN.
+fn column_values(column: &ArrayRef) -> Result<Vec<f64>, ArrowReadError> {
+    if let DataType::Timestamp(unit, _) = column.data_type() {
+        let per_second = match unit {
+            TimeUnit::Second => 1.0,
+            TimeUnit::Millisecond => 1e3,
+            TimeUnit::Microsecond => 1e6,
+            TimeUnit::Nanosecond => 1e9,
+        };
+        let ticks = arrow_cast::cast(column, &DataType::Int64)?;
+        return Ok(ticks
+            .as_primitive::<Int64Type>()
+            .iter()
+            .map(|value| value.map_or(f64::NAN, |value| value as f64 / per_second))
+            .collect());
+    }
+    let values = arrow_cast::cast(column, &DataType::Float64)?;
+    Ok(values
+        .as_primitive::<Float64Type>()
+        .iter()
+        .map(|value| value.unwrap_or(f64::NAN))
+        .collect())
+}
+
+/// A source of record batches that can be read more than once.
+trait BatchSource<'a> {
+    fn schema(&self) -> Result<Schema, ArrowReadError>;
+    /// Reads the batches with only the given columns.
+    fn batches(&self, columns: &[&str]) -> Result<Box<dyn RecordBatchReader + 'a>, ArrowReadError>;
+}
+
+struct ParquetSource {
+    bytes: Bytes,
+    batch_size: usize,
+}
+
+impl BatchSource<'static> for ParquetSource {
+    fn schema(&self) -> Result<Schema, ArrowReadError> {
+        let builder = ParquetRecordBatchReaderBuilder::try_new(self.bytes.clone())?;
+        Ok(builder.schema().as_ref().clone())
+    }
+
+    fn batches(
+        &self,
+        columns: &[&str],
+    ) -> Result<Box<dyn RecordBatchReader + 'static>, ArrowReadError> {
+        let builder = ParquetRecordBatchReaderBuilder::try_new(self.bytes.clone())?;
+        let indices: Vec<usize> = builder
+            .schema()
+            .fields()
+            .iter()
+            .enumerate()
+            .filter(|(_, field)| columns.contains(&field.name().as_str()))
+            .map(|(i, _)| i)
+            .collect();
+        let mask = ProjectionMask::roots(builder.parquet_schema(), indices);
+        let reader = builder
+            .with_projection(mask)
+            .with_batch_size(self.batch_size)
+            .build()?;
+        Ok(Box::new(reader))
+    }
+}
+
+/// Arrow IPC data in either the file or the streaming format.
+struct IpcSource<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> BatchSource<'a> for IpcSource<'a> {
+    fn schema(&self) -> Result<Schema, ArrowReadError> {
+        Ok(self.batches(&[])?.schema().as_ref().clone())
+    }
+
+    fn batches(&self, columns: &[&str]) -> Result<Box<dyn RecordBatchReader + 'a>, ArrowReadError> {
+        let cursor = Cursor::new(self.buf);
+        let projection = |schema: &Schema| -> Vec<usize> {
+            (0..schema.fields().len())
+                .filter(|&i| columns.contains(&schema.field(i).name().as_str()))
+                .collect()
+        };
+        if self.buf.starts_with(ARROW_FILE_MAGIC) {
+            let reader = FileReader::try_new(cursor.clone(), None)?;
+            let projection = projection(&reader.schema());
+            Ok(Box::new(FileReader::try_new(cursor, Some(projection))?))
+        } else {
+            let reader = StreamReader::try_new(cursor.clone(), None)?;
+            let projection = projection(&reader.schema());
+            Ok(Box::new(StreamReader::try_new(cursor, Some(projection))?))
+        }
+    }
+}
+
+/// Streams the points of a table, converting one record batch at a time.
+pub struct ArrowReader<'a> {
+    header: PointHeader,
+    /// Position columns followed by the attribute columns.
+    columns: Vec<String>,
+    batches: Box<dyn RecordBatchReader + 'a>,
+}
+
+impl ArrowReader<'_> {
+    fn next_points(&mut self) -> Option<Result<Vec<Point>, ArrowReadError>> {
+        let batch = match self.batches.next()? {
+            Ok(batch) => batch,
+            Err(error) => return Some(Err(error.into())),
+        };
+        let values = self
+            .columns
+            .iter()
+            .map(|column| column_values(batch.column_by_name(column).unwrap()))
+            .collect::<Result<Vec<Vec<f64>>, _>>();
+        let values = match values {
+            Ok(values) => values,
+            Err(error) => return Some(Err(error)),
+        };
+
+        let mut points = Vec::with_capacity(batch.num_rows());
+        for row in 0..batch.num_rows() {
+            let [x, y, z] = [0, 1, 2].map(|axis| values[axis][row]);
+            if x.is_nan() || y.is_nan() || z.is_nan() {
+                continue;
+            }
+            let mut attributes = Vec::new();
+            for (attribute, column) in self.header.attributes.list.iter().zip(&values[3..]) {
+                attribute.r#type.write_f64(column[row], &mut attributes);
+            }
+            points.push(Point::with_attributes(Vector3 { x, y, z }, attributes));
+        }
+        Some(Ok(points))
+    }
+}
+
+impl PointReader for ArrowReader<'_> {
+    fn header(&self) -> &PointHeader {
+        &self.header
+    }
+
+    fn next_batch(&mut self) -> Option<Result<Vec<Point>, ReadError>> {
+        self.next_points()
+            .map(|points| points.map_err(ReadError::format))
+    }
+}
+
+/// Coordinate reference system of the primary geometry column in GeoParquet `geo`
+/// metadata. A missing `crs` means OGC:CRS84, a PROJJSON one is reduced to its authority
+/// code and one without an identifier, like an explicit null, is unknown.
+fn geo_crs(schema: &Schema) -> Option<String> {
+    let geo: serde_json::Value = serde_json::from_str(schema.metadata().get("geo")?).ok()?;
+    let primary = geo.get("primary_column")?.as_str()?;
+    let column = geo.get("columns")?.get(primary)?;
+    match column.get("crs") {
+        None => Some("OGC:CRS84".to_string()),
+        Some(serde_json::Value::String(crs)) => Some(crs.clone()),
+        Some(crs) => {
+            let id = crs.get("id")?;
+            let authority = id.get("authority")?.as_str()?;
+            match id.get("code")? {
+                serde_json::Value::String(code) => Some(format!("{}:{}", authority, code)),
+                code => Some(format!("{}:{}", authority, code.as_u64()?)),
+            }
+        }
+    }
+}
+
+/// Resolves the columns and reads the bounds from the position columns, leaving the
+/// batches with all mapped columns to be streamed.
+fn open_batches<'a>(
+    source: &dyn BatchSource<'a>,
+    options: &ArrowOptions,
+) -> Result<ArrowReader<'a>, ArrowReadError> {
+    let schema = source.schema()?;
+    let position = [options.x.as_str(), options.y.as_str(), options.z.as_str()];
+    for column in position {
+        if schema.field_with_name(column).is_err() {
+            return Err(ArrowReadError::MissingColumn {
+                column: column.to_string(),
+            });
+        }
+    }
+
+    let mut columns: Vec<&str> = position.to_vec();
+    let mut attribute_list = Vec::new();
+    for field in schema.fields() {
+        let column = field.name().as_str();
+        if position.contains(&column) {
+            continue;
+        }
+        let default_type = attribute_type(field.data_type());
+        let (name, r#type) = match (options.overrides.get(column), default_type) {
+            (Some(ColumnOverride::Skip), _) => continue,
+            (Some(ColumnOverride::Attribute { name, r#type }), Some(_)) => (name.as_str(), *r#type),
+            (Some(ColumnOverride::Attribute { .. }), None) => {
+                return Err(ArrowReadError::UnsupportedColumn {
+                    column: column.to_string(),
+                    data_type: field.data_type().to_string(),
+                })
+            }
+            (None, Some(r#type)) => (column, r#type),
+            (None, None) => continue,
+        };
+        columns.push(column);
+        attribute_list.push(Attribute::new(name, r#type, 1));
+    }
+
+    let mut lower = [f64::INFINITY; 3];
+    let mut upper = [f64::NEG_INFINITY; 3];
+    let mut count = 0;
+    for batch in source.batches(&position)? {
+        let batch = batch?;
+        let values = position
+            .iter()
+            .map(|column| column_values(batch.column_by_name(column).unwrap()))
+            .collect::<Result<Vec<Vec<f64>>, _>>()?;
+        for ((&x, &y), &z) in values[0].iter().zip(&values[1]).zip(&values[2]) {
+            let point = [x, y, z];
+            if point.iter().any(|value| value.is_nan()) {
+                continue;
+            }
+            lower = [0, 1, 2].map(|axis| lower[axis].min(point[axis]));
+            upper = [0, 1, 2].map(|axis| upper[axis].max(point[axis]));
+            count += 1;
+        }
+    }
+    if count == 0 {
+        return Err(ArrowReadError::NoPoints);
+    }
+
+    Ok(ArrowReader {
+        header: PointHeader {
+            count: Some(count),
+            bounds: Some(Bounds::new(
+                upper[0], upper[1], upper[2], lower[0], lower[1], lower[2],
+            )),
+            attributes: Attributes::from_attributes(attribute_list),
+            crs: geo_crs(&schema),
+        },
+        batches: source.batches(&columns)?,
+        columns: columns.into_iter().map(str::to_string).collect(),
+    })
+}
+
+/// Opens a Parquet file, streaming its record batches to
+/// [`Potree::from_reader`](crate::potree::Potree::from_reader).
+///
+/// Every numeric column other than the position becomes an attribute of the matching type,
+/// timestamps as double seconds since the epoch, unless overridden in `options`. Other
+/// columns are ignored. Rows with a null or NaN coordinate are skipped. The coordinate
+/// reference system is taken from GeoParquet metadata.
+pub fn open_parquet(
+    buf: &[u8],
+    options: &ArrowOptions,
+) -> Result<ArrowReader<'static>, ArrowReadError> {
+    let source = ParquetSource {
+        bytes: Bytes::copy_from_slice(buf),
+        batch_size: options.batch_size,
+    };
+    open_batches(&source, options)
+}
+
+/// Opens an Arrow IPC file or stream, with columns mapped as by [`open_parquet`].
+pub fn open_arrow_ipc<'a>(
+    buf: &'a [u8],
+    options: &ArrowOptions,
+) -> Result<ArrowReader<'a>, ArrowReadError> {
+    open_batches(&IpcSource { buf }, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::{
+        Float64Array, Int32Array, RecordBatch, StringArray, TimestampMillisecondArray,
+    };
+    use arrow_ipc::writer::{FileWriter, StreamWriter};
+    use parquet::arrow::ArrowWriter;
+
+    use super::*;
+    use crate::potree::{BuildOptions, Potree};
+
+    fn batch() -> RecordBatch {
+        RecordBatch::try_from_iter(vec![
+            (
+                "x",
+                Arc::new(Float64Array::from(vec![
+                    Some(0.0),
+                    Some(1.0),
+                    None,
+                    Some(2.0),
+                ])) as ArrayRef,
+            ),
+            (
+                "y",
+                Arc::new(Float64Array::from(vec![0.0, 2.0, 5.0, 4.0])) as ArrayRef,
+            ),
+            (
+                "z",
+                Arc::new(Float64Array::from(vec![0.0, 3.0, 5.0, 6.0])) as ArrayRef,
+            ),
+            (
+                "label",
+                Arc::new(Int32Array::from(vec![1, 2, 3, 4])) as ArrayRef,
+            ),
+            (
+                "time",
+                Arc::new(TimestampMillisecondArray::from(vec![
+                    1500, 2500, 3500, 4500,
+                ])) as ArrayRef,
+            ),
+            (
+                "name",
+                Arc::new(StringArray::from(vec!["a", "b", "c", "d"])) as ArrayRef,
+            ),
+        ])
+        .unwrap()
+    }
+
+    fn options() -> ArrowOptions {
+        let mut options = ArrowOptions {
+            batch_size: 2,
+            ..Default::default()
+        };
+        options.overrides.insert(
+            "label".to_string(),
+            ColumnOverride::Attribute {
+                name: "classification".to_string(),
+                r#type: AttributeType::UINT8,
+            },
+        );
+        options
+    }
+
+    fn check(mut reader: ArrowReader) {
+        let potree = Potree::from_reader(&mut reader, &BuildOptions::default()).unwrap();
+        assert_eq!(potree.size, 3);
+        let names: Vec<&str> = potree
+            .attributes
+            .list
+            .iter()
+            .map(|a| a.name.as_str())
+            .collect();
+        assert_eq!(names, ["classification", "time"]);
+        let mut points = potree.into_points();
+        points.sort_by(|a, b| a.position.x.total_cmp(&b.position.x));
+        assert_eq!(points[1].attributes[0], 2);
+        assert_eq!(
+            AttributeType::DOUBLE.read_f64(&points[2].attributes[1..]),
+            4.5
+        );
+    }
+
+    #[test]
+    fn test_read_parquet() {
+        let mut buf = Vec::new();
+        let mut writer = ArrowWriter::try_new(&mut buf, batch().schema(), None).unwrap();
+        writer.write(&batch()).unwrap();
+        writer.close().unwrap();
+
+        check(open_parquet(&buf, &options()).unwrap());
+    }
+
+    #[test]
+    fn test_geo_crs() {
+        let geo = |crs: &str| {
+            let metadata = format!(
+                r#"{{"version":"1.1.0","primary_column":"geometry","columns":{{"geometry":{{"encoding":"WKB"{}}}}}}}"#,
+                crs
+            );
+            Schema::empty().with_metadata(HashMap::from([("geo".to_string(), metadata)]))
+        };
+        let projjson = r#","crs":{"type":"ProjectedCRS","name":"ETRS89 / UTM zone 32N","id":{"authority":"EPSG","code":25832}}"#;
+        assert_eq!(geo_crs(&geo(projjson)).as_deref(), Some("EPSG:25832"));
+        assert_eq!(geo_crs(&geo("")).as_deref(), Some("OGC:CRS84"));
+        assert_eq!(geo_crs(&geo(r#","crs":null"#)), None);
+        assert_eq!(geo_crs(&Schema::empty()), None);
+
+        let schema = batch().schema().as_ref().clone().with_metadata(geo(projjson).metadata);
+        let mut buf = Vec::new();
+        let mut writer = ArrowWriter::try_new(&mut buf, Arc::new(schema.clone()), None).unwrap();
+        writer.write(&batch().with_schema(Arc::new(schema)).unwrap()).unwrap();
+        writer.close().unwrap();
+        let reader = open_parquet(&buf, &options()).unwrap();
+        assert_eq!(reader.header().crs.as_deref(), Some("EPSG:25832"));
+    }
+
+    #[test]
+    fn test_read_arrow_ipc() {
+        let mut file = Vec::new();
+        let mut writer = FileWriter::try_new(&mut file, &batch().schema()).unwrap();
+        writer.write(&batch()).unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+        check(open_arrow_ipc(&file, &options()).unwrap());
+
+        let mut stream = Vec::new();
+        let mut writer = StreamWriter::try_new(&mut stream, &batch().schema()).unwrap();
+        writer.write(&batch()).unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+        check(open_arrow_ipc(&stream, &options()).unwrap());
+    }
+
+    #[test]
+    fn test_missing_column() {
+        let options = ArrowOptions {
+            z: "height".to_string(),
+            ..Default::default()
+        };
+        let mut buf = Vec::new();
+        let mut writer = FileWriter::try_new(&mut buf, &batch().schema()).unwrap();
+        writer.write(&batch()).unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+        assert!(matches!(
+            open_arrow_ipc(&buf, &options),
+            Err(ArrowReadError::MissingColumn { .. })
+        ));
+    }
+}
@@ -0,0 +1,294 @@
+use std::collections::VecDeque;
+use std::io::{Error, Seek, SeekFrom, Write};
+
+use byteorder::{LittleEndian, WriteBytesExt};
+use laz::LazVlr;
+
+use crate::las::{
+    compress_chunk, laz_vlr_payload, wkt_vlr, write_chunk_table, write_evlr, write_vlr, LasHeader,
+    LasLayout, EVLR_HEADER_SIZE, HEADER_SIZE, LAZ_POINT_FORMAT_BIT, VLR_HEADER_SIZE,
+};
+use crate::model::node::Node;
+use crate::potree::Potree;
+
+const COPC_USER_ID: &str = "copc";
+const COPC_INFO_SIZE: usize = 160;
+const HIERARCHY_ENTRY_SIZE: usize = 32;
+
+/// Non-empty nodes breadth first, the order their chunks are written in.
+fn nodes_breadth_first(root: &Node) -> Vec<&Node> {
+    let mut nodes = Vec::new();
+    let mut queue = VecDeque::from([root]);
+    while let Some(node) = queue.pop_front() {
+        if node.num_points() == 0 {
+            continue;
+        }
+        nodes.push(node);
+        queue.extend(node.children.iter().flatten().map(|child| child.as_ref()));
+    }
+    nodes
+}
+
+/// Writes the octree as a single Cloud Optimized Point Cloud file: a LAZ 1.4 file with
+/// the COPC info VLR, a LAZ chunk of points per node and the node hierarchy as an EVLR.
+///
+/// The octree is rebuilt with a cubic root first, as COPC voxel keys address octants of
+/// a cube. Attributes are mapped onto point format 6, 7 or 8 as described for
+/// [`LasLayout`], with the rest stored as extra bytes. The CRS is stored in the OGC WKT
+/// VLR as described for [`wkt_vlr`].
+pub fn write_copc<W: Write + Seek>(potree: Potree, mut out: W) -> Result<W, Error> {
+    let potree = potree.into_cubic();
+    let layout = LasLayout::new(&potree.attributes);
+    let laz_vlr = layout.laz_vlr()?;
+    let laszip = laz_vlr_payload(&laz_vlr)?;
+    let extra_bytes = layout.extra_bytes_vlr();
+    let wkt = wkt_vlr(potree.crs.as_deref());
+
+    let mut vlrs: Vec<(&str, u16, &str, &[u8])> = Vec::new();
+    let info_placeholder = [0u8; COPC_INFO_SIZE];
+    vlrs.push((COPC_USER_ID, 1, "COPC info", &info_placeholder));
+    vlrs.push((
+        LazVlr::USER_ID,
+        LazVlr::RECORD_ID,
+        LazVlr::DESCRIPTION,
+        &laszip,
+    ));
+    if let Some(wkt) = &wkt {
+        vlrs.push(("LASF_Projection", 2112, "OGC WKT", wkt));
+    }
+    if let Some(extra_bytes) = &extra_bytes {
+        vlrs.push(("LASF_Spec", 4, "Extra bytes", extra_bytes));
+    }
+    let offset_to_points = HEADER_SIZE as usize
+        + vlrs
+            .iter()
+            .map(|(_, _, _, payload)| VLR_HEADER_SIZE + payload.len())
+            .sum::<usize>();
+
+    // Header, VLRs and the offset to the LAZ chunk table in front of the chunks are
+    // written again once the chunk offsets are known.
+    out.write_all(&vec![0; offset_to_points + 8])?;
+
+    let root = &potree.root.bounds;
+    let offset = [root.lx, root.ly, root.lz];
+    let mut points_by_return = [0u64; 15];
+    let mut position = offset_to_points as u64 + 8;
+    let nodes = nodes_breadth_first(&potree.root);
+    let mut hierarchy = Vec::with_capacity(nodes.len() * HIERARCHY_ENTRY_SIZE);
+    let mut chunks = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        let mut records = Vec::with_capacity(node.num_points() * layout.record_length as usize);
+        for point in node.points() {
+            let return_number = layout.write_point(point, potree.scale, offset, &mut records);
+            if (1..=15).contains(&return_number) {
+                points_by_return[return_number as usize - 1] += 1;
+            }
+        }
+        let chunk = compress_chunk(&laz_vlr, &records)?;
+        out.write_all(&chunk)?;
+
+        for value in node.voxel_key() {
+            hierarchy.write_i32::<LittleEndian>(value)?;
+        }
+        hierarchy.write_u64::<LittleEndian>(position)?;
+        hierarchy.write_i32::<LittleEndian>(chunk.len() as i32)?;
+        hierarchy.write_i32::<LittleEndian>(node.num_points() as i32)?;
+        chunks.push((node.num_points() as u64, chunk.len() as u64));
+        position += chunk.len() as u64;
+    }
+    let chunk_table_start = position;
+    let mut chunk_table = Vec::new();
+    write_chunk_table(&mut chunk_table, &laz_vlr, &chunks)?;
+    out.write_all(&chunk_table)?;
+
+    let evlr_start = chunk_table_start + chunk_table.len() as u64;
+    write_evlr(&mut out, COPC_USER_ID, 1000, "EPT hierarchy", &hierarchy)?;
+
+    let (gps_min, gps_max) = layout
+        .gps_time_range(&potree.attributes)
+        .unwrap_or((0.0, 0.0));
+    let mut info = Vec::with_capacity(COPC_INFO_SIZE);
+    info.write_f64::<LittleEndian>((root.lx + root.ux) / 2.0)?;
+    info.write_f64::<LittleEndian>((root.ly + root.uy) / 2.0)?;
+    info.write_f64::<LittleEndian>((root.lz + root.uz) / 2.0)?;
+    info.write_f64::<LittleEndian>(root.size_x / 2.0)?;
+    info.write_f64::<LittleEndian>(potree.spacing)?;
+    info.write_u64::<LittleEndian>(evlr_start + EVLR_HEADER_SIZE as u64)?;
+    info.write_u64::<LittleEndian>(hierarchy.len() as u64)?;
+    info.write_f64::<LittleEndian>(gps_min)?;
+    info.write_f64::<LittleEndian>(gps_max)?;
+    info.resize(COPC_INFO_SIZE, 0);
+    vlrs[0].3 = &info;
+
+    let bounds = &potree.bounds;
+    let header = LasHeader {
+        point_format: layout.point_format | LAZ_POINT_FORMAT_BIT,
+        record_length: layout.record_length,
+        offset_to_points: offset_to_points as u32,
+        vlr_count: vlrs.len() as u32,
+        evlr_start,
+        evlr_count: 1,
+        point_count: potree.size as u64,
+        points_by_return,
+        scale: potree.scale,
+        offset,
+        min: [bounds.lx, bounds.ly, bounds.lz],
+        max: [bounds.ux, bounds.uy, bounds.uz],
+    };
+    out.seek(SeekFrom::Start(0))?;
+    header.write(&mut out)?;
+    for (user_id, record_id, description, payload) in vlrs {
+        write_vlr(&mut out, user_id, record_id, description, payload)?;
+    }
+    out.write_i64::<LittleEndian>(chunk_table_start as i64)?;
+    out.seek(SeekFrom::End(0))?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use laz::record::{LayeredPointRecordDecompressor, RecordDecompressor};
+    use laz::LasZipDecompressor;
+
+    use super::*;
+    use crate::model::attributes::{Attribute, AttributeType, Attributes};
+    use crate::model::bounds::Bounds;
+    use crate::model::node::empty_child_node_array;
+    use crate::model::point::Point;
+    use crate::model::vector3::Vector3;
+
+    fn read_u64(buf: &[u8], at: usize) -> u64 {
+        u64::from_le_bytes(buf[at..at + 8].try_into().unwrap())
+    }
+
+    fn read_f64(buf: &[u8], at: usize) -> f64 {
+        f64::from_le_bytes(buf[at..at + 8].try_into().unwrap())
+    }
+
+    fn read_i32(buf: &[u8], at: usize) -> i32 {
+        i32::from_le_bytes(buf[at..at + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_voxel_key() {
+        let node = |name: &str| {
+            let bounds = Bounds::new(1.0, 1.0, 1.0, 0.0, 0.0, 0.0);
+            Node::new(name.to_string(), 1.0, bounds, empty_child_node_array(), 1)
+        };
+        assert_eq!(node("r").voxel_key(), [0, 0, 0, 0]);
+        assert_eq!(node("r7").voxel_key(), [1, 1, 1, 1]);
+        assert_eq!(node("r41").voxel_key(), [2, 2, 0, 1]);
+    }
+
+    #[test]
+    fn test_write_copc() {
+        let points: Vec<Point> = (0..3000)
+            .map(|i| {
+                let position = Vector3 {
+                    x: (i % 40) as f64,
+                    y: (i / 40 % 10) as f64,
+                    z: (i % 3) as f64,
+                };
+                let mut attributes = Vec::new();
+                AttributeType::UINT16.write_f64(i as f64, &mut attributes);
+                for channel in [255.0, 0.0, 1.0] {
+                    AttributeType::UINT8.write_f64(channel, &mut attributes);
+                }
+                AttributeType::FLOAT.write_f64(i as f64 / 2.0, &mut attributes);
+                Point::with_attributes(position, attributes)
+            })
+            .collect();
+        let mut attributes = Attributes::from_attributes(vec![
+            Attribute::new("intensity", AttributeType::UINT16, 1),
+            Attribute::new("rgb", AttributeType::UINT8, 3),
+            Attribute::new("range", AttributeType::FLOAT, 1),
+        ]);
+        for point in &points {
+            attributes.update_ranges(&point.attributes);
+        }
+        let potree = Potree::with_attributes(points, attributes, 200);
+
+        let buf = write_copc(potree, Cursor::new(Vec::new()))
+            .unwrap()
+            .into_inner();
+        assert_eq!(&buf[0..4], b"LASF");
+        assert_eq!(buf[24..26], [1, 4]);
+        assert_eq!(buf[104], 7 | 0x80);
+        let record_length = u16::from_le_bytes([buf[105], buf[106]]) as usize;
+        assert_eq!(record_length, 36 + 4);
+        assert_eq!(read_u64(&buf, 247), 3000);
+        let scale = read_f64(&buf, 131);
+        let offset_to_points = u32::from_le_bytes(buf[96..100].try_into().unwrap()) as usize;
+
+        let info = HEADER_SIZE as usize;
+        assert_eq!(&buf[info + 2..info + 6], b"copc");
+        assert_eq!(u16::from_le_bytes([buf[info + 18], buf[info + 19]]), 1);
+        let laszip = info + VLR_HEADER_SIZE + COPC_INFO_SIZE;
+        assert_eq!(&buf[laszip + 2..laszip + 16], b"laszip encoded");
+        let laszip_size = u16::from_le_bytes([buf[laszip + 20], buf[laszip + 21]]) as usize;
+        let laz_vlr = LazVlr::from_buffer(
+            &buf[laszip + VLR_HEADER_SIZE..laszip + VLR_HEADER_SIZE + laszip_size],
+        )
+        .unwrap();
+        assert_eq!(laz_vlr.items_size() as usize, record_length);
+
+        // The chunk table lets plain LAZ readers decompress all points in file order.
+        let mut all = vec![0; 3000 * record_length];
+        let mut source = Cursor::new(buf.as_slice());
+        source.set_position(offset_to_points as u64);
+        let mut decompressor = LasZipDecompressor::new(source, laz_vlr.clone()).unwrap();
+        decompressor.decompress_many(&mut all).unwrap();
+
+        let info = info + VLR_HEADER_SIZE;
+        let center = [0, 8, 16].map(|at| read_f64(&buf, info + at));
+        let halfsize = read_f64(&buf, info + 24);
+        let hierarchy_offset = read_u64(&buf, info + 40) as usize;
+        let hierarchy_size = read_u64(&buf, info + 48) as usize;
+        assert_eq!(
+            read_u64(&buf, 235) as usize,
+            hierarchy_offset - EVLR_HEADER_SIZE
+        );
+
+        let offset = [131 + 24, 131 + 32, 131 + 40].map(|at| read_f64(&buf, at));
+        let mut total = 0;
+        for entry in
+            buf[hierarchy_offset..hierarchy_offset + hierarchy_size].chunks(HIERARCHY_ENTRY_SIZE)
+        {
+            let [level, x, y, z] = [0, 4, 8, 12].map(|at| read_i32(entry, at));
+            let chunk_offset = read_u64(entry, 16) as usize;
+            let chunk_size = read_i32(entry, 24) as usize;
+            let count = read_i32(entry, 28) as usize;
+            assert!(chunk_size < count * record_length);
+
+            // Every chunk decompresses on its own, as COPC readers fetch them by offset.
+            let mut decompressor = LayeredPointRecordDecompressor::new(Cursor::new(
+                &buf[chunk_offset..chunk_offset + chunk_size],
+            ));
+            decompressor.set_fields_from(laz_vlr.items()).unwrap();
+            let mut records = vec![0; count * record_length];
+            decompressor.decompress_many(&mut records).unwrap();
+            assert_eq!(
+                records,
+                all[total * record_length..(total + count) * record_length]
+            );
+            total += count;
+
+            let size = 2.0 * halfsize / (1 << level) as f64;
+            let min = [x, y, z]
+                .iter()
+                .zip(center)
+                .map(|(i, center)| center - halfsize + *i as f64 * size)
+                .collect::<Vec<_>>();
+            for record in records.chunks(record_length) {
+                for axis in 0..3 {
+                    let value = read_i32(record, axis * 4) as f64 * scale + offset[axis];
+                    assert!(value >= min[axis] - scale && value <= min[axis] + size + scale);
+                }
+                assert_eq!(record[30..32], [255, 255]);
+            }
+        }
+        assert_eq!(total, 3000);
+    }
+}
@@ -1,38 +1,266 @@
-use csv::{Reader};
-use serde::{Deserialize};
+use core::fmt;
 
-use crate::{model::vector3::Vector3, potree::Potree};
+use csv::{ReaderBuilder, StringRecord};
 
-#[derive(Debug, Deserialize)]
-#[serde(untagged)]
-enum Intensity  {
-	Int(u8),
-	Float(f32)
+use crate::model::attributes::{Attribute, AttributeType, Attributes};
+use crate::model::point::Point;
+use crate::model::point_cloud::PointCloud;
+use crate::model::vector3::Vector3;
+
+#[derive(Clone, Debug)]
+pub enum Column {
+	/// Header name, matched case-insensitively.
+	Name(String),
+	/// Zero based column index.
+	Index(usize),
 }
 
-#[derive(Debug, Deserialize)]
-struct Point {
-	pub x: f64,
-	pub y: f64,
-	pub z: f64,
-	pub intensity: Option<Intensity>
+impl Column {
+	pub fn name(name: &str) -> Column {
+		Column::Name(name.to_string())
+	}
 }
 
-pub fn from_csv(buf: &[u8]) -> Result<Potree, Box<dyn std::error::Error>> {
-    let mut rdr = Reader::from_reader(buf);
-	let mut points: Vec<Vector3> = Vec::new();
-	let mut num_points = 0;
-    for result in rdr.deserialize() {
-        let record: Point = result?;
-		  
-		let Point { x, y, z, intensity } = record;
-		points.push(Vector3 { x, y, z });
+impl fmt::Display for Column {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Column::Name(name) => write!(f, "'{}'", name),
+			Column::Index(index) => write!(f, "{}", index),
+		}
+	}
+}
 
-		num_points = num_points + 1;
-    }
-	let potree = Potree::new(points, 20000);
+/// Maps a column to a potree attribute of the given type.
+#[derive(Clone)]
+pub struct ColumnMapping {
+	pub column: Column,
+	pub attribute: String,
+	pub r#type: AttributeType,
+	/// Skip the mapping instead of failing when a named column is not in the header.
+	pub optional: bool,
+}
+
+impl ColumnMapping {
+	pub fn new(column: Column, attribute: &str, r#type: AttributeType) -> ColumnMapping {
+		ColumnMapping {
+			column,
+			attribute: attribute.to_string(),
+			r#type,
+			optional: false,
+		}
+	}
+}
+
+pub struct CsvOptions {
+	pub delimiter: u8,
+	pub quote: u8,
+	pub comment: Option<u8>,
+	/// `None` treats the first row as a header if any of its fields is not a number.
+	/// Without a header, named position columns fall back to the first three columns.
+	pub has_headers: Option<bool>,
+	/// Number of lines to skip before the header or first row.
+	pub skip_rows: usize,
+	pub x: Column,
+	pub y: Column,
+	pub z: Column,
+	pub attributes: Vec<ColumnMapping>,
+}
+
+impl Default for CsvOptions {
+	fn default() -> CsvOptions {
+		CsvOptions {
+			delimiter: b',',
+			quote: b'"',
+			comment: None,
+			has_headers: None,
+			skip_rows: 0,
+			x: Column::name("x"),
+			y: Column::name("y"),
+			z: Column::name("z"),
+			attributes: vec![
+				ColumnMapping {
+					optional: true,
+					..ColumnMapping::new(Column::name("intensity"), "intensity", AttributeType::FLOAT)
+				},
+				ColumnMapping {
+					optional: true,
+					..ColumnMapping::new(
+						Column::name("classification"),
+						"classification",
+						AttributeType::UINT8,
+					)
+				},
+			],
+		}
+	}
+}
+
+#[derive(Debug)]
+pub enum CsvReadError {
+	Csv(csv::Error),
+	NoPoints,
+	MissingColumn { column: String },
+	MissingValue { line: u64, column: String },
+	InvalidValue { line: u64, column: String, value: String },
+}
+
+impl fmt::Display for CsvReadError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			CsvReadError::Csv(error) => write!(f, "{}", error),
+			CsvReadError::NoPoints => write!(f, "No points in csv"),
+			CsvReadError::MissingColumn { column } => write!(f, "Column {} not found", column),
+			CsvReadError::MissingValue { line, column } => {
+				write!(f, "Line {}: missing value for column {}", line, column)
+			}
+			CsvReadError::InvalidValue {
+				line,
+				column,
+				value,
+			} => write!(
+				f,
+				"Line {}: could not parse '{}' in column {} as a number",
+				line, value, column
+			),
+		}
+	}
+}
+
+impl std::error::Error for CsvReadError {}
+
+impl From<csv::Error> for CsvReadError {
+	fn from(error: csv::Error) -> CsvReadError {
+		CsvReadError::Csv(error)
+	}
+}
+
+struct ResolvedColumn {
+	index: usize,
+	label: String,
+}
+
+fn resolve_column(
+	column: &Column,
+	header: Option<&StringRecord>,
+) -> Result<Option<ResolvedColumn>, CsvReadError> {
+	match (column, header) {
+		(Column::Index(index), _) => Ok(Some(ResolvedColumn {
+			index: *index,
+			label: column.to_string(),
+		})),
+		(Column::Name(name), Some(header)) => Ok(header
+			.iter()
+			.position(|field| field.trim().eq_ignore_ascii_case(name))
+			.map(|index| ResolvedColumn {
+				index,
+				label: column.to_string(),
+			})),
+		(Column::Name(_), None) => Ok(None),
+	}
+}
+
+fn parse_value(record: &StringRecord, column: &ResolvedColumn, line: u64) -> Result<f64, CsvReadError> {
+	let value = record.get(column.index).ok_or_else(|| CsvReadError::MissingValue {
+		line,
+		column: column.label.clone(),
+	})?;
+	value.trim().parse::<f64>().map_err(|_| CsvReadError::InvalidValue {
+		line,
+		column: column.label.clone(),
+		value: value.to_string(),
+	})
+}
+
+fn skip_lines(buf: &[u8], lines: usize) -> &[u8] {
+	let mut start = 0;
+	for _ in 0..lines {
+		match buf[start..].iter().position(|&b| b == b'\n') {
+			Some(newline) => start += newline + 1,
+			None => return &[],
+		}
+	}
+	&buf[start..]
+}
 
-    Ok(potree)
+pub fn read_csv(buf: &[u8]) -> Result<PointCloud, CsvReadError> {
+	read_csv_with_options(buf, &CsvOptions::default())
+}
+
+pub fn read_csv_with_options(buf: &[u8], options: &CsvOptions) -> Result<PointCloud, CsvReadError> {
+	let mut rdr = ReaderBuilder::new()
+		.delimiter(options.delimiter)
+		.quote(options.quote)
+		.comment(options.comment)
+		.has_headers(false)
+		.flexible(true)
+		.from_reader(skip_lines(buf, options.skip_rows));
+	let mut records = rdr.records();
+
+	let first = match records.next() {
+		Some(record) => record?,
+		None => return Err(CsvReadError::NoPoints),
+	};
+	let has_headers = options
+		.has_headers
+		.unwrap_or_else(|| first.iter().any(|field| field.trim().parse::<f64>().is_err()));
+	let header = if has_headers { Some(&first) } else { None };
+
+	let mut position = Vec::new();
+	for (i, column) in [&options.x, &options.y, &options.z].into_iter().enumerate() {
+		let resolved = match (resolve_column(column, header)?, header) {
+			(Some(resolved), _) => resolved,
+			(None, None) => ResolvedColumn {
+				index: i,
+				label: i.to_string(),
+			},
+			(None, Some(_)) => {
+				return Err(CsvReadError::MissingColumn {
+					column: column.to_string(),
+				})
+			}
+		};
+		position.push(resolved);
+	}
+
+	let mut mappings = Vec::new();
+	let mut attribute_list = Vec::new();
+	for mapping in &options.attributes {
+		match resolve_column(&mapping.column, header)? {
+			Some(resolved) => {
+				attribute_list.push(Attribute::new(&mapping.attribute, mapping.r#type, 1));
+				mappings.push((resolved, mapping.r#type));
+			}
+			None if mapping.optional => {}
+			None => {
+				return Err(CsvReadError::MissingColumn {
+					column: mapping.column.to_string(),
+				})
+			}
+		}
+	}
+
+	let rows = if has_headers { None } else { Some(Ok(first)) };
+	let mut points: Vec<Point> = Vec::new();
+	for result in rows.into_iter().chain(records) {
+		let record = result?;
+		let line = record.position().map_or(0, |position| position.line()) + options.skip_rows as u64;
+
+		let position = Vector3 {
+			x: parse_value(&record, &position[0], line)?,
+			y: parse_value(&record, &position[1], line)?,
+			z: parse_value(&record, &position[2], line)?,
+		};
+		let mut attributes = Vec::new();
+		for (column, r#type) in &mappings {
+			r#type.write_f64(parse_value(&record, column, line)?, &mut attributes);
+		}
+
+		points.push(Point::with_attributes(position, attributes));
+	}
+	if points.is_empty() {
+		return Err(CsvReadError::NoPoints);
+	}
+	Ok(PointCloud::new(points, Attributes::from_attributes(attribute_list)))
 }
 
 
@@ -41,26 +269,79 @@ mod tests {
 
 	use std::fs;
 	use crate::csv_reader;
-   
+	use crate::csv_reader::{Column, ColumnMapping, CsvOptions, CsvReadError};
+	use crate::model::attributes::AttributeType;
+
     #[test]
     fn test_read_csv() -> Result<(), Box<dyn std::error::Error>> {
 		let buffer = fs::read("resources/points_integer_intensity.csv")?;
-		let potree = csv_reader::from_csv(&buffer)?;
+		let potree = csv_reader::read_csv(&buffer)?.into_potree();
 
 		assert_eq!(potree.size, 10);
 
 		Ok(())
     }
-   
+
     #[test]
     fn test_read_csv_intensity() -> Result<(), Box<dyn std::error::Error>> {
 		let buffer = fs::read("resources/points_intensity.csv")?;
-		let potree = csv_reader::from_csv(&buffer)?;
+		let potree = csv_reader::read_csv(&buffer)?.into_potree();
 
 		assert_eq!(potree.size, 10);
+		let intensity = potree.attributes.get("intensity").unwrap();
+		assert_eq!(intensity.r#type, AttributeType::FLOAT);
+		assert!(intensity.min.x >= 0.0 && intensity.max.x <= 1.0);
+
+		Ok(())
+    }
+
+    #[test]
+    fn test_read_csv_column_mapping() -> Result<(), Box<dyn std::error::Error>> {
+		let buffer = fs::read("resources/points_semicolon.csv")?;
+		let options = CsvOptions {
+			delimiter: b';',
+			comment: Some(b'#'),
+			skip_rows: 1,
+			attributes: vec![
+				ColumnMapping::new(Column::name("R"), "red", AttributeType::UINT8),
+				ColumnMapping::new(Column::Index(6), "classification", AttributeType::UINT8),
+			],
+			..CsvOptions::default()
+		};
+		let potree = csv_reader::read_csv_with_options(&buffer, &options)?.into_potree();
+
+		assert_eq!(potree.size, 4);
+		assert_eq!(potree.attributes.bytes, 2);
+		let classification = potree.attributes.get("classification").unwrap();
+		assert_eq!((classification.min.x, classification.max.x), (1.0, 6.0));
+
+		Ok(())
+    }
+
+    #[test]
+    fn test_read_csv_without_header() -> Result<(), Box<dyn std::error::Error>> {
+		let buffer = b"1.0 2.0 3.0\n4.0 5.0 6.0\n";
+		let options = CsvOptions {
+			delimiter: b' ',
+			..CsvOptions::default()
+		};
+		let potree = csv_reader::read_csv_with_options(buffer, &options)?.into_potree();
+
+		assert_eq!(potree.size, 2);
+		assert_eq!(potree.bounds.ux, 4.0);
 
 		Ok(())
     }
 
-	
+    #[test]
+    fn test_read_csv_invalid_value() {
+		let buffer = b"x,y,z\n1.0,2.0,3.0\n1.0,abc,3.0\n";
+		match csv_reader::read_csv(buffer) {
+			Err(CsvReadError::InvalidValue { line, column, .. }) => {
+				assert_eq!(line, 3);
+				assert_eq!(column, "'y'");
+			}
+			_ => panic!("Expected invalid value error"),
+		}
+    }
 }
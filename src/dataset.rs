@@ -0,0 +1,344 @@
+use core::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::model::attributes::{Attribute, AttributeType, Attributes};
+use crate::model::bounds::Bounds;
+use crate::model::hierarchy::Type;
+use crate::model::metadata::Metadata;
+use crate::model::options::Encoding;
+use crate::model::point::Point;
+use crate::model::point_cloud::PointCloud;
+use crate::model::vector3::Vector3;
+
+/// type + childMask + numPoints + offset + size
+const BYTES_PER_NODE: usize = 1 + 1 + 4 + 8 + 8;
+
+#[derive(Debug)]
+pub enum DatasetError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    UnsupportedEncoding,
+    InvalidHierarchy { msg: String },
+}
+
+impl fmt::Display for DatasetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DatasetError::Io(error) => write!(f, "{}", error),
+            DatasetError::Json(error) => write!(f, "Invalid metadata.json: {}", error),
+            DatasetError::UnsupportedEncoding => {
+                write!(f, "Only datasets with the DEFAULT encoding can be read")
+            }
+            DatasetError::InvalidHierarchy { msg } => write!(f, "Invalid hierarchy.bin: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for DatasetError {}
+
+impl From<io::Error> for DatasetError {
+    fn from(error: io::Error) -> DatasetError {
+        DatasetError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for DatasetError {
+    fn from(error: serde_json::Error) -> DatasetError {
+        DatasetError::Json(error)
+    }
+}
+
+/// A node listed in `hierarchy.bin`, with the location of its points in `octree.bin`.
+pub struct DatasetNode {
+    pub name: String,
+    pub bounds: Bounds,
+    pub num_points: u32,
+    pub byte_offset: u64,
+    pub byte_size: u64,
+}
+
+impl DatasetNode {
+    pub fn level(&self) -> usize {
+        self.name.len() - 1
+    }
+}
+
+/// Selects the points of a dataset inside `bounds`, from nodes down to `max_level`.
+/// Either limit may be left out.
+#[derive(Clone, Default)]
+pub struct Query {
+    pub bounds: Option<Bounds>,
+    pub max_level: Option<usize>,
+}
+
+impl Query {
+    fn matches_node(&self, node: &DatasetNode) -> bool {
+        self.max_level.is_none_or(|level| node.level() <= level)
+            && self
+                .bounds
+                .as_ref()
+                .is_none_or(|bounds| bounds.intersects(&node.bounds))
+    }
+}
+
+/// A Potree 2.0 dataset on disk, with its hierarchy loaded and points read node by node.
+pub struct PotreeDataset {
+    pub metadata: Metadata,
+    /// Attributes of every point, without the position.
+    pub attributes: Attributes,
+    /// Every node of the hierarchy, breadth first within each hierarchy chunk.
+    pub nodes: Vec<DatasetNode>,
+    dir: PathBuf,
+}
+
+fn dataset_attributes(metadata: &Metadata) -> Attributes {
+    let list = metadata
+        .attributes
+        .iter()
+        .filter(|attribute| attribute.name != "position")
+        .map(|attribute| {
+            let r#type = AttributeType::from_name(&attribute.r#type);
+            let mut result = Attribute::new(&attribute.name, r#type, attribute.num_elements as i32);
+            result.description = attribute.description.clone();
+            let to_vector = |values: &[f64], default: f64| Vector3 {
+                x: values.first().copied().unwrap_or(default),
+                y: values.get(1).copied().unwrap_or(default),
+                z: values.get(2).copied().unwrap_or(default),
+            };
+            result.min = to_vector(&attribute.min, f64::INFINITY);
+            result.max = to_vector(&attribute.max, f64::NEG_INFINITY);
+            result
+        })
+        .collect();
+    Attributes::from_attributes(list)
+}
+
+/// Reads the nodes of the hierarchy chunk at `offset`, whose first entry is the node
+/// `name` with `bounds`. Proxy entries are replaced by the chunks they point to.
+fn read_chunk(
+    hierarchy: &[u8],
+    offset: usize,
+    size: usize,
+    root: (String, Bounds),
+    nodes: &mut Vec<DatasetNode>,
+) -> Result<(), DatasetError> {
+    let chunk =
+        hierarchy
+            .get(offset..offset + size)
+            .ok_or_else(|| DatasetError::InvalidHierarchy {
+                msg: format!("chunk at {} of {} bytes is out of bounds", offset, size),
+            })?;
+    let mut pending = vec![root];
+    for (i, entry) in chunk.chunks_exact(BYTES_PER_NODE).enumerate() {
+        let (name, bounds) =
+            pending
+                .get(i)
+                .cloned()
+                .ok_or_else(|| DatasetError::InvalidHierarchy {
+                    msg: format!("chunk at {} lists more nodes than its child masks", offset),
+                })?;
+        let (node_type, child_mask) = (entry[0], entry[1]);
+        let num_points = LittleEndian::read_u32(&entry[2..6]);
+        let byte_offset = LittleEndian::read_u64(&entry[6..14]);
+        let byte_size = LittleEndian::read_u64(&entry[14..22]);
+
+        if node_type == Type::Proxy as u8 {
+            read_chunk(
+                hierarchy,
+                byte_offset as usize,
+                byte_size as usize,
+                (name, bounds),
+                nodes,
+            )?;
+            continue;
+        }
+        for index in 0..8 {
+            if child_mask & (1 << index) != 0 {
+                pending.push((format!("{}{}", name, index), bounds.child(index)));
+            }
+        }
+        nodes.push(DatasetNode {
+            name,
+            bounds,
+            num_points,
+            byte_offset,
+            byte_size,
+        });
+    }
+    Ok(())
+}
+
+impl PotreeDataset {
+    /// Opens the dataset in `dir` from its `metadata.json` and `hierarchy.bin`.
+    pub fn open(dir: &Path) -> Result<PotreeDataset, DatasetError> {
+        let metadata: Metadata = serde_json::from_slice(&fs::read(dir.join("metadata.json"))?)?;
+        if !matches!(metadata.encoding, Encoding::DEFAULT) {
+            return Err(DatasetError::UnsupportedEncoding);
+        }
+        let hierarchy = fs::read(dir.join("hierarchy.bin"))?;
+
+        let (min, max) = (metadata.bounding_box.min, metadata.bounding_box.max);
+        let bounds = Bounds::new(max[0], max[1], max[2], min[0], min[1], min[2]);
+        let mut nodes = Vec::new();
+        read_chunk(
+            &hierarchy,
+            0,
+            metadata.hierarchy.first_chunk_size as usize,
+            ("r".to_string(), bounds),
+            &mut nodes,
+        )?;
+
+        Ok(PotreeDataset {
+            attributes: dataset_attributes(&metadata),
+            metadata,
+            nodes,
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    fn read_node_from(
+        &self,
+        octree: &mut File,
+        node: &DatasetNode,
+    ) -> Result<Vec<Point>, DatasetError> {
+        let mut buf = vec![0; node.byte_size as usize];
+        octree.seek(SeekFrom::Start(node.byte_offset))?;
+        octree.read_exact(&mut buf)?;
+
+        let bytes_per_point = 12 + self.attributes.bytes as usize;
+        let (scale, offset) = (self.metadata.scale, self.metadata.offset);
+        let points = buf
+            .chunks_exact(bytes_per_point)
+            .map(|record| {
+                let position = Vector3 {
+                    x: LittleEndian::read_i32(&record[0..4]) as f64 * scale[0] + offset[0],
+                    y: LittleEndian::read_i32(&record[4..8]) as f64 * scale[1] + offset[1],
+                    z: LittleEndian::read_i32(&record[8..12]) as f64 * scale[2] + offset[2],
+                };
+                Point::with_attributes(position, record[12..].to_vec())
+            })
+            .collect();
+        Ok(points)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Reads the points of one node from `octree.bin`.
+    pub fn read_node(&self, node: &DatasetNode) -> Result<Vec<Point>, DatasetError> {
+        let mut octree = File::open(self.dir.join("octree.bin"))?;
+        self.read_node_from(&mut octree, node)
+    }
+
+    /// Reads the points matching `query`, with attribute ranges of the selected points and
+    /// the scale and projection of the dataset.
+    pub fn query(&self, query: &Query) -> Result<PointCloud, DatasetError> {
+        let mut octree = File::open(self.dir.join("octree.bin"))?;
+        let mut points = Vec::new();
+        for node in self.nodes.iter().filter(|node| query.matches_node(node)) {
+            let node_points = self.read_node_from(&mut octree, node)?;
+            points.extend(node_points.into_iter().filter(|point| {
+                query
+                    .bounds
+                    .as_ref()
+                    .is_none_or(|bounds| bounds.contains(&point.position))
+            }));
+        }
+
+        let list = self
+            .attributes
+            .list
+            .iter()
+            .map(|attribute| Attribute {
+                min: Vector3::infinity(),
+                max: Vector3::infinity() * -1.0,
+                ..attribute.clone()
+            })
+            .collect();
+        let mut attributes = Attributes::from_attributes(list);
+        for point in &points {
+            attributes.update_ranges(&point.attributes);
+        }
+        let projection = &self.metadata.projection;
+        Ok(PointCloud {
+            crs: (!projection.is_empty()).then(|| projection.clone()),
+            scale: Some(self.metadata.scale.into_iter().fold(f64::INFINITY, f64::min)),
+            ..PointCloud::new(points, attributes)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::potree::Potree;
+    use crate::writer::write_potree;
+
+    #[test]
+    fn test_query_dataset() -> Result<(), Box<dyn std::error::Error>> {
+        let points = (0..5000)
+            .map(|i| {
+                let position = Vector3 {
+                    x: (i % 50) as f64,
+                    y: (i / 50 % 20) as f64,
+                    z: (i % 7) as f64,
+                };
+                Point::with_attributes(position, (i as u32).to_le_bytes().to_vec())
+            })
+            .collect();
+        let attributes =
+            Attributes::from_attributes(vec![Attribute::new("index", AttributeType::UINT32, 1)]);
+        let mut potree = Potree::with_attributes(points, attributes, 300);
+        potree.crs = Some("EPSG:25832".to_string());
+        let scale = potree.scale;
+
+        let dir = std::env::temp_dir().join("potree-dataset-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir)?;
+        write_potree(potree, &dir)?;
+
+        let dataset = PotreeDataset::open(&dir)?;
+        assert!(dataset.nodes.len() > 1);
+        let total: u32 = dataset.nodes.iter().map(|node| node.num_points).sum();
+        assert_eq!(total, 5000);
+
+        let all = dataset.query(&Query::default())?;
+        assert_eq!(all.len(), 5000);
+        assert_eq!(all.crs.as_deref(), Some("EPSG:25832"));
+        assert_eq!(all.scale, Some(scale));
+        let index = all.attributes.get("index").unwrap();
+        assert_eq!((index.min.x, index.max.x), (0.0, 4999.0));
+        let first = all
+            .points
+            .iter()
+            .find(|point| point.attributes == 1234u32.to_le_bytes())
+            .unwrap();
+        assert!((first.position.x - 34.0).abs() < 1e-3);
+        assert!((first.position.y - 4.0).abs() < 1e-3);
+
+        let bounds = Bounds::new(10.0, 5.0, 10.0, 0.0, 0.0, 0.0);
+        let boxed = dataset.query(&Query {
+            bounds: Some(bounds.clone()),
+            max_level: None,
+        })?;
+        assert_eq!(boxed.len(), 11 * 6 * 5000 / (50 * 20));
+        assert!(boxed
+            .points
+            .iter()
+            .all(|point| bounds.contains(&point.position)));
+
+        let root = dataset.query(&Query {
+            bounds: None,
+            max_level: Some(0),
+        })?;
+        assert_eq!(root.len() as u32, dataset.nodes[0].num_points);
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
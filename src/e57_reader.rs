@@ -0,0 +1,624 @@
+use core::fmt;
+
+use byteorder::{ByteOrder, LittleEndian};
+use roxmltree::{Document, Node as XmlNode};
+
+use crate::model::attributes::{Attribute, AttributeType, Attributes};
+use crate::model::point::Point;
+use crate::model::point_cloud::PointCloud;
+use crate::model::vector3::Vector3;
+
+const SIGNATURE: &[u8] = b"ASTM-E57";
+const HEADER_SIZE: usize = 48;
+const CRC_SIZE: usize = 4;
+const SECTION_HEADER_SIZE: usize = 32;
+const DATA_PACKET: u8 = 1;
+
+#[derive(Debug)]
+pub enum E57ReadError {
+    InvalidHeader,
+    Xml(String),
+    Unsupported { msg: String },
+    Truncated,
+    NoPoints,
+}
+
+impl fmt::Display for E57ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            E57ReadError::InvalidHeader => write!(f, "Not an E57 file"),
+            E57ReadError::Xml(msg) => write!(f, "Invalid E57 XML section: {}", msg),
+            E57ReadError::Unsupported { msg } => write!(f, "Unsupported E57 content: {}", msg),
+            E57ReadError::Truncated => write!(f, "E57 file is truncated"),
+            E57ReadError::NoPoints => write!(f, "No points in E57 file"),
+        }
+    }
+}
+
+impl std::error::Error for E57ReadError {}
+
+/// Encoding of a field in a compressed vector prototype.
+enum Encoding {
+    Float { double: bool },
+    Integer { min: i64, max: i64 },
+    ScaledInteger { min: i64, max: i64, scale: f64, offset: f64 },
+}
+
+struct Field {
+    name: String,
+    encoding: Encoding,
+}
+
+impl Field {
+    /// Value range implied by the prototype, if the encoding has one.
+    fn range(&self) -> Option<(f64, f64)> {
+        match self.encoding {
+            Encoding::Float { .. } => None,
+            Encoding::Integer { min, max } => Some((min as f64, max as f64)),
+            Encoding::ScaledInteger {
+                min,
+                max,
+                scale,
+                offset,
+            } => Some((min as f64 * scale + offset, max as f64 * scale + offset)),
+        }
+    }
+}
+
+struct Pose {
+    rotation: [[f64; 3]; 3],
+    translation: [f64; 3],
+}
+
+impl Pose {
+    fn from_quaternion(w: f64, x: f64, y: f64, z: f64, translation: [f64; 3]) -> Pose {
+        Pose {
+            rotation: [
+                [
+                    1.0 - 2.0 * (y * y + z * z),
+                    2.0 * (x * y - w * z),
+                    2.0 * (x * z + w * y),
+                ],
+                [
+                    2.0 * (x * y + w * z),
+                    1.0 - 2.0 * (x * x + z * z),
+                    2.0 * (y * z - w * x),
+                ],
+                [
+                    2.0 * (x * z - w * y),
+                    2.0 * (y * z + w * x),
+                    1.0 - 2.0 * (x * x + y * y),
+                ],
+            ],
+            translation,
+        }
+    }
+
+    fn apply(&self, p: [f64; 3]) -> Vector3 {
+        let r = &self.rotation;
+        let t = &self.translation;
+        Vector3 {
+            x: r[0][0] * p[0] + r[0][1] * p[1] + r[0][2] * p[2] + t[0],
+            y: r[1][0] * p[0] + r[1][1] * p[1] + r[1][2] * p[2] + t[1],
+            z: r[2][0] * p[0] + r[2][1] * p[1] + r[2][2] * p[2] + t[2],
+        }
+    }
+}
+
+struct Scan {
+    pose: Pose,
+    record_count: usize,
+    file_offset: usize,
+    fields: Vec<Field>,
+    intensity_limits: Option<(f64, f64)>,
+    color_limits: [Option<(f64, f64)>; 3],
+}
+
+/// Removes the CRC at the end of every page, leaving the logical byte stream.
+fn logical_bytes(buf: &[u8], page_size: usize) -> Vec<u8> {
+    let mut logical = Vec::with_capacity(buf.len());
+    for page in buf.chunks(page_size) {
+        logical.extend_from_slice(&page[..page.len().saturating_sub(CRC_SIZE)]);
+    }
+    logical
+}
+
+fn to_logical(physical: usize, page_size: usize) -> usize {
+    (physical / page_size) * (page_size - CRC_SIZE) + physical % page_size
+}
+
+fn slice(buf: &[u8], start: usize, len: usize) -> Result<&[u8], E57ReadError> {
+    buf.get(start..start + len).ok_or(E57ReadError::Truncated)
+}
+
+fn child<'a, 'input>(node: XmlNode<'a, 'input>, name: &str) -> Option<XmlNode<'a, 'input>> {
+    node.children().find(|c| c.is_element() && c.tag_name().name() == name)
+}
+
+fn child_f64(node: XmlNode, name: &str) -> Option<f64> {
+    child(node, name).and_then(|c| c.text()).and_then(|t| t.trim().parse().ok())
+}
+
+fn attribute_f64(node: XmlNode, name: &str) -> Option<f64> {
+    node.attribute(name).and_then(|value| value.trim().parse().ok())
+}
+
+fn attribute_i64(node: XmlNode, name: &str) -> Option<i64> {
+    node.attribute(name).and_then(|value| value.trim().parse().ok())
+}
+
+fn parse_pose(scan: XmlNode) -> Pose {
+    let pose = child(scan, "pose");
+    let rotation = pose.and_then(|p| child(p, "rotation"));
+    let translation = pose.and_then(|p| child(p, "translation"));
+    let component = |node: Option<XmlNode>, name: &str, default: f64| {
+        node.and_then(|n| child_f64(n, name)).unwrap_or(default)
+    };
+
+    Pose::from_quaternion(
+        component(rotation, "w", 1.0),
+        component(rotation, "x", 0.0),
+        component(rotation, "y", 0.0),
+        component(rotation, "z", 0.0),
+        [
+            component(translation, "x", 0.0),
+            component(translation, "y", 0.0),
+            component(translation, "z", 0.0),
+        ],
+    )
+}
+
+fn parse_field(node: XmlNode) -> Result<Field, E57ReadError> {
+    let name = node.tag_name().name().to_string();
+    let min = attribute_i64(node, "minimum").unwrap_or(i64::MIN);
+    let max = attribute_i64(node, "maximum").unwrap_or(i64::MAX);
+    let encoding = match node.attribute("type") {
+        Some("Float") => Encoding::Float {
+            double: node.attribute("precision") != Some("single"),
+        },
+        Some("Integer") => Encoding::Integer { min, max },
+        Some("ScaledInteger") => Encoding::ScaledInteger {
+            min,
+            max,
+            scale: attribute_f64(node, "scale").unwrap_or(1.0),
+            offset: attribute_f64(node, "offset").unwrap_or(0.0),
+        },
+        other => {
+            return Err(E57ReadError::Unsupported {
+                msg: format!("field '{}' of type {:?}", name, other),
+            })
+        }
+    };
+
+    Ok(Field { name, encoding })
+}
+
+fn parse_limits(scan: XmlNode, limits: &str, min: &str, max: &str) -> Option<(f64, f64)> {
+    let limits = child(scan, limits)?;
+    Some((child_f64(limits, min)?, child_f64(limits, max)?))
+}
+
+fn parse_scan(scan: XmlNode) -> Result<Scan, E57ReadError> {
+    let points = child(scan, "points")
+        .ok_or_else(|| E57ReadError::Xml("data3D entry without points".to_string()))?;
+    let prototype = child(points, "prototype")
+        .ok_or_else(|| E57ReadError::Xml("points without prototype".to_string()))?;
+    let fields = prototype
+        .children()
+        .filter(|c| c.is_element())
+        .map(parse_field)
+        .collect::<Result<Vec<Field>, E57ReadError>>()?;
+
+    Ok(Scan {
+        pose: parse_pose(scan),
+        record_count: attribute_i64(points, "recordCount").unwrap_or(0) as usize,
+        file_offset: attribute_i64(points, "fileOffset")
+            .ok_or_else(|| E57ReadError::Xml("points without fileOffset".to_string()))?
+            as usize,
+        fields,
+        intensity_limits: parse_limits(scan, "intensityLimits", "intensityMinimum", "intensityMaximum"),
+        color_limits: [
+            parse_limits(scan, "colorLimits", "colorRedMinimum", "colorRedMaximum"),
+            parse_limits(scan, "colorLimits", "colorGreenMinimum", "colorGreenMaximum"),
+            parse_limits(scan, "colorLimits", "colorBlueMinimum", "colorBlueMaximum"),
+        ],
+    })
+}
+
+/// Collects the bytestream of every field across all data packets of a binary section.
+fn read_bytestreams(
+    logical: &[u8],
+    scan: &Scan,
+    page_size: usize,
+) -> Result<Vec<Vec<u8>>, E57ReadError> {
+    let section_start = to_logical(scan.file_offset, page_size);
+    let header = slice(logical, section_start, SECTION_HEADER_SIZE)?;
+    if header[0] != 1 {
+        return Err(E57ReadError::Unsupported {
+            msg: format!("section id {} for compressed vector", header[0]),
+        });
+    }
+    let section_end = section_start + LittleEndian::read_u64(&header[8..16]) as usize;
+    let mut position = to_logical(LittleEndian::read_u64(&header[16..24]) as usize, page_size);
+
+    let mut streams = vec![Vec::new(); scan.fields.len()];
+    while position < section_end {
+        let packet_header = slice(logical, position, 6)?;
+        let packet_length = LittleEndian::read_u16(&packet_header[2..4]) as usize + 1;
+        if packet_header[0] == DATA_PACKET {
+            let count = LittleEndian::read_u16(&packet_header[4..6]) as usize;
+            if count != streams.len() {
+                return Err(E57ReadError::Unsupported {
+                    msg: format!("{} bytestreams for {} fields", count, streams.len()),
+                });
+            }
+            let lengths = slice(logical, position + 6, count * 2)?;
+            let mut offset = position + 6 + count * 2;
+            for (i, stream) in streams.iter_mut().enumerate() {
+                let length = LittleEndian::read_u16(&lengths[i * 2..]) as usize;
+                stream.extend_from_slice(slice(logical, offset, length)?);
+                offset += length;
+            }
+        }
+        position += packet_length;
+    }
+
+    Ok(streams)
+}
+
+fn bits_for_range(min: i64, max: i64) -> u32 {
+    let range = (max as i128 - min as i128) as u128;
+    128 - range.leading_zeros()
+}
+
+/// Reads `count` integers packed least significant bit first with `bits` bits each.
+fn unpack_integers(stream: &[u8], bits: u32, count: usize) -> Result<Vec<u64>, E57ReadError> {
+    if bits == 0 {
+        return Ok(vec![0; count]);
+    }
+    if (count * bits as usize).div_ceil(8) > stream.len() {
+        return Err(E57ReadError::Truncated);
+    }
+    let mask = if bits == 64 { u64::MAX } else { (1u64 << bits) - 1 };
+    let mut values = Vec::with_capacity(count);
+    for i in 0..count {
+        let start = i * bits as usize;
+        let first = start / 8;
+        let last = (start + bits as usize).div_ceil(8).min(stream.len());
+        let mut window: u128 = 0;
+        for (j, byte) in stream[first..last].iter().enumerate() {
+            window |= (*byte as u128) << (8 * j);
+        }
+        values.push((window >> (start % 8)) as u64 & mask);
+    }
+    Ok(values)
+}
+
+fn decode_field(field: &Field, stream: &[u8], count: usize) -> Result<Vec<f64>, E57ReadError> {
+    match field.encoding {
+        Encoding::Float { double } => {
+            let size = if double { 8 } else { 4 };
+            if stream.len() < count * size {
+                return Err(E57ReadError::Truncated);
+            }
+            Ok(stream
+                .chunks_exact(size)
+                .take(count)
+                .map(|bytes| {
+                    if double {
+                        LittleEndian::read_f64(bytes)
+                    } else {
+                        LittleEndian::read_f32(bytes) as f64
+                    }
+                })
+                .collect())
+        }
+        Encoding::Integer { min, max } => Ok(unpack_integers(stream, bits_for_range(min, max), count)?
+            .into_iter()
+            .map(|value| (min as i128 + value as i128) as f64)
+            .collect()),
+        Encoding::ScaledInteger {
+            min,
+            max,
+            scale,
+            offset,
+        } => Ok(unpack_integers(stream, bits_for_range(min, max), count)?
+            .into_iter()
+            .map(|value| (min as i128 + value as i128) as f64 * scale + offset)
+            .collect()),
+    }
+}
+
+fn normalize(value: f64, limits: Option<(f64, f64)>, target: f64) -> f64 {
+    match limits {
+        Some((min, max)) if max > min => ((value - min) / (max - min)).clamp(0.0, 1.0) * target,
+        _ => value.clamp(0.0, target),
+    }
+}
+
+/// Reads every scan of an E57 file into one point cloud in the file's common frame.
+///
+/// Each scan's pose is applied to its points and spherical coordinates are converted to
+/// Cartesian ones. Points flagged invalid by `cartesianInvalidState` or
+/// `sphericalInvalidState` are skipped. Intensity is scaled to uint16 and colors to 0..255
+/// using the scan's limits, and `scan_index` holds the position of the scan in `data3D`.
+/// The file's `coordinateMetadata`, if any, becomes the coordinate reference system.
+pub fn read_e57(buf: &[u8]) -> Result<PointCloud, E57ReadError> {
+    if buf.len() < HEADER_SIZE || &buf[0..8] != SIGNATURE {
+        return Err(E57ReadError::InvalidHeader);
+    }
+    let xml_offset = LittleEndian::read_u64(&buf[24..32]) as usize;
+    let xml_length = LittleEndian::read_u64(&buf[32..40]) as usize;
+    let page_size = LittleEndian::read_u64(&buf[40..48]) as usize;
+    if page_size <= CRC_SIZE {
+        return Err(E57ReadError::InvalidHeader);
+    }
+
+    let logical = logical_bytes(buf, page_size);
+    let xml_bytes = slice(&logical, to_logical(xml_offset, page_size), xml_length)?;
+    let xml = std::str::from_utf8(xml_bytes).map_err(|e| E57ReadError::Xml(e.to_string()))?;
+    let document = Document::parse(xml).map_err(|e| E57ReadError::Xml(e.to_string()))?;
+    let root = document.root_element();
+    let crs = child(root, "coordinateMetadata")
+        .and_then(|c| c.text())
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+        .map(str::to_string);
+    let scans = match child(root, "data3D") {
+        Some(data3d) => data3d
+            .children()
+            .filter(|c| c.is_element())
+            .map(parse_scan)
+            .collect::<Result<Vec<Scan>, E57ReadError>>()?,
+        None => Vec::new(),
+    };
+
+    let has_field = |name: &str| scans.iter().any(|s| s.fields.iter().any(|f| f.name == name));
+    let has_intensity = has_field("intensity");
+    let has_color = has_field("colorRed");
+    let mut attribute_list = Vec::new();
+    if has_intensity {
+        attribute_list.push(Attribute::new("intensity", AttributeType::UINT16, 1));
+    }
+    if has_color {
+        attribute_list.push(Attribute::new("rgb", AttributeType::UINT16, 3));
+    }
+    attribute_list.push(Attribute::new("scan_index", AttributeType::UINT16, 1));
+
+    let mut points: Vec<Point> = Vec::new();
+    for (scan_index, scan) in scans.iter().enumerate() {
+        let streams = read_bytestreams(&logical, scan, page_size)?;
+        let mut columns = Vec::new();
+        for (field, stream) in scan.fields.iter().zip(&streams) {
+            columns.push(decode_field(field, stream, scan.record_count)?);
+        }
+        let column = |name: &str| {
+            scan.fields
+                .iter()
+                .position(|f| f.name == name)
+                .map(|i| (&columns[i], scan.fields[i].range()))
+        };
+
+        let cartesian = match (column("cartesianX"), column("cartesianY"), column("cartesianZ")) {
+            (Some((x, _)), Some((y, _)), Some((z, _))) => Some([x, y, z]),
+            _ => None,
+        };
+        let spherical = match (
+            column("sphericalRange"),
+            column("sphericalAzimuth"),
+            column("sphericalElevation"),
+        ) {
+            (Some((r, _)), Some((a, _)), Some((e, _))) => Some([r, a, e]),
+            _ => None,
+        };
+        let invalid = if cartesian.is_some() {
+            column("cartesianInvalidState")
+        } else {
+            column("sphericalInvalidState")
+        };
+        let intensity = column("intensity");
+        let colors = [column("colorRed"), column("colorGreen"), column("colorBlue")];
+
+        for i in 0..scan.record_count {
+            if invalid.is_some_and(|(states, _)| states[i] != 0.0) {
+                continue;
+            }
+            let local = match (cartesian, spherical) {
+                (Some([x, y, z]), _) => [x[i], y[i], z[i]],
+                (None, Some([range, azimuth, elevation])) => [
+                    range[i] * elevation[i].cos() * azimuth[i].cos(),
+                    range[i] * elevation[i].cos() * azimuth[i].sin(),
+                    range[i] * elevation[i].sin(),
+                ],
+                (None, None) => {
+                    return Err(E57ReadError::Unsupported {
+                        msg: format!("scan {} has neither cartesian nor spherical coordinates", scan_index),
+                    })
+                }
+            };
+
+            let mut attributes = Vec::new();
+            if has_intensity {
+                let value = intensity.map_or(0.0, |(values, range)| {
+                    normalize(values[i], scan.intensity_limits.or(range), 65535.0)
+                });
+                AttributeType::UINT16.write_f64(value.round(), &mut attributes);
+            }
+            if has_color {
+                for (channel, limits) in colors.iter().zip(&scan.color_limits) {
+                    let value = channel.map_or(0.0, |(values, range)| {
+                        normalize(values[i], limits.or(range), 255.0)
+                    });
+                    AttributeType::UINT16.write_f64(value.round(), &mut attributes);
+                }
+            }
+            AttributeType::UINT16.write_f64(scan_index as f64, &mut attributes);
+
+            points.push(Point::with_attributes(scan.pose.apply(local), attributes));
+        }
+    }
+    if points.is_empty() {
+        return Err(E57ReadError::NoPoints);
+    }
+
+    Ok(PointCloud {
+        crs,
+        ..PointCloud::new(points, Attributes::from_attributes(attribute_list))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::e57_reader::{read_e57, unpack_integers, E57ReadError};
+
+    const PAGE_SIZE: usize = 1024;
+
+    fn to_physical(logical: usize) -> usize {
+        (logical / (PAGE_SIZE - 4)) * PAGE_SIZE + logical % (PAGE_SIZE - 4)
+    }
+
+    /// Packs values least significant bit first, as written by E57 bitpack encoders.
+    fn pack_integers(values: &[u64], bits: usize) -> Vec<u8> {
+        let mut bytes = vec![0u8; (values.len() * bits).div_ceil(8)];
+        for (i, value) in values.iter().enumerate() {
+            for bit in 0..bits {
+                if value >> bit & 1 == 1 {
+                    let position = i * bits + bit;
+                    bytes[position / 8] |= 1 << (position % 8);
+                }
+            }
+        }
+        bytes
+    }
+
+    fn f64_stream(values: &[f64]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    fn f32_stream(values: &[f32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    /// Builds a paged E57 file from `(scan xml with {offset} placeholder, bytestreams)`.
+    fn build_e57(scans: &[(String, Vec<Vec<u8>>)]) -> Vec<u8> {
+        let mut logical = vec![0u8; 48];
+        let mut scan_xml = String::new();
+        for (xml, streams) in scans {
+            let section_start = logical.len();
+            let mut packet = vec![1u8, 0, 0, 0];
+            packet.extend_from_slice(&(streams.len() as u16).to_le_bytes());
+            for stream in streams {
+                packet.extend_from_slice(&(stream.len() as u16).to_le_bytes());
+            }
+            for stream in streams {
+                packet.extend_from_slice(stream);
+            }
+            while packet.len() % 4 != 0 {
+                packet.push(0);
+            }
+            let packet_length = (packet.len() - 1) as u16;
+            packet[2..4].copy_from_slice(&packet_length.to_le_bytes());
+
+            logical.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0, 0]);
+            logical.extend_from_slice(&(32 + packet.len() as u64).to_le_bytes());
+            logical.extend_from_slice(&(to_physical(section_start + 32) as u64).to_le_bytes());
+            logical.extend_from_slice(&0u64.to_le_bytes());
+            logical.extend_from_slice(&packet);
+
+            scan_xml += &xml.replace("{offset}", &to_physical(section_start).to_string());
+        }
+
+        let xml = format!(
+            "<?xml version=\"1.0\"?><e57Root type=\"Structure\" xmlns=\"http://www.astm.org/COMMIT/E57/2010-e57-v1.0\"><coordinateMetadata type=\"String\">EPSG:32633</coordinateMetadata><data3D type=\"Vector\">{}</data3D></e57Root>",
+            scan_xml
+        );
+        let xml_offset = to_physical(logical.len());
+        logical.extend_from_slice(xml.as_bytes());
+
+        let mut physical = Vec::new();
+        for page in logical.chunks(PAGE_SIZE - 4) {
+            physical.extend_from_slice(page);
+            physical.resize(physical.len() + PAGE_SIZE - 4 - page.len() + 4, 0);
+        }
+        let length = physical.len() as u64;
+        physical[0..8].copy_from_slice(b"ASTM-E57");
+        physical[8..12].copy_from_slice(&1u32.to_le_bytes());
+        physical[16..24].copy_from_slice(&length.to_le_bytes());
+        physical[24..32].copy_from_slice(&(xml_offset as u64).to_le_bytes());
+        physical[32..40].copy_from_slice(&(xml.len() as u64).to_le_bytes());
+        physical[40..48].copy_from_slice(&(PAGE_SIZE as u64).to_le_bytes());
+        physical
+    }
+
+    #[test]
+    fn test_unpack_integers() {
+        let values = [0, 100, 57, 3, 127, 64];
+        let packed = pack_integers(&values, 7);
+        assert_eq!(unpack_integers(&packed, 7, values.len()).unwrap(), values);
+    }
+
+    #[test]
+    fn test_read_e57() -> Result<(), E57ReadError> {
+        let cartesian_scan = (
+            "<vectorChild type=\"Structure\">\
+                <pose type=\"Structure\"><translation type=\"Structure\"><x type=\"Float\">10</x><y type=\"Float\">0</y><z type=\"Float\">0</z></translation></pose>\
+                <points type=\"CompressedVector\" fileOffset=\"{offset}\" recordCount=\"3\"><prototype type=\"Structure\">\
+                    <cartesianX type=\"Float\"/><cartesianY type=\"Float\"/><cartesianZ type=\"Float\"/>\
+                    <intensity type=\"Integer\" minimum=\"0\" maximum=\"100\"/>\
+                    <cartesianInvalidState type=\"Integer\" minimum=\"0\" maximum=\"2\"/>\
+                </prototype></points></vectorChild>"
+                .to_string(),
+            vec![
+                f64_stream(&[1.0, 2.0, 3.0]),
+                f64_stream(&[0.0, 0.0, 0.0]),
+                f64_stream(&[1.0, 1.0, 1.0]),
+                pack_integers(&[0, 50, 100], 7),
+                pack_integers(&[0, 0, 2], 2),
+            ],
+        );
+        let half = std::f64::consts::FRAC_1_SQRT_2;
+        let spherical_scan = (
+            format!(
+                "<vectorChild type=\"Structure\">\
+                    <pose type=\"Structure\"><rotation type=\"Structure\"><w type=\"Float\">{}</w><x type=\"Float\">0</x><y type=\"Float\">0</y><z type=\"Float\">{}</z></rotation></pose>\
+                    <colorLimits type=\"Structure\"><colorRedMinimum type=\"Integer\">0</colorRedMinimum><colorRedMaximum type=\"Integer\">255</colorRedMaximum></colorLimits>\
+                    <points type=\"CompressedVector\" fileOffset=\"{{offset}}\" recordCount=\"2\"><prototype type=\"Structure\">\
+                        <sphericalRange type=\"ScaledInteger\" minimum=\"0\" maximum=\"10000\" scale=\"0.001\"/>\
+                        <sphericalAzimuth type=\"Float\" precision=\"single\"/><sphericalElevation type=\"Float\" precision=\"single\"/>\
+                        <colorRed type=\"Integer\" minimum=\"0\" maximum=\"255\"/>\
+                    </prototype></points></vectorChild>",
+                half, half
+            ),
+            vec![
+                pack_integers(&[5000, 2000], 14),
+                f32_stream(&[0.0, 0.0]),
+                f32_stream(&[0.0, std::f32::consts::FRAC_PI_2]),
+                pack_integers(&[255, 0], 8),
+            ],
+        );
+
+        let potree = read_e57(&build_e57(&[cartesian_scan, spherical_scan]))?.into_potree();
+
+        assert_eq!(potree.size, 4);
+        assert_eq!(potree.crs.as_deref(), Some("EPSG:32633"));
+        let names: Vec<&str> = potree.attributes.list.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, ["intensity", "rgb", "scan_index"]);
+        let intensity = potree.attributes.get("intensity").unwrap();
+        assert_eq!((intensity.min.x, intensity.max.x), (0.0, 32768.0));
+        let scan_index = potree.attributes.get("scan_index").unwrap();
+        assert_eq!((scan_index.min.x, scan_index.max.x), (0.0, 1.0));
+
+        // The first scan is shifted by 10 along x, the second rotated 90 degrees around z
+        // which moves the point 5 along its local x axis to y.
+        assert!((potree.bounds.ux - 12.0).abs() < 1e-9);
+        assert!((potree.bounds.uy - 5.0).abs() < 1e-6);
+        assert!((potree.bounds.uz - 2.0).abs() < 1e-6);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_invalid_header() {
+        assert!(matches!(read_e57(b"not an e57 file"), Err(E57ReadError::InvalidHeader)));
+    }
+}
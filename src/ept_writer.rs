@@ -0,0 +1,512 @@
+use std::fs::{self, File};
+use std::io::Error;
+use std::path::Path;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+use laz::LazVlr;
+use serde_json::{Map, Value};
+
+use crate::las::{
+    compress_chunk, laz_vlr_payload, wkt_vlr, write_chunk_table, write_vlr, LasHeader, LasLayout,
+    HEADER_SIZE, LAZ_POINT_FORMAT_BIT, VLR_HEADER_SIZE,
+};
+use crate::model::attributes::{Attribute, AttributeType};
+use crate::model::ept::{Dimension, Ept, Srs};
+use crate::model::node::Node;
+use crate::potree::Potree;
+use crate::writer::HRC_STEP_SIZE;
+
+/// PDAL dimension names of the attributes a LAS reader produces.
+const PDAL_NAMES: [(&[&str], &str); 8] = [
+    (&["intensity"], "Intensity"),
+    (&["return number"], "ReturnNumber"),
+    (&["number of returns"], "NumberOfReturns"),
+    (&["classification"], "Classification"),
+    (&["user data"], "UserData"),
+    (&["scan angle", "scan angle rank"], "ScanAngleRank"),
+    (&["point source id"], "PointSourceId"),
+    (&["gps time", "timestamp"], "GpsTime"),
+];
+
+/// The EPT dimension names of an attribute, one per element.
+fn dimension_names(attribute: &Attribute) -> Vec<String> {
+    let elements = attribute.num_elements as usize;
+    let name = attribute.name.to_lowercase().replace(['_', '-'], " ");
+    match name.as_str() {
+        "rgb" | "rgba" | "color" if (3..=4).contains(&elements) => {
+            return ["Red", "Green", "Blue", "Alpha"][..elements]
+                .iter()
+                .map(|name| name.to_string())
+                .collect();
+        }
+        "normal" | "normals" if elements == 3 => {
+            return ["NormalX", "NormalY", "NormalZ"]
+                .iter()
+                .map(|name| name.to_string())
+                .collect();
+        }
+        _ => {}
+    }
+    if elements == 1 {
+        if let Some((_, pdal_name)) = PDAL_NAMES
+            .iter()
+            .find(|(names, _)| names.contains(&name.as_str()))
+        {
+            return vec![pdal_name.to_string()];
+        }
+    }
+
+    // Dimension names may only hold letters, digits and underscores.
+    let name: String = attribute
+        .name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if elements == 1 {
+        vec![name]
+    } else {
+        (0..elements)
+            .map(|element| format!("{}_{}", name, element))
+            .collect()
+    }
+}
+
+fn dimension_type(r#type: AttributeType) -> &'static str {
+    match r#type {
+        AttributeType::INT8
+        | AttributeType::INT16
+        | AttributeType::INT32
+        | AttributeType::INT64 => "signed",
+        AttributeType::FLOAT | AttributeType::DOUBLE => "float",
+        _ => "unsigned",
+    }
+}
+
+/// `authority` and `horizontal` for CRS codes such as `EPSG:4326`, `wkt` for anything else.
+fn srs(crs: &str) -> Srs {
+    match crs.split_once(':') {
+        Some((authority, code))
+            if !crs.contains('[') && code.chars().all(|c| c.is_ascii_digit()) =>
+        {
+            Srs {
+                authority: Some(authority.to_uppercase()),
+                horizontal: Some(code.to_string()),
+                wkt: None,
+            }
+        }
+        _ => Srs {
+            wkt: Some(crs.to_string()),
+            ..Srs::default()
+        },
+    }
+}
+
+fn key(node: &Node) -> String {
+    let [d, x, y, z] = node.voxel_key();
+    format!("{}-{}-{}-{}", d, x, y, z)
+}
+
+fn non_empty_children(node: &Node) -> impl Iterator<Item = &Node> {
+    node.children
+        .iter()
+        .flatten()
+        .map(|child| child.as_ref())
+        .filter(|child| child.num_points() > 0)
+}
+
+/// Encoding of the files in `ept-data`.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub enum EptDataType {
+    /// Scaled 32 bit integer positions followed by the attributes as they are.
+    #[default]
+    Binary,
+    /// A LAZ file per node with the attributes mapped onto LAS fields by [`LasLayout`].
+    Laszip,
+}
+
+impl EptDataType {
+    fn name(self) -> &'static str {
+        match self {
+            EptDataType::Binary => "binary",
+            EptDataType::Laszip => "laszip",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            EptDataType::Binary => "bin",
+            EptDataType::Laszip => "laz",
+        }
+    }
+}
+
+/// Options for writing an Entwine Point Tile dataset.
+#[derive(Default)]
+pub struct EptOptions {
+    pub data_type: EptDataType,
+}
+
+/// The LAS layout and VLRs every LAZ file of a dataset shares.
+struct LazFiles {
+    layout: LasLayout,
+    laz_vlr: LazVlr,
+    vlrs: Vec<(&'static str, u16, &'static str, Vec<u8>)>,
+}
+
+impl LazFiles {
+    fn new(potree: &Potree) -> Result<LazFiles, Error> {
+        let layout = LasLayout::new(&potree.attributes);
+        let laz_vlr = layout.laz_vlr()?;
+        let mut vlrs = vec![(
+            LazVlr::USER_ID,
+            LazVlr::RECORD_ID,
+            LazVlr::DESCRIPTION,
+            laz_vlr_payload(&laz_vlr)?,
+        )];
+        if let Some(wkt) = wkt_vlr(potree.crs.as_deref()) {
+            vlrs.push(("LASF_Projection", 2112, "OGC WKT", wkt));
+        }
+        if let Some(extra_bytes) = layout.extra_bytes_vlr() {
+            vlrs.push(("LASF_Spec", 4, "Extra bytes", extra_bytes));
+        }
+        Ok(LazFiles {
+            layout,
+            laz_vlr,
+            vlrs,
+        })
+    }
+
+    /// A LAZ 1.4 file with the points of `node` in a single chunk.
+    fn encode(&self, node: &Node, scale: f64, offset: [f64; 3]) -> Result<Vec<u8>, Error> {
+        let layout = &self.layout;
+        let mut records = Vec::with_capacity(node.num_points() * layout.record_length as usize);
+        let mut points_by_return = [0u64; 15];
+        let mut min = [f64::INFINITY; 3];
+        let mut max = [f64::NEG_INFINITY; 3];
+        for point in node.points() {
+            let return_number = layout.write_point(point, scale, offset, &mut records);
+            if (1..=15).contains(&return_number) {
+                points_by_return[return_number as usize - 1] += 1;
+            }
+            let position = point.position.to_array();
+            min = [0, 1, 2].map(|axis| min[axis].min(position[axis]));
+            max = [0, 1, 2].map(|axis| max[axis].max(position[axis]));
+        }
+        let chunk = compress_chunk(&self.laz_vlr, &records)?;
+
+        let offset_to_points = HEADER_SIZE as usize
+            + self
+                .vlrs
+                .iter()
+                .map(|(_, _, _, payload)| VLR_HEADER_SIZE + payload.len())
+                .sum::<usize>();
+        let header = LasHeader {
+            point_format: layout.point_format | LAZ_POINT_FORMAT_BIT,
+            record_length: layout.record_length,
+            offset_to_points: offset_to_points as u32,
+            vlr_count: self.vlrs.len() as u32,
+            evlr_start: 0,
+            evlr_count: 0,
+            point_count: node.num_points() as u64,
+            points_by_return,
+            scale,
+            offset,
+            min,
+            max,
+        };
+        let mut buf = Vec::with_capacity(offset_to_points + 8 + chunk.len() + 16);
+        header.write(&mut buf)?;
+        for (user_id, record_id, description, payload) in &self.vlrs {
+            write_vlr(&mut buf, user_id, *record_id, description, payload)?;
+        }
+        buf.write_i64::<LittleEndian>((offset_to_points + 8 + chunk.len()) as i64)?;
+        buf.extend_from_slice(&chunk);
+        write_chunk_table(
+            &mut buf,
+            &self.laz_vlr,
+            &[(node.num_points() as u64, chunk.len() as u64)],
+        )?;
+        Ok(buf)
+    }
+}
+
+fn write_data(
+    node: &Node,
+    scale: f64,
+    offset: [f64; 3],
+    laz: Option<&LazFiles>,
+    dir: &Path,
+) -> Result<(), Error> {
+    let (buf, data_type) = match laz {
+        Some(laz) => (laz.encode(node, scale, offset)?, EptDataType::Laszip),
+        None => {
+            let mut buf = Vec::new();
+            for point in node.points() {
+                let position = point.position.to_array();
+                for axis in 0..3 {
+                    let value = ((position[axis] - offset[axis]) / scale).round();
+                    AttributeType::INT32.write_f64(value, &mut buf);
+                }
+                buf.extend_from_slice(&point.attributes);
+            }
+            (buf, EptDataType::Binary)
+        }
+    };
+    let name = format!("ept-data/{}.{}", key(node), data_type.extension());
+    fs::write(dir.join(name), buf)?;
+
+    for child in non_empty_children(node) {
+        write_data(child, scale, offset, laz, dir)?;
+    }
+    Ok(())
+}
+
+/// Adds the point counts of `node` and its descendants to the hierarchy file starting at
+/// `start_level`. Nodes `HRC_STEP_SIZE` levels deeper start a file of their own and are
+/// listed with a count of -1.
+fn add_counts(
+    node: &Node,
+    counts: &mut Map<String, Value>,
+    start_level: usize,
+    dir: &Path,
+) -> Result<(), Error> {
+    if node.level() == start_level + HRC_STEP_SIZE {
+        counts.insert(key(node), Value::from(-1));
+        return write_hierarchy(node, dir);
+    }
+    counts.insert(key(node), Value::from(node.num_points()));
+    for child in non_empty_children(node) {
+        add_counts(child, counts, start_level, dir)?;
+    }
+    Ok(())
+}
+
+fn write_hierarchy(node: &Node, dir: &Path) -> Result<(), Error> {
+    let mut counts = Map::new();
+    add_counts(node, &mut counts, node.level(), dir)?;
+    let file = File::create(dir.join(format!("ept-hierarchy/{}.json", key(node))))?;
+    serde_json::to_writer(file, &counts)?;
+    Ok(())
+}
+
+/// Writes the octree as an Entwine Point Tile dataset in the `binary` encoding.
+pub fn write_ept(potree: Potree, dir: &Path) -> Result<(), Error> {
+    write_ept_with_options(potree, dir, &EptOptions::default())
+}
+
+/// Writes the octree as an Entwine Point Tile dataset: `ept.json`, an
+/// `ept-data/D-X-Y-Z.bin` or `.laz` file per node and `ept-hierarchy` files every
+/// `HRC_STEP_SIZE` levels.
+///
+/// The octree is rebuilt with a cubic root first, as EPT keys address octants of a cube.
+/// Positions are stored as scaled 32 bit integers around the center of the root. In the
+/// `binary` encoding they are followed by the attributes as they are, named after their
+/// PDAL dimensions where there is one, and in the `laszip` encoding by the LAS fields and
+/// extra bytes the attributes map onto.
+pub fn write_ept_with_options(
+    potree: Potree,
+    dir: &Path,
+    options: &EptOptions,
+) -> Result<(), Error> {
+    let potree = potree.into_cubic();
+    fs::create_dir_all(dir.join("ept-data"))?;
+    fs::create_dir_all(dir.join("ept-hierarchy"))?;
+
+    let root = &potree.root.bounds;
+    let center = [
+        (root.lx + root.ux) / 2.0,
+        (root.ly + root.uy) / 2.0,
+        (root.lz + root.uz) / 2.0,
+    ];
+    let laz = match options.data_type {
+        EptDataType::Laszip => Some(LazFiles::new(&potree)?),
+        EptDataType::Binary => None,
+    };
+    write_data(&potree.root, potree.scale, center, laz.as_ref(), dir)?;
+    write_hierarchy(&potree.root, dir)?;
+
+    let mut schema: Vec<Dimension> = ["X", "Y", "Z"]
+        .iter()
+        .zip(center)
+        .map(|(name, offset)| Dimension {
+            name: name.to_string(),
+            r#type: "signed".to_string(),
+            size: 4,
+            scale: Some(potree.scale),
+            offset: Some(offset),
+        })
+        .collect();
+    let dimensions: Vec<(String, AttributeType)> = match &laz {
+        Some(laz) => laz.layout.dimensions(),
+        None => potree
+            .attributes
+            .list
+            .iter()
+            .flat_map(|attribute| {
+                dimension_names(attribute)
+                    .into_iter()
+                    .map(|name| (name, attribute.r#type))
+            })
+            .collect(),
+    };
+    for (name, r#type) in dimensions {
+        schema.push(Dimension {
+            name,
+            r#type: dimension_type(r#type).to_string(),
+            size: r#type.size() as u32,
+            scale: None,
+            offset: None,
+        });
+    }
+
+    let bounds = &potree.bounds;
+    let ept = Ept {
+        bounds: [root.lx, root.ly, root.lz, root.ux, root.uy, root.uz],
+        bounds_conforming: [
+            bounds.lx, bounds.ly, bounds.lz, bounds.ux, bounds.uy, bounds.uz,
+        ],
+        data_type: options.data_type.name().to_string(),
+        hierarchy_type: "json".to_string(),
+        points: potree.size as u64,
+        schema,
+        span: (root.size_x / potree.spacing).round().max(1.0) as u64,
+        srs: potree.crs.as_deref().map(srs),
+        version: "1.0.0".to_string(),
+    };
+    let file = File::create(dir.join("ept.json"))?;
+    serde_json::to_writer_pretty(file, &ept)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Cursor;
+
+    use laz::LasZipDecompressor;
+
+    use super::*;
+    use crate::model::attributes::Attributes;
+    use crate::model::point::Point;
+    use crate::model::vector3::Vector3;
+
+    #[test]
+    fn test_dimension_names() {
+        let names =
+            |name: &str, r#type, elements| dimension_names(&Attribute::new(name, r#type, elements));
+        assert_eq!(
+            names("rgb", AttributeType::UINT16, 3),
+            ["Red", "Green", "Blue"]
+        );
+        assert_eq!(names("gps-time", AttributeType::DOUBLE, 1), ["GpsTime"]);
+        assert_eq!(
+            names("surface normal", AttributeType::FLOAT, 2),
+            ["surface_normal_0", "surface_normal_1"]
+        );
+    }
+
+    fn potree() -> Potree {
+        let points = (0..3000)
+            .map(|i| {
+                let position = Vector3 {
+                    x: 100.0 + (i % 40) as f64,
+                    y: (i / 40 % 10) as f64,
+                    z: (i % 3) as f64,
+                };
+                let mut attributes = Vec::new();
+                for channel in [255.0, 0.0, 1.0] {
+                    AttributeType::UINT16.write_f64(channel, &mut attributes);
+                }
+                AttributeType::FLOAT.write_f64(i as f64, &mut attributes);
+                Point::with_attributes(position, attributes)
+            })
+            .collect();
+        let attributes = Attributes::from_attributes(vec![
+            Attribute::new("rgb", AttributeType::UINT16, 3),
+            Attribute::new("range", AttributeType::FLOAT, 1),
+        ]);
+        let mut potree = Potree::with_attributes(points, attributes, 200);
+        potree.crs = Some("EPSG:2056".to_string());
+        potree
+    }
+
+    #[test]
+    fn test_write_ept() -> Result<(), Box<dyn std::error::Error>> {
+        let potree = potree();
+        let dir = std::env::temp_dir().join("potree-ept-writer-test");
+        let _ = fs::remove_dir_all(&dir);
+        write_ept(potree, &dir)?;
+
+        let ept: Ept = serde_json::from_slice(&fs::read(dir.join("ept.json"))?)?;
+        assert_eq!(ept.points, 3000);
+        let names: Vec<&str> = ept.schema.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(names, ["X", "Y", "Z", "Red", "Green", "Blue", "range"]);
+        assert_eq!(ept.srs.unwrap().horizontal.as_deref(), Some("2056"));
+        assert_eq!(ept.bounds[3] - ept.bounds[0], ept.bounds[5] - ept.bounds[2]);
+        let record_size: u32 = ept.schema.iter().map(|d| d.size).sum();
+
+        let hierarchy: Map<String, Value> =
+            serde_json::from_slice(&fs::read(dir.join("ept-hierarchy/0-0-0-0.json"))?)?;
+        assert!(hierarchy.len() > 1);
+        let mut total = 0;
+        for (key, count) in &hierarchy {
+            let count = count.as_u64().unwrap();
+            let data = fs::read(dir.join(format!("ept-data/{}.bin", key)))?;
+            assert_eq!(data.len() as u64, count * record_size as u64);
+            total += count;
+        }
+        assert_eq!(total, 3000);
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_ept_laszip() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = std::env::temp_dir().join("potree-ept-writer-laszip-test");
+        let _ = fs::remove_dir_all(&dir);
+        let options = EptOptions {
+            data_type: EptDataType::Laszip,
+        };
+        write_ept_with_options(potree(), &dir, &options)?;
+
+        let ept: Ept = serde_json::from_slice(&fs::read(dir.join("ept.json"))?)?;
+        assert_eq!(ept.data_type, "laszip");
+        let names: Vec<&str> = ept.schema.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(names, ["X", "Y", "Z", "Red", "Green", "Blue", "range"]);
+
+        let hierarchy: Map<String, Value> =
+            serde_json::from_slice(&fs::read(dir.join("ept-hierarchy/0-0-0-0.json"))?)?;
+        let mut total = 0;
+        for (key, count) in &hierarchy {
+            let count = count.as_u64().unwrap() as usize;
+            let data = fs::read(dir.join(format!("ept-data/{}.laz", key)))?;
+            assert_eq!(&data[0..4], b"LASF");
+            assert_eq!(data[104], 7 | 0x80);
+            assert_eq!(u64::from_le_bytes(data[247..255].try_into()?), count as u64);
+
+            let laszip = HEADER_SIZE as usize;
+            assert_eq!(&data[laszip + 2..laszip + 16], b"laszip encoded");
+            let size = u16::from_le_bytes([data[laszip + 20], data[laszip + 21]]) as usize;
+            let start = laszip + VLR_HEADER_SIZE;
+            let laz_vlr = LazVlr::from_buffer(&data[start..start + size])?;
+            let record_length = laz_vlr.items_size() as usize;
+            assert_eq!(record_length, 36 + 4);
+
+            let offset_to_points = u32::from_le_bytes(data[96..100].try_into()?) as u64;
+            let mut source = Cursor::new(data.as_slice());
+            source.set_position(offset_to_points);
+            let mut records = vec![0; count * record_length];
+            LasZipDecompressor::new(source, laz_vlr)?.decompress_many(&mut records)?;
+            for record in records.chunks(record_length) {
+                assert_eq!(record[30..36], [255, 255, 0, 0, 1, 1]);
+            }
+            total += count;
+        }
+        assert_eq!(total, 3000);
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,444 @@
+use std::io::{Error, ErrorKind, Write};
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::las::{wkt_vlr, write_vlr, LasHeader, LasLayout, HEADER_SIZE, VLR_HEADER_SIZE};
+use crate::model::attributes::{Attribute, AttributeType, Attributes};
+use crate::model::node::Node;
+use crate::model::point::Point;
+use crate::model::point_cloud::PointCloud;
+use crate::potree::Potree;
+
+/// Position resolution of points exported without a known scale.
+const DEFAULT_SCALE: f64 = 0.001;
+
+/// Points handed to the file writers, borrowed from an octree or from a point cloud such
+/// as the result of a dataset query.
+pub struct ExportPoints<'a> {
+    pub points: Vec<&'a Point>,
+    pub attributes: &'a Attributes,
+    /// Resolution positions are stored with in formats using integer coordinates.
+    pub scale: f64,
+    pub crs: Option<&'a str>,
+}
+
+fn collect_points<'a>(node: &'a Node, points: &mut Vec<&'a Point>) {
+    points.extend(node.points());
+    for child in node.children.iter().flatten() {
+        collect_points(child, points);
+    }
+}
+
+impl<'a> From<&'a Potree> for ExportPoints<'a> {
+    fn from(potree: &'a Potree) -> ExportPoints<'a> {
+        let mut points = Vec::with_capacity(potree.size as usize);
+        collect_points(&potree.root, &mut points);
+        ExportPoints {
+            points,
+            attributes: &potree.attributes,
+            scale: potree.scale,
+            crs: potree.crs.as_deref(),
+        }
+    }
+}
+
+impl<'a> From<&'a PointCloud> for ExportPoints<'a> {
+    fn from(cloud: &'a PointCloud) -> ExportPoints<'a> {
+        ExportPoints {
+            points: cloud.points.iter().collect(),
+            attributes: &cloud.attributes,
+            scale: cloud.scale.unwrap_or(DEFAULT_SCALE),
+            crs: cloud.crs.as_deref(),
+        }
+    }
+}
+
+/// Names of the columns an attribute is written to, one per element: colors as `red`,
+/// `green`, `blue` and `alpha`, normals as `nx`, `ny` and `nz` and other multi-element
+/// attributes as `name_0`, `name_1`, ... Characters other than letters, digits and
+/// underscores are replaced by underscores.
+fn column_names(attribute: &Attribute) -> Vec<String> {
+    let elements = attribute.num_elements as usize;
+    let named: &[&str] = match attribute.name.to_lowercase().as_str() {
+        "rgb" | "rgba" | "color" if (3..=4).contains(&elements) => {
+            &["red", "green", "blue", "alpha"]
+        }
+        "normal" | "normals" if elements == 3 => &["nx", "ny", "nz"],
+        _ => &[],
+    };
+    if !named.is_empty() {
+        return named[..elements]
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+    }
+    let name: String = attribute
+        .name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if elements == 1 {
+        vec![name]
+    } else {
+        (0..elements)
+            .map(|element| format!("{}_{}", name, element))
+            .collect()
+    }
+}
+
+/// Every element of every attribute with its offset in the point attributes.
+fn elements(attributes: &Attributes) -> impl Iterator<Item = (&Attribute, usize)> {
+    attributes.list.iter().flat_map(move |attribute| {
+        let offset = attributes.get_offset(&attribute.name) as usize;
+        (0..attribute.num_elements as usize).map(move |element| {
+            (
+                attribute,
+                offset + element * attribute.element_size as usize,
+            )
+        })
+    })
+}
+
+/// Writes the points as a LAS 1.4 file of point format 6, 7 or 8, with attributes
+/// without a LAS field stored as extra bytes. The CRS is stored in the OGC WKT VLR as
+/// described for [`wkt_vlr`].
+pub fn write_las<'a, W: Write>(
+    points: impl Into<ExportPoints<'a>>,
+    out: &mut W,
+) -> Result<(), Error> {
+    let points = points.into();
+    let layout = LasLayout::new(points.attributes);
+
+    let mut min = [f64::INFINITY; 3];
+    let mut max = [f64::NEG_INFINITY; 3];
+    for point in &points.points {
+        for (axis, value) in point.position.to_array().into_iter().enumerate() {
+            min[axis] = min[axis].min(value);
+            max[axis] = max[axis].max(value);
+        }
+    }
+    if points.points.is_empty() {
+        (min, max) = ([0.0; 3], [0.0; 3]);
+    }
+
+    let mut records = Vec::with_capacity(points.points.len() * layout.record_length as usize);
+    let mut points_by_return = [0u64; 15];
+    for point in &points.points {
+        let return_number = layout.write_point(point, points.scale, min, &mut records);
+        if (1..=15).contains(&return_number) {
+            points_by_return[return_number as usize - 1] += 1;
+        }
+    }
+
+    let mut vlrs = Vec::new();
+    if let Some(wkt) = wkt_vlr(points.crs) {
+        vlrs.push(("LASF_Projection", 2112, "OGC WKT", wkt));
+    }
+    if let Some(extra_bytes) = layout.extra_bytes_vlr() {
+        vlrs.push(("LASF_Spec", 4, "Extra bytes", extra_bytes));
+    }
+    let offset_to_points = HEADER_SIZE as usize
+        + vlrs
+            .iter()
+            .map(|(_, _, _, payload)| VLR_HEADER_SIZE + payload.len())
+            .sum::<usize>();
+
+    let header = LasHeader {
+        point_format: layout.point_format,
+        record_length: layout.record_length,
+        offset_to_points: offset_to_points as u32,
+        vlr_count: vlrs.len() as u32,
+        evlr_start: 0,
+        evlr_count: 0,
+        point_count: points.points.len() as u64,
+        points_by_return,
+        scale: points.scale,
+        offset: min,
+        min,
+        max,
+    };
+    header.write(out)?;
+    for (user_id, record_id, description, payload) in &vlrs {
+        write_vlr(out, user_id, *record_id, description, payload)?;
+    }
+    out.write_all(&records)
+}
+
+fn ply_type(r#type: AttributeType) -> &'static str {
+    match r#type {
+        AttributeType::INT8 => "char",
+        AttributeType::UINT8 => "uchar",
+        AttributeType::INT16 => "short",
+        AttributeType::UINT16 => "ushort",
+        AttributeType::INT32 => "int",
+        AttributeType::UINT32 => "uint",
+        AttributeType::FLOAT => "float",
+        // PLY has no 64 bit integers.
+        _ => "double",
+    }
+}
+
+/// Writes the points as a binary little endian PLY file with double positions. Attribute
+/// types are kept, except for 64 bit integers which PLY lacks and are written as doubles.
+pub fn write_ply<'a, W: Write>(
+    points: impl Into<ExportPoints<'a>>,
+    out: &mut W,
+) -> Result<(), Error> {
+    let points = points.into();
+    let mut header = String::from("ply\nformat binary_little_endian 1.0\n");
+    if let Some(crs) = points.crs.filter(|crs| !crs.contains('\n')) {
+        header += &format!("comment crs {}\n", crs);
+    }
+    header += &format!("element vertex {}\n", points.points.len());
+    for axis in ["x", "y", "z"] {
+        header += &format!("property double {}\n", axis);
+    }
+    for attribute in &points.attributes.list {
+        for name in column_names(attribute) {
+            header += &format!("property {} {}\n", ply_type(attribute.r#type), name);
+        }
+    }
+    header += "end_header\n";
+    out.write_all(header.as_bytes())?;
+
+    let elements: Vec<_> = elements(points.attributes).collect();
+    let mut buf = Vec::new();
+    for point in &points.points {
+        for value in point.position.to_array() {
+            buf.extend_from_slice(&value.to_le_bytes());
+        }
+        for (attribute, offset) in &elements {
+            let bytes = &point.attributes[*offset..*offset + attribute.element_size as usize];
+            match attribute.r#type {
+                AttributeType::INT64 | AttributeType::UINT64 => {
+                    AttributeType::DOUBLE.write_f64(attribute.r#type.read_f64(bytes), &mut buf)
+                }
+                _ => buf.extend_from_slice(bytes),
+            }
+        }
+    }
+    out.write_all(&buf)
+}
+
+/// Formats a value without losing precision: floats in their shortest form and 64 bit
+/// integers exactly.
+fn format_value(r#type: AttributeType, bytes: &[u8]) -> String {
+    match r#type {
+        AttributeType::FLOAT => LittleEndian::read_f32(bytes).to_string(),
+        AttributeType::INT64 => LittleEndian::read_i64(bytes).to_string(),
+        AttributeType::UINT64 => LittleEndian::read_u64(bytes).to_string(),
+        _ => r#type.read_f64(bytes).to_string(),
+    }
+}
+
+/// Writes the points as comma separated values with a header of `x`, `y`, `z` and a
+/// column per attribute element.
+pub fn write_csv<'a, W: Write>(
+    points: impl Into<ExportPoints<'a>>,
+    out: &mut W,
+) -> Result<(), Error> {
+    let points = points.into();
+    let mut writer = csv::Writer::from_writer(out);
+
+    let mut header = vec!["x".to_string(), "y".to_string(), "z".to_string()];
+    for attribute in &points.attributes.list {
+        header.extend(column_names(attribute));
+    }
+    writer.write_record(&header)?;
+
+    let elements: Vec<_> = elements(points.attributes).collect();
+    let mut record = Vec::with_capacity(header.len());
+    for point in &points.points {
+        record.clear();
+        record.extend(point.position.to_array().map(|value| value.to_string()));
+        for (attribute, offset) in &elements {
+            let bytes = &point.attributes[*offset..*offset + attribute.element_size as usize];
+            record.push(format_value(attribute.r#type, bytes));
+        }
+        writer.write_record(&record)?;
+    }
+    writer.flush()
+}
+
+fn pcd_type(r#type: AttributeType) -> char {
+    match r#type {
+        AttributeType::INT8
+        | AttributeType::INT16
+        | AttributeType::INT32
+        | AttributeType::INT64 => 'I',
+        AttributeType::FLOAT | AttributeType::DOUBLE => 'F',
+        _ => 'U',
+    }
+}
+
+/// Writes the points as a binary PCD v0.7 file with double positions. Every attribute
+/// becomes a field of its type with a count of its elements.
+pub fn write_pcd<'a, W: Write>(
+    points: impl Into<ExportPoints<'a>>,
+    out: &mut W,
+) -> Result<(), Error> {
+    let points = points.into();
+    if points
+        .attributes
+        .list
+        .iter()
+        .any(|attribute| attribute.r#type == AttributeType::UNDEFINED)
+    {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "PCD fields need a defined type",
+        ));
+    }
+
+    let mut fields = vec!["x".to_string(), "y".to_string(), "z".to_string()];
+    let mut sizes = vec![8; 3];
+    let mut types = vec!['F'; 3];
+    let mut counts = vec![1; 3];
+    for attribute in &points.attributes.list {
+        let name: String = attribute
+            .name
+            .chars()
+            .map(|c| if c.is_whitespace() { '_' } else { c })
+            .collect();
+        fields.push(name);
+        sizes.push(attribute.element_size);
+        types.push(pcd_type(attribute.r#type));
+        counts.push(attribute.num_elements);
+    }
+    let join = |values: Vec<String>| values.join(" ");
+    let header = format!(
+        "# .PCD v0.7 - Point Cloud Data file format\nVERSION 0.7\nFIELDS {}\nSIZE {}\nTYPE {}\nCOUNT {}\nWIDTH {}\nHEIGHT 1\nVIEWPOINT 0 0 0 1 0 0 0\nPOINTS {}\nDATA binary\n",
+        fields.join(" "),
+        join(sizes.iter().map(|size| size.to_string()).collect()),
+        join(types.iter().map(|c| c.to_string()).collect()),
+        join(counts.iter().map(|count| count.to_string()).collect()),
+        points.points.len(),
+        points.points.len(),
+    );
+    out.write_all(header.as_bytes())?;
+
+    let mut buf = Vec::with_capacity(points.points.len() * (24 + points.attributes.bytes as usize));
+    for point in &points.points {
+        for value in point.position.to_array() {
+            buf.extend_from_slice(&value.to_le_bytes());
+        }
+        buf.extend_from_slice(&point.attributes);
+    }
+    out.write_all(&buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::csv_reader::{read_csv_with_options, CsvOptions};
+    use crate::model::vector3::Vector3;
+    use crate::pcd_reader::read_pcd;
+
+    fn cloud() -> PointCloud {
+        let points = (0..100)
+            .map(|i| {
+                let position = Vector3 {
+                    x: 1000.0 + i as f64 * 0.5,
+                    y: (i % 10) as f64,
+                    z: 2.25,
+                };
+                let mut attributes = Vec::new();
+                AttributeType::FLOAT.write_f64(i as f64 / 4.0, &mut attributes);
+                for channel in [255.0, 128.0, i as f64] {
+                    AttributeType::UINT8.write_f64(channel, &mut attributes);
+                }
+                AttributeType::UINT8.write_f64((i % 3) as f64, &mut attributes);
+                AttributeType::INT64.write_f64(-(i as f64), &mut attributes);
+                Point::with_attributes(position, attributes)
+            })
+            .collect::<Vec<_>>();
+        let mut attributes = Attributes::from_attributes(vec![
+            Attribute::new("intensity", AttributeType::FLOAT, 1),
+            Attribute::new("rgb", AttributeType::UINT8, 3),
+            Attribute::new("classification", AttributeType::UINT8, 1),
+            Attribute::new("frame id", AttributeType::INT64, 1),
+        ]);
+        for point in &points {
+            attributes.update_ranges(&point.attributes);
+        }
+        PointCloud::new(points, attributes)
+    }
+
+    #[test]
+    fn test_write_las() {
+        let cloud = cloud();
+        let mut buf = Vec::new();
+        write_las(&cloud, &mut buf).unwrap();
+
+        assert_eq!(&buf[0..4], b"LASF");
+        assert_eq!(buf[104], 7);
+        let record_length = u16::from_le_bytes([buf[105], buf[106]]) as usize;
+        // Format 7 with the int64 frame id as extra bytes.
+        assert_eq!(record_length, 36 + 8);
+        assert_eq!(LittleEndian::read_u64(&buf[247..255]), 100);
+        let offset_to_points = LittleEndian::read_u32(&buf[96..100]) as usize;
+        assert_eq!(buf.len(), offset_to_points + 100 * record_length);
+
+        let last = &buf[offset_to_points + 99 * record_length..];
+        let x = LittleEndian::read_i32(&last[0..4]) as f64 * DEFAULT_SCALE + 1000.0;
+        assert!((x - 1049.5).abs() < 1e-9);
+        assert_eq!(LittleEndian::read_u16(&last[12..14]), 25);
+        assert_eq!(last[16], 0);
+        assert_eq!(LittleEndian::read_u16(&last[30..32]), 255 * 257);
+        assert_eq!(LittleEndian::read_i64(&last[36..44]), -99);
+    }
+
+    #[test]
+    fn test_write_ply() {
+        let potree = cloud().into_potree();
+        let mut buf = Vec::new();
+        write_ply(&potree, &mut buf).unwrap();
+
+        let end = b"end_header\n";
+        let data_start = buf.windows(end.len()).position(|w| w == end).unwrap() + end.len();
+        let header = std::str::from_utf8(&buf[..data_start]).unwrap();
+        assert!(header.contains("element vertex 100\n"));
+        assert!(header.contains("property uchar red\n"));
+        assert!(header.contains("property double frame_id\n"));
+        assert_eq!(buf.len() - data_start, 100 * (24 + 4 + 3 + 1 + 8));
+    }
+
+    #[test]
+    fn test_write_csv() {
+        let cloud = cloud();
+        let mut buf = Vec::new();
+        write_csv(&cloud, &mut buf).unwrap();
+
+        let text = std::str::from_utf8(&buf).unwrap();
+        let mut lines = text.lines();
+        assert_eq!(
+            lines.next().unwrap(),
+            "x,y,z,intensity,red,green,blue,classification,frame_id"
+        );
+        assert_eq!(lines.nth(1).unwrap(), "1000.5,1,2.25,0.25,255,128,1,1,-1");
+
+        let read = read_csv_with_options(&buf, &CsvOptions::default()).unwrap();
+        assert_eq!(read.len(), 100);
+        assert_eq!(read.attributes.get_offset("classification"), 4);
+        assert_eq!(read.points[5].attributes[4], 2);
+    }
+
+    #[test]
+    fn test_write_pcd() {
+        let cloud = cloud();
+        let mut buf = Vec::new();
+        write_pcd(&cloud, &mut buf).unwrap();
+
+        let read = read_pcd(&buf).unwrap();
+        assert_eq!(read.len(), 100);
+        let names: Vec<&str> = read
+            .attributes
+            .list
+            .iter()
+            .map(|a| a.name.as_str())
+            .collect();
+        assert_eq!(names, ["intensity", "rgb", "classification", "frame_id"]);
+        assert_eq!(read.points[7].position.x, 1003.5);
+        assert_eq!(read.points[7].attributes, cloud.points[7].attributes);
+    }
+}
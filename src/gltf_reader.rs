@@ -0,0 +1,289 @@
+use core::fmt;
+
+use base64::Engine;
+use gltf::buffer::Source;
+use gltf::mesh::Mode;
+use gltf::{Gltf, Node};
+
+use crate::mesh::{Mesh, MeshOptions};
+use crate::model::point_cloud::PointCloud;
+use crate::model::vector3::Vector3;
+
+type Matrix = [[f64; 4]; 4];
+type NormalMatrix = [[f64; 3]; 3];
+
+const IDENTITY: Matrix = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+#[derive(Debug)]
+pub enum GltfReadError {
+    Gltf(gltf::Error),
+    InvalidBuffer { index: usize, msg: String },
+    NoPoints,
+}
+
+impl fmt::Display for GltfReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GltfReadError::Gltf(error) => write!(f, "Could not read glTF: {}", error),
+            GltfReadError::InvalidBuffer { index, msg } => {
+                write!(f, "Buffer {}: {}", index, msg)
+            }
+            GltfReadError::NoPoints => write!(f, "No POINTS or TRIANGLES primitives in glTF"),
+        }
+    }
+}
+
+impl std::error::Error for GltfReadError {}
+
+impl From<gltf::Error> for GltfReadError {
+    fn from(error: gltf::Error) -> Self {
+        GltfReadError::Gltf(error)
+    }
+}
+
+/// Column-major product `a * b`, as glTF stores its matrices.
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut result = [[0.0; 4]; 4];
+    for (column, result_column) in result.iter_mut().enumerate() {
+        for (row, value) in result_column.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[k][row] * b[column][k]).sum();
+        }
+    }
+    result
+}
+
+fn transform_point(m: &Matrix, [x, y, z]: [f32; 3]) -> Vector3 {
+    let [x, y, z] = [x as f64, y as f64, z as f64];
+    let [x, y, z] = [0, 1, 2].map(|row| m[0][row] * x + m[1][row] * y + m[2][row] * z + m[3][row]);
+    Vector3 { x, y, z }
+}
+
+/// Inverse transpose of the upper 3x3 of `m`, column-major like `m`, which keeps normals
+/// perpendicular to their surface under non-uniform scales. It is built from the cofactors,
+/// whose scaling by the determinant is undone when the normals are normalized.
+fn normal_matrix(m: &Matrix) -> NormalMatrix {
+    let [a, b, c] = [0, 1, 2].map(|column| [m[column][0], m[column][1], m[column][2]]);
+    let cross = |u: [f64; 3], v: [f64; 3]| {
+        [
+            u[1] * v[2] - u[2] * v[1],
+            u[2] * v[0] - u[0] * v[2],
+            u[0] * v[1] - u[1] * v[0],
+        ]
+    };
+    let cofactors = [cross(b, c), cross(c, a), cross(a, b)];
+    let determinant: f64 = (0..3).map(|i| a[i] * cofactors[0][i]).sum();
+    cofactors.map(|column| column.map(|v| v * determinant.signum()))
+}
+
+fn transform_normal(n: &NormalMatrix, [x, y, z]: [f32; 3]) -> [f64; 3] {
+    let [x, y, z] = [x as f64, y as f64, z as f64];
+    let normal = [0, 1, 2].map(|row| n[0][row] * x + n[1][row] * y + n[2][row] * z);
+    let length = normal.iter().map(|v| v * v).sum::<f64>().sqrt();
+    if length > 0.0 {
+        normal.map(|v| v / length)
+    } else {
+        normal
+    }
+}
+
+/// Loads the binary chunk of a GLB and buffers embedded as base64 data URIs.
+fn load_buffers(gltf: &Gltf) -> Result<Vec<Vec<u8>>, GltfReadError> {
+    gltf.document
+        .buffers()
+        .map(|buffer| {
+            let invalid = |msg: &str| GltfReadError::InvalidBuffer {
+                index: buffer.index(),
+                msg: msg.to_string(),
+            };
+            let data = match buffer.source() {
+                Source::Bin => gltf
+                    .blob
+                    .clone()
+                    .ok_or_else(|| invalid("missing GLB binary chunk"))?,
+                Source::Uri(uri) => {
+                    let encoded = uri
+                        .strip_prefix("data:")
+                        .and_then(|uri| uri.split_once(";base64,"))
+                        .map(|(_, encoded)| encoded)
+                        .ok_or_else(|| invalid("external buffers are not supported"))?;
+                    base64::engine::general_purpose::STANDARD
+                        .decode(encoded)
+                        .map_err(|error| invalid(&error.to_string()))?
+                }
+            };
+            if data.len() < buffer.length() {
+                return Err(invalid("buffer is truncated"));
+            }
+            Ok(data)
+        })
+        .collect()
+}
+
+fn read_node(node: &Node, parent: &Matrix, buffers: &[Vec<u8>], mesh: &mut Mesh) {
+    let local = node
+        .transform()
+        .matrix()
+        .map(|column| column.map(f64::from));
+    let matrix = multiply(parent, &local);
+    let normal_matrix = normal_matrix(&matrix);
+
+    if let Some(node_mesh) = node.mesh() {
+        for primitive in node_mesh.primitives() {
+            let mode = primitive.mode();
+            if mode != Mode::Points && mode != Mode::Triangles {
+                continue;
+            }
+            let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+            let positions = match reader.read_positions() {
+                Some(positions) => positions,
+                None => continue,
+            };
+            let mut colors = reader.read_colors(0).map(|colors| colors.into_rgb_f32());
+            let mut normals = reader.read_normals();
+
+            let first = mesh.len();
+            for position in positions {
+                let color = colors
+                    .as_mut()
+                    .and_then(Iterator::next)
+                    .map(|color| color.map(|channel| (channel as f64 * 65535.0).round()));
+                let normal = normals
+                    .as_mut()
+                    .and_then(Iterator::next)
+                    .map(|normal| transform_normal(&normal_matrix, normal));
+                mesh.push_vertex(transform_point(&matrix, position), color, normal);
+            }
+            let count = mesh.len() - first;
+
+            if mode == Mode::Triangles {
+                let indices: Vec<usize> = match reader.read_indices() {
+                    Some(indices) => indices.into_u32().map(|i| i as usize).collect(),
+                    None => (0..count).collect(),
+                };
+                for triangle in indices.chunks_exact(3) {
+                    if triangle.iter().all(|&i| i < count) {
+                        mesh.triangles.push([
+                            first + triangle[0],
+                            first + triangle[1],
+                            first + triangle[2],
+                        ]);
+                    }
+                }
+            }
+        }
+    }
+
+    for child in node.children() {
+        read_node(&child, &matrix, buffers, mesh);
+    }
+}
+
+/// Reads the vertices of POINTS and TRIANGLES primitives in a glTF or GLB file as points.
+///
+/// `POSITION`, `COLOR_0` and `NORMAL` are read with the node transforms of the default
+/// scene applied, or of the first scene if none is set. Buffers must be embedded, either
+/// in the GLB binary chunk or as data URIs. With a sample density in `options` the
+/// triangles are sampled as well.
+pub fn read_gltf(buf: &[u8], options: &MeshOptions) -> Result<PointCloud, GltfReadError> {
+    let gltf = Gltf::from_slice(buf)?;
+    let buffers = load_buffers(&gltf)?;
+
+    let mut mesh = Mesh::default();
+    let scene = gltf
+        .document
+        .default_scene()
+        .or_else(|| gltf.document.scenes().next());
+    if let Some(scene) = scene {
+        for node in scene.nodes() {
+            read_node(&node, &IDENTITY, &buffers, &mut mesh);
+        }
+    }
+
+    if mesh.is_empty() {
+        return Err(GltfReadError::NoPoints);
+    }
+    Ok(mesh.into_point_cloud(options))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A triangle translated by (10, 0, 0) under a parent scaled by 2, with red vertex colors.
+    fn triangle_gltf() -> Vec<u8> {
+        let mut data = Vec::new();
+        for value in [0.0_f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        for _ in 0..3 {
+            data.extend_from_slice(&[255, 0, 0, 255]);
+        }
+        let uri = base64::engine::general_purpose::STANDARD.encode(&data);
+        let json = format!(
+            r#"{{
+                "asset": {{"version": "2.0"}},
+                "scene": 0,
+                "scenes": [{{"nodes": [0]}}],
+                "nodes": [
+                    {{"scale": [2, 2, 2], "children": [1]}},
+                    {{"translation": [10, 0, 0], "mesh": 0}}
+                ],
+                "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0, "COLOR_0": 1}}}}]}}],
+                "buffers": [{{"byteLength": 48, "uri": "data:application/octet-stream;base64,{}"}}],
+                "bufferViews": [
+                    {{"buffer": 0, "byteOffset": 0, "byteLength": 36}},
+                    {{"buffer": 0, "byteOffset": 36, "byteLength": 12}}
+                ],
+                "accessors": [
+                    {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                      "min": [0, 0, 0], "max": [1, 1, 0]}},
+                    {{"bufferView": 1, "componentType": 5121, "normalized": true, "count": 3,
+                      "type": "VEC4"}}
+                ]
+            }}"#,
+            uri
+        );
+        json.into_bytes()
+    }
+
+    #[test]
+    fn test_read_gltf() {
+        let cloud = read_gltf(&triangle_gltf(), &MeshOptions::default()).unwrap();
+        assert_eq!(cloud.len(), 3);
+        assert_eq!(cloud.points[1].position.to_array(), [22.0, 0.0, 0.0]);
+        assert_eq!(cloud.points[2].position.to_array(), [20.0, 2.0, 0.0]);
+        assert_eq!(cloud.points[0].attributes, [255, 255, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_transform_normal() {
+        let mut scale = IDENTITY;
+        scale[0][0] = 2.0;
+        let normal = transform_normal(&normal_matrix(&scale), [1.0, 1.0, 0.0]);
+        let expected = [1.0 / 5f64.sqrt(), 2.0 / 5f64.sqrt(), 0.0];
+        assert!((0..3).all(|i| (normal[i] - expected[i]).abs() < 1e-9));
+
+        let mut mirror = IDENTITY;
+        mirror[0][0] = -1.0;
+        assert_eq!(
+            transform_normal(&normal_matrix(&mirror), [1.0, 0.0, 0.0]),
+            [-1.0, 0.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn test_sample_gltf() {
+        let options = MeshOptions {
+            sample_density: Some(25.0),
+            seed: 3,
+        };
+        // The transformed triangle has an area of 2.
+        let cloud = read_gltf(&triangle_gltf(), &options).unwrap();
+        assert_eq!(cloud.len(), 53);
+    }
+}
@@ -0,0 +1,455 @@
+use core::fmt;
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::model::attributes::{Attribute, AttributeType, Attributes};
+use crate::model::point::Point;
+use crate::model::point_cloud::PointCloud;
+use crate::npy::{NpyArray, NpyError};
+
+/// Rotation as a unit quaternion.
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct Quaternion {
+    pub w: f64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+/// An oriented 3D box assigning `class_id` to the points inside it.
+#[derive(Deserialize, Clone, Debug)]
+pub struct BoxLabel {
+    pub center: [f64; 3],
+    /// Full extent along the box x (length), y (width) and z (height) axes.
+    pub size: [f64; 3],
+    /// Rotation around the z axis in radians, used when `rotation` is not set.
+    #[serde(default)]
+    pub yaw: f64,
+    #[serde(default)]
+    pub rotation: Option<Quaternion>,
+    #[serde(alias = "class")]
+    pub class_id: u8,
+}
+
+impl BoxLabel {
+    /// Rows of the world to box rotation, the transpose of the box orientation.
+    fn inverse_rotation(&self) -> [[f64; 3]; 3] {
+        let q = self.rotation.unwrap_or(Quaternion {
+            w: (self.yaw / 2.0).cos(),
+            x: 0.0,
+            y: 0.0,
+            z: (self.yaw / 2.0).sin(),
+        });
+        let norm = (q.w * q.w + q.x * q.x + q.y * q.y + q.z * q.z).sqrt();
+        let (w, x, y, z) = (q.w / norm, q.x / norm, q.y / norm, q.z / norm);
+        [
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y + w * z),
+                2.0 * (x * z - w * y),
+            ],
+            [
+                2.0 * (x * y - w * z),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z + w * x),
+            ],
+            [
+                2.0 * (x * z + w * y),
+                2.0 * (y * z - w * x),
+                1.0 - 2.0 * (x * x + y * y),
+            ],
+        ]
+    }
+}
+
+/// Boxes and the names of their classes.
+#[derive(Deserialize, Default, Debug)]
+pub struct BoxLabels {
+    #[serde(default)]
+    pub classes: BTreeMap<u8, String>,
+    pub boxes: Vec<BoxLabel>,
+}
+
+impl BoxLabels {
+    /// Stores the class names in metadata extras as `classes`, keyed by class id.
+    pub fn insert_classes(&self, extras: &mut Map<String, Value>) {
+        if self.classes.is_empty() {
+            return;
+        }
+        let classes: Map<String, Value> = self
+            .classes
+            .iter()
+            .map(|(id, name)| (id.to_string(), Value::String(name.clone())))
+            .collect();
+        extras.insert("classes".to_string(), Value::Object(classes));
+    }
+}
+
+#[derive(Debug)]
+pub enum LabelError {
+    Json(serde_json::Error),
+    Npy(NpyError),
+    InvalidAttribute { name: String },
+    InvalidLabels { msg: String },
+    CountMismatch { points: usize, labels: usize },
+    ValueOutOfRange { name: String, value: u32 },
+}
+
+impl fmt::Display for LabelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LabelError::Json(error) => write!(f, "Could not parse labels: {}", error),
+            LabelError::Npy(error) => write!(f, "Could not read labels: {}", error),
+            LabelError::InvalidAttribute { name } => {
+                write!(f, "Attribute '{}' must be a single value", name)
+            }
+            LabelError::InvalidLabels { msg } => write!(f, "Invalid label file: {}", msg),
+            LabelError::CountMismatch { points, labels } => write!(
+                f,
+                "Label file has {} labels but the point cloud has {} points",
+                labels, points
+            ),
+            LabelError::ValueOutOfRange { name, value } => {
+                write!(
+                    f,
+                    "Label {} does not fit the type of attribute '{}'",
+                    value, name
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for LabelError {}
+
+impl From<serde_json::Error> for LabelError {
+    fn from(error: serde_json::Error) -> Self {
+        LabelError::Json(error)
+    }
+}
+
+impl From<NpyError> for LabelError {
+    fn from(error: NpyError) -> Self {
+        LabelError::Npy(error)
+    }
+}
+
+/// Reads box labels from JSON, either `{"classes": {"1": "car"}, "boxes": [...]}` or a
+/// bare array of boxes.
+///
+/// A box is `{"center": [x, y, z], "size": [l, w, h], "yaw": 0.5, "class_id": 1}`, with
+/// `"rotation": {"w": 1, "x": 0, "y": 0, "z": 0}` instead of `yaw` for full 3D rotations.
+pub fn read_box_labels(buf: &[u8]) -> Result<BoxLabels, LabelError> {
+    let json: Value = serde_json::from_slice(buf)?;
+    if json.is_array() {
+        return Ok(BoxLabels {
+            classes: BTreeMap::new(),
+            boxes: serde_json::from_value(json)?,
+        });
+    }
+    Ok(serde_json::from_value(json)?)
+}
+
+/// Returns the offset and type of a single value attribute, appending it if missing.
+///
+/// An appended attribute starts out as 0 for every point.
+pub(crate) fn ensure_attribute(
+    attributes: &mut Attributes,
+    points: &mut [Point],
+    name: &str,
+    r#type: AttributeType,
+) -> Result<(usize, AttributeType), LabelError> {
+    if let Some(attribute) = attributes.get(name) {
+        if attribute.num_elements != 1 {
+            return Err(LabelError::InvalidAttribute {
+                name: name.to_string(),
+            });
+        }
+        return Ok((attributes.get_offset(name) as usize, attribute.r#type));
+    }
+    let offset = attributes.bytes as usize;
+    for point in points.iter_mut() {
+        point
+            .attributes
+            .resize(point.attributes.len() + r#type.size() as usize, 0);
+    }
+    attributes.list.push(Attribute::new(name, r#type, 1));
+    attributes.bytes += r#type.size();
+    Ok((offset, r#type))
+}
+
+/// Overwrites a single value attribute of a point at `offset`.
+pub(crate) fn set_value(point: &mut Point, offset: usize, r#type: AttributeType, value: f64) {
+    let mut bytes = Vec::with_capacity(8);
+    r#type.write_f64(value, &mut bytes);
+    point.attributes[offset..offset + bytes.len()].copy_from_slice(&bytes);
+}
+
+/// Assigns the class of every box to the points inside it, before they are indexed.
+///
+/// Points are written to a uint8 `classification` attribute, which is added with 0 for
+/// unlabeled points if the input has none. Where boxes overlap the later box wins.
+pub fn label_boxes(cloud: &mut PointCloud, labels: &BoxLabels) -> Result<(), LabelError> {
+    let (offset, r#type) = ensure_attribute(
+        &mut cloud.attributes,
+        &mut cloud.points,
+        "classification",
+        AttributeType::UINT8,
+    )?;
+
+    let boxes: Vec<(&BoxLabel, [[f64; 3]; 3])> = labels
+        .boxes
+        .iter()
+        .map(|label| (label, label.inverse_rotation()))
+        .collect();
+    for point in &mut cloud.points {
+        let class = boxes.iter().rev().find(|(label, rotation)| {
+            let p = &point.position;
+            let d = [
+                p.x - label.center[0],
+                p.y - label.center[1],
+                p.z - label.center[2],
+            ];
+            rotation.iter().zip(label.size).all(|(row, size)| {
+                let local = row[0] * d[0] + row[1] * d[1] + row[2] * d[2];
+                local.abs() <= size / 2.0
+            })
+        });
+        if let Some((label, _)) = class {
+            set_value(point, offset, r#type, label.class_id as f64);
+        }
+    }
+    Ok(())
+}
+
+/// Per-point labels from a sidecar file, in the order of the points they belong to.
+pub struct PointLabels {
+    pub classes: Vec<u32>,
+    pub instances: Option<Vec<u32>>,
+}
+
+/// Reads a SemanticKITTI `.label` file of little endian uint32 values holding the semantic
+/// class in the lower and the instance id in the upper 16 bits.
+pub fn read_semantic_kitti_labels(buf: &[u8]) -> Result<PointLabels, LabelError> {
+    if !buf.len().is_multiple_of(4) {
+        return Err(LabelError::InvalidLabels {
+            msg: format!("{} bytes is not a multiple of 4", buf.len()),
+        });
+    }
+    let values: Vec<u32> = buf
+        .chunks_exact(4)
+        .map(|value| u32::from_le_bytes([value[0], value[1], value[2], value[3]]))
+        .collect();
+
+    Ok(PointLabels {
+        classes: values.iter().map(|value| value & 0xffff).collect(),
+        instances: Some(values.iter().map(|value| value >> 16).collect()),
+    })
+}
+
+/// Reads labels from an integer `.npy` array of shape (N,) holding classes or (N, 2)
+/// holding classes and instance ids.
+pub fn read_npy_labels(buf: &[u8]) -> Result<PointLabels, LabelError> {
+    let array = NpyArray::parse(buf)?;
+    if !matches!(array.dtype.kind, b'i' | b'u' | b'b') {
+        return Err(LabelError::InvalidLabels {
+            msg: "labels must be integers".to_string(),
+        });
+    }
+    let column = |column: usize| -> Result<Vec<u32>, LabelError> {
+        (0..array.rows())
+            .map(|row| {
+                let value = array.get(row, column);
+                if value < 0.0 || value > u32::MAX as f64 {
+                    return Err(LabelError::InvalidLabels {
+                        msg: format!("label {} in row {} is out of range", value, row),
+                    });
+                }
+                Ok(value as u32)
+            })
+            .collect()
+    };
+
+    match array.shape.as_slice() {
+        [_] | [_, 1] => Ok(PointLabels {
+            classes: column(0)?,
+            instances: None,
+        }),
+        [_, 2] => Ok(PointLabels {
+            classes: column(0)?,
+            instances: Some(column(1)?),
+        }),
+        shape => Err(LabelError::InvalidLabels {
+            msg: format!("expected shape (N,) or (N, 2), found {:?}", shape),
+        }),
+    }
+}
+
+fn max_value(r#type: AttributeType) -> f64 {
+    match r#type {
+        AttributeType::INT8 => i8::MAX as f64,
+        AttributeType::INT16 => i16::MAX as f64,
+        AttributeType::INT32 => i32::MAX as f64,
+        AttributeType::UINT8 | AttributeType::UNDEFINED => u8::MAX as f64,
+        AttributeType::UINT16 => u16::MAX as f64,
+        _ => u32::MAX as f64,
+    }
+}
+
+/// Writes `values` to a single value attribute, using the narrowest of uint8, uint16 and
+/// uint32 that holds them if the attribute has to be added.
+fn write_labels(cloud: &mut PointCloud, name: &str, values: &[u32]) -> Result<(), LabelError> {
+    let max = values.iter().copied().max().unwrap_or(0);
+    let r#type = if max <= u8::MAX as u32 {
+        AttributeType::UINT8
+    } else if max <= u16::MAX as u32 {
+        AttributeType::UINT16
+    } else {
+        AttributeType::UINT32
+    };
+    let (offset, r#type) =
+        ensure_attribute(&mut cloud.attributes, &mut cloud.points, name, r#type)?;
+    if max as f64 > max_value(r#type) {
+        return Err(LabelError::ValueOutOfRange {
+            name: name.to_string(),
+            value: max,
+        });
+    }
+    for (point, value) in cloud.points.iter_mut().zip(values) {
+        set_value(point, offset, r#type, *value as f64);
+    }
+    Ok(())
+}
+
+/// Joins sidecar labels with the points of any reader by index.
+///
+/// Classes go to the `classification` attribute and instance ids to `instance_id`,
+/// replacing existing values. Both are added if missing.
+pub fn join_labels(cloud: &mut PointCloud, labels: &PointLabels) -> Result<(), LabelError> {
+    let counts = [Some(&labels.classes), labels.instances.as_ref()];
+    for values in counts.into_iter().flatten() {
+        if values.len() != cloud.len() {
+            return Err(LabelError::CountMismatch {
+                points: cloud.len(),
+                labels: values.len(),
+            });
+        }
+    }
+    write_labels(cloud, "classification", &labels.classes)?;
+    if let Some(instances) = &labels.instances {
+        write_labels(cloud, "instance_id", instances)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::FRAC_PI_4;
+
+    use crate::labels::{
+        join_labels, label_boxes, read_box_labels, read_npy_labels, read_semantic_kitti_labels,
+        LabelError,
+    };
+    use crate::model::attributes::{AttributeType, Attributes};
+    use crate::model::point::Point;
+    use crate::model::point_cloud::PointCloud;
+    use crate::model::vector3::Vector3;
+    use crate::npy::tests::npy;
+    use crate::raw_reader::{read_raw, RawLayout};
+    use crate::writer::write_potree_to_buffers;
+
+    #[test]
+    fn test_label_boxes() -> Result<(), LabelError> {
+        let points = vec![
+            Vector3 {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            Vector3 {
+                x: 1.3,
+                y: 0.0,
+                z: 0.0,
+            },
+            Vector3 {
+                x: 0.9,
+                y: 0.9,
+                z: 0.0,
+            },
+            Vector3 {
+                x: 10.0,
+                y: 10.0,
+                z: 1.0,
+            },
+        ];
+        let labels = read_box_labels(
+            format!(
+                r#"{{"classes": {{"1": "car", "2": "pedestrian"}},
+                    "boxes": [{{"center": [0, 0, 0], "size": [4, 0.2, 2], "yaw": {}, "class_id": 1}},
+                              {{"center": [10, 10, 1], "size": [1, 1, 2],
+                                "rotation": {{"w": 1, "x": 0, "y": 0, "z": 0}}, "class": 2}}]}}"#,
+                FRAC_PI_4
+            )
+            .as_bytes(),
+        )?;
+        let mut cloud = PointCloud::new(
+            points.into_iter().map(Point::new).collect(),
+            Attributes::new(),
+        );
+        label_boxes(&mut cloud, &labels)?;
+
+        // The box is rotated onto the diagonal, so (1.3, 0, 0) falls outside.
+        let classes: Vec<u8> = cloud.points.iter().map(|p| p.attributes[0]).collect();
+        assert_eq!(classes, [1, 0, 1, 2]);
+
+        let mut potree = cloud.into_potree();
+        labels.insert_classes(&mut potree.extras);
+        let classification = potree.attributes.get("classification").unwrap();
+        assert_eq!(classification.r#type, AttributeType::UINT8);
+        assert_eq!((classification.min.x, classification.max.x), (0.0, 2.0));
+        let metadata = write_potree_to_buffers(&potree).metadata;
+        assert_eq!(metadata.extras["classes"]["2"], "pedestrian");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_join_semantic_kitti_labels() -> Result<(), Box<dyn std::error::Error>> {
+        let scan: Vec<u8> = (0..3)
+            .flat_map(|i| [i as f32, 0.0, 0.0, 0.5])
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        let mut cloud = read_raw(&scan, &RawLayout::kitti())?;
+        let label_file: Vec<u8> = [10u32, (7 << 16) | 252, 40]
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        join_labels(&mut cloud, &read_semantic_kitti_labels(&label_file)?)?;
+
+        let classification = cloud.attributes.get("classification").unwrap();
+        assert_eq!(classification.r#type, AttributeType::UINT8);
+        // Values of the second point: intensity, classification, instance id.
+        assert_eq!(&cloud.points[1].attributes[4..], [252, 7]);
+        let potree = cloud.into_potree();
+        let instance_id = potree.attributes.get("instance_id").unwrap();
+        assert_eq!((instance_id.min.x, instance_id.max.x), (0.0, 7.0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_join_labels_count_mismatch() -> Result<(), Box<dyn std::error::Error>> {
+        let mut cloud = read_raw(&[0; 24], &RawLayout::xyz_f64())?;
+        let labels = read_npy_labels(&npy("'<i8'", false, "(2,)", &[0; 16]))?;
+        match join_labels(&mut cloud, &labels) {
+            Err(LabelError::CountMismatch { points, labels }) => {
+                assert_eq!((points, labels), (1, 2))
+            }
+            _ => panic!("Expected count mismatch error"),
+        }
+
+        Ok(())
+    }
+}
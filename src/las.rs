@@ -0,0 +1,502 @@
+use std::io::{Error, ErrorKind, Write};
+
+use byteorder::{LittleEndian, WriteBytesExt};
+use laz::laszip::{ChunkTable, ChunkTableEntry};
+use laz::record::{LayeredPointRecordCompressor, RecordCompressor};
+use laz::{LasZipError, LazVlr, LazVlrBuilder};
+
+use crate::model::attributes::{AttributeType, Attributes};
+use crate::model::point::Point;
+use crate::tiles_writer::is_geographic;
+
+pub const HEADER_SIZE: u16 = 375;
+pub const VLR_HEADER_SIZE: usize = 54;
+pub const EVLR_HEADER_SIZE: usize = 60;
+const EXTRA_BYTES_DESCRIPTOR_SIZE: usize = 192;
+/// Global encoding bit declaring the CRS as WKT, required for point formats 6 and above.
+const GLOBAL_ENCODING_WKT: u16 = 1 << 4;
+/// Bit of the point format marking the point records as LAZ compressed.
+pub const LAZ_POINT_FORMAT_BIT: u8 = 0x80;
+
+/// One value of a point attribute a LAS field is written from.
+struct Field {
+    offset: usize,
+    r#type: AttributeType,
+    scale: f64,
+}
+
+impl Field {
+    fn read(&self, attributes: &[u8]) -> f64 {
+        let size = self.r#type.size() as usize;
+        self.r#type
+            .read_f64(&attributes[self.offset..self.offset + size])
+            * self.scale
+    }
+}
+
+/// An attribute without a standard LAS field, stored as extra bytes.
+struct ExtraBytes {
+    name: String,
+    offset: usize,
+    r#type: AttributeType,
+}
+
+impl ExtraBytes {
+    /// The LAS 1.4 extra bytes data type, 1 to 10 for unsigned char to double.
+    fn data_type(&self) -> u8 {
+        match self.r#type {
+            AttributeType::UINT8 => 1,
+            AttributeType::INT8 => 2,
+            AttributeType::UINT16 => 3,
+            AttributeType::INT16 => 4,
+            AttributeType::UINT32 => 5,
+            AttributeType::INT32 => 6,
+            AttributeType::UINT64 => 7,
+            AttributeType::INT64 => 8,
+            AttributeType::FLOAT => 9,
+            _ => 10,
+        }
+    }
+}
+
+/// How the attributes of an octree map onto a LAS 1.4 point record of format 6, 7 or 8.
+///
+/// Attributes named after LAS fields, such as `intensity`, `return number`, `gps-time` or
+/// `rgb`, fill those fields and every other attribute is stored as extra bytes, one
+/// value per element.
+pub struct LasLayout {
+    pub point_format: u8,
+    pub record_length: u16,
+    intensity: Option<Field>,
+    return_number: Option<Field>,
+    number_of_returns: Option<Field>,
+    classification: Option<Field>,
+    user_data: Option<Field>,
+    scan_angle: Option<Field>,
+    point_source_id: Option<Field>,
+    gps_time: Option<Field>,
+    rgb: Option<[Field; 3]>,
+    nir: Option<Field>,
+    extra_bytes: Vec<ExtraBytes>,
+}
+
+impl LasLayout {
+    pub fn new(attributes: &Attributes) -> LasLayout {
+        let mut layout = LasLayout {
+            point_format: 6,
+            record_length: 30,
+            intensity: None,
+            return_number: None,
+            number_of_returns: None,
+            classification: None,
+            user_data: None,
+            scan_angle: None,
+            point_source_id: None,
+            gps_time: None,
+            rgb: None,
+            nir: None,
+            extra_bytes: Vec::new(),
+        };
+
+        for attribute in &attributes.list {
+            let offset = attributes.get_offset(&attribute.name) as usize;
+            let field = Field {
+                offset,
+                r#type: attribute.r#type,
+                scale: 1.0,
+            };
+            let name = attribute.name.to_lowercase().replace(['_', '-'], " ");
+            let scalar = attribute.num_elements == 1;
+            let slot = match name.as_str() {
+                "intensity" if scalar => &mut layout.intensity,
+                "return number" if scalar => &mut layout.return_number,
+                "number of returns" if scalar => &mut layout.number_of_returns,
+                "classification" if scalar => &mut layout.classification,
+                "user data" if scalar => &mut layout.user_data,
+                "scan angle" | "scan angle rank" if scalar => &mut layout.scan_angle,
+                "point source id" if scalar => &mut layout.point_source_id,
+                "gps time" | "timestamp" if scalar => &mut layout.gps_time,
+                "nir" | "near infrared" if scalar => &mut layout.nir,
+                "rgb" | "rgba" | "color" if attribute.num_elements >= 3 && layout.rgb.is_none() => {
+                    let max = attribute.max.to_array().into_iter().fold(0.0, f64::max);
+                    let size = attribute.r#type.size() as usize;
+                    layout.rgb = Some([0, 1, 2].map(|element| Field {
+                        offset: offset + element * size,
+                        r#type: attribute.r#type,
+                        scale: if max <= 255.0 { 257.0 } else { 1.0 },
+                    }));
+                    continue;
+                }
+                _ => {
+                    layout.push_extra_bytes(
+                        &attribute.name,
+                        offset,
+                        attribute.r#type,
+                        attribute.num_elements,
+                    );
+                    continue;
+                }
+            };
+            if slot.is_none() {
+                *slot = Some(field);
+            } else {
+                layout.push_extra_bytes(&attribute.name, offset, attribute.r#type, 1);
+            }
+        }
+
+        // Near infrared is only part of format 8, which also has colors.
+        layout.point_format = match (&layout.rgb, &layout.nir) {
+            (Some(_), Some(_)) => 8,
+            (Some(_), None) => 7,
+            (None, _) => 6,
+        };
+        if layout.point_format == 6 {
+            if let Some(nir) = layout.nir.take() {
+                layout.push_extra_bytes("nir", nir.offset, nir.r#type, 1);
+            }
+        }
+        let base_length = match layout.point_format {
+            6 => 30,
+            7 => 36,
+            _ => 38,
+        };
+        layout.record_length = base_length
+            + layout
+                .extra_bytes
+                .iter()
+                .map(|extra| extra.r#type.size() as u16)
+                .sum::<u16>();
+        layout
+    }
+
+    fn push_extra_bytes(
+        &mut self,
+        name: &str,
+        offset: usize,
+        r#type: AttributeType,
+        elements: i32,
+    ) {
+        let size = r#type.size() as usize;
+        for element in 0..elements as usize {
+            let name = if elements == 1 {
+                name.to_string()
+            } else {
+                format!("{}[{}]", name, element)
+            };
+            self.extra_bytes.push(ExtraBytes {
+                name,
+                offset: offset + element * size,
+                r#type,
+            });
+        }
+    }
+
+    /// Encodes a point, returning its return number.
+    pub fn write_point(
+        &self,
+        point: &Point,
+        scale: f64,
+        offset: [f64; 3],
+        buf: &mut Vec<u8>,
+    ) -> u8 {
+        let value = |field: &Option<Field>, default: f64| {
+            field
+                .as_ref()
+                .map_or(default, |field| field.read(&point.attributes))
+        };
+        let position = point.position.to_array();
+        for axis in 0..3 {
+            let value = ((position[axis] - offset[axis]) / scale).round();
+            AttributeType::INT32.write_f64(value, buf);
+        }
+        AttributeType::UINT16.write_f64(value(&self.intensity, 0.0).round(), buf);
+        let return_number = value(&self.return_number, 1.0).clamp(0.0, 15.0) as u8;
+        let number_of_returns = value(&self.number_of_returns, 1.0).clamp(0.0, 15.0) as u8;
+        buf.push(return_number | (number_of_returns << 4));
+        buf.push(0);
+        AttributeType::UINT8.write_f64(value(&self.classification, 0.0).round(), buf);
+        AttributeType::UINT8.write_f64(value(&self.user_data, 0.0).round(), buf);
+        AttributeType::INT16.write_f64(value(&self.scan_angle, 0.0).round(), buf);
+        AttributeType::UINT16.write_f64(value(&self.point_source_id, 0.0).round(), buf);
+        AttributeType::DOUBLE.write_f64(value(&self.gps_time, 0.0), buf);
+        if let Some(rgb) = &self.rgb {
+            for channel in rgb {
+                AttributeType::UINT16.write_f64(channel.read(&point.attributes).round(), buf);
+            }
+        }
+        if self.point_format == 8 {
+            AttributeType::UINT16.write_f64(value(&self.nir, 0.0).round(), buf);
+        }
+        for extra in &self.extra_bytes {
+            let size = extra.r#type.size() as usize;
+            buf.extend_from_slice(&point.attributes[extra.offset..extra.offset + size]);
+        }
+        return_number
+    }
+
+    /// PDAL names and types of the fields filled from attributes, in record order, followed
+    /// by the extra bytes.
+    pub fn dimensions(&self) -> Vec<(String, AttributeType)> {
+        let fields = [
+            (&self.intensity, "Intensity", AttributeType::UINT16),
+            (&self.return_number, "ReturnNumber", AttributeType::UINT8),
+            (
+                &self.number_of_returns,
+                "NumberOfReturns",
+                AttributeType::UINT8,
+            ),
+            (&self.classification, "Classification", AttributeType::UINT8),
+            (&self.user_data, "UserData", AttributeType::UINT8),
+            (&self.scan_angle, "ScanAngleRank", AttributeType::INT16),
+            (
+                &self.point_source_id,
+                "PointSourceId",
+                AttributeType::UINT16,
+            ),
+            (&self.gps_time, "GpsTime", AttributeType::DOUBLE),
+        ];
+        let mut dimensions: Vec<(String, AttributeType)> = fields
+            .into_iter()
+            .filter(|(field, _, _)| field.is_some())
+            .map(|(_, name, r#type)| (name.to_string(), r#type))
+            .collect();
+        if self.rgb.is_some() {
+            for name in ["Red", "Green", "Blue"] {
+                dimensions.push((name.to_string(), AttributeType::UINT16));
+            }
+        }
+        if self.point_format == 8 {
+            dimensions.push(("Infrared".to_string(), AttributeType::UINT16));
+        }
+        for extra in &self.extra_bytes {
+            dimensions.push((extra.name.clone(), extra.r#type));
+        }
+        dimensions
+    }
+
+    /// Range of the GPS time over all points, from the attribute ranges.
+    pub fn gps_time_range(&self, attributes: &Attributes) -> Option<(f64, f64)> {
+        let field = self.gps_time.as_ref()?;
+        attributes
+            .list
+            .iter()
+            .find(|attribute| attributes.get_offset(&attribute.name) as usize == field.offset)
+            .map(|attribute| (attribute.min.x, attribute.max.x))
+    }
+
+    /// The laszip VLR describing LAZ chunks of these records. Chunks vary in size, so every
+    /// node can be compressed into a chunk of its own.
+    pub fn laz_vlr(&self) -> Result<LazVlr, Error> {
+        let extra_bytes = self
+            .extra_bytes
+            .iter()
+            .map(|extra| extra.r#type.size() as u16)
+            .sum();
+        Ok(LazVlrBuilder::default()
+            .with_point_format(self.point_format, extra_bytes)
+            .map_err(laz_error)?
+            .with_variable_chunk_size()
+            .build())
+    }
+
+    /// The payload of the extra bytes VLR, `None` without extra bytes.
+    pub fn extra_bytes_vlr(&self) -> Option<Vec<u8>> {
+        if self.extra_bytes.is_empty() {
+            return None;
+        }
+        let mut payload = Vec::with_capacity(self.extra_bytes.len() * EXTRA_BYTES_DESCRIPTOR_SIZE);
+        for extra in &self.extra_bytes {
+            let start = payload.len();
+            payload.extend_from_slice(&[0, 0, extra.data_type(), 0]);
+            payload.extend_from_slice(&fixed_string(&extra.name, 32));
+            payload.resize(start + EXTRA_BYTES_DESCRIPTOR_SIZE - 32, 0);
+            payload.extend_from_slice(&fixed_string(&extra.name, 32));
+        }
+        Some(payload)
+    }
+}
+
+fn laz_error(error: LasZipError) -> Error {
+    match error {
+        LasZipError::IoError(error) => error,
+        error => Error::new(ErrorKind::InvalidInput, error),
+    }
+}
+
+/// The payload of the laszip VLR.
+pub fn laz_vlr_payload(vlr: &LazVlr) -> Result<Vec<u8>, Error> {
+    let mut payload = Vec::new();
+    vlr.write_to(&mut payload)?;
+    Ok(payload)
+}
+
+/// Compresses point records into a LAZ chunk that can be decompressed on its own.
+pub fn compress_chunk(vlr: &LazVlr, records: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut compressor = LayeredPointRecordCompressor::new(Vec::new());
+    compressor.set_fields_from(vlr.items()).map_err(laz_error)?;
+    compressor.compress_many(records)?;
+    compressor.done()?;
+    Ok(compressor.into_inner())
+}
+
+/// Writes the LAZ chunk table for chunks given as point count and byte size, in order.
+pub fn write_chunk_table<W: Write>(
+    out: &mut W,
+    vlr: &LazVlr,
+    chunks: &[(u64, u64)],
+) -> Result<(), Error> {
+    let mut table = ChunkTable::with_capacity(chunks.len());
+    for &(point_count, byte_count) in chunks {
+        table.push(ChunkTableEntry {
+            point_count,
+            byte_count,
+        });
+    }
+    table.write_to(out, vlr)
+}
+
+/// A null padded string truncated to `length` bytes.
+fn fixed_string(value: &str, length: usize) -> Vec<u8> {
+    let mut bytes = value.as_bytes().to_vec();
+    bytes.resize(length, 0);
+    bytes
+}
+
+/// The fields of a LAS 1.4 header that depend on the file.
+pub struct LasHeader {
+    pub point_format: u8,
+    pub record_length: u16,
+    pub offset_to_points: u32,
+    pub vlr_count: u32,
+    pub evlr_start: u64,
+    pub evlr_count: u32,
+    pub point_count: u64,
+    pub points_by_return: [u64; 15],
+    pub scale: f64,
+    pub offset: [f64; 3],
+    pub min: [f64; 3],
+    pub max: [f64; 3],
+}
+
+impl LasHeader {
+    pub fn write<W: Write>(&self, out: &mut W) -> Result<(), Error> {
+        out.write_all(b"LASF")?;
+        out.write_u16::<LittleEndian>(0)?;
+        out.write_u16::<LittleEndian>(GLOBAL_ENCODING_WKT)?;
+        out.write_all(&[0; 16])?;
+        out.write_all(&[1, 4])?;
+        out.write_all(&fixed_string("", 32))?;
+        out.write_all(&fixed_string("rusty-potree-converter", 32))?;
+        out.write_u16::<LittleEndian>(0)?;
+        out.write_u16::<LittleEndian>(0)?;
+        out.write_u16::<LittleEndian>(HEADER_SIZE)?;
+        out.write_u32::<LittleEndian>(self.offset_to_points)?;
+        out.write_u32::<LittleEndian>(self.vlr_count)?;
+        out.write_u8(self.point_format)?;
+        out.write_u16::<LittleEndian>(self.record_length)?;
+        // Legacy point counts stay zero for point formats 6 and above.
+        out.write_all(&[0; 4 + 5 * 4])?;
+        for _ in 0..3 {
+            out.write_f64::<LittleEndian>(self.scale)?;
+        }
+        for offset in self.offset {
+            out.write_f64::<LittleEndian>(offset)?;
+        }
+        for axis in 0..3 {
+            out.write_f64::<LittleEndian>(self.max[axis])?;
+            out.write_f64::<LittleEndian>(self.min[axis])?;
+        }
+        out.write_u64::<LittleEndian>(0)?;
+        out.write_u64::<LittleEndian>(self.evlr_start)?;
+        out.write_u32::<LittleEndian>(self.evlr_count)?;
+        out.write_u64::<LittleEndian>(self.point_count)?;
+        for count in self.points_by_return {
+            out.write_u64::<LittleEndian>(count)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn write_vlr<W: Write>(
+    out: &mut W,
+    user_id: &str,
+    record_id: u16,
+    description: &str,
+    payload: &[u8],
+) -> Result<(), Error> {
+    out.write_u16::<LittleEndian>(0)?;
+    out.write_all(&fixed_string(user_id, 16))?;
+    out.write_u16::<LittleEndian>(record_id)?;
+    out.write_u16::<LittleEndian>(payload.len() as u16)?;
+    out.write_all(&fixed_string(description, 32))?;
+    out.write_all(payload)
+}
+
+pub fn write_evlr<W: Write>(
+    out: &mut W,
+    user_id: &str,
+    record_id: u16,
+    description: &str,
+    payload: &[u8],
+) -> Result<(), Error> {
+    out.write_u16::<LittleEndian>(0)?;
+    out.write_all(&fixed_string(user_id, 16))?;
+    out.write_u16::<LittleEndian>(record_id)?;
+    out.write_u64::<LittleEndian>(payload.len() as u64)?;
+    out.write_all(&fixed_string(description, 32))?;
+    out.write_all(payload)
+}
+
+/// The payload of the OGC WKT VLR. WKT is stored as it is and an authority code such as
+/// `EPSG:25832` as a WKT stub that only names the code, which readers resolve through
+/// their CRS database. Other notations give `None` and are left out of the file.
+pub fn wkt_vlr(crs: Option<&str>) -> Option<Vec<u8>> {
+    let crs = crs?.trim();
+    let wkt = if crs.contains('[') {
+        crs.to_string()
+    } else {
+        let (authority, code) = crs.split_once(':')?;
+        let valid = |part: &str| {
+            !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        };
+        if !valid(authority) || !valid(code) {
+            return None;
+        }
+        let keyword = if is_geographic(crs) {
+            "GEOGCS"
+        } else {
+            "PROJCS"
+        };
+        let authority = authority.to_uppercase();
+        format!(
+            "{}[\"{}:{}\",AUTHORITY[\"{}\",\"{}\"]]",
+            keyword, authority, code, authority, code
+        )
+    };
+    let mut payload = wkt.into_bytes();
+    payload.push(0);
+    Some(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wkt_vlr() {
+        let wkt = |crs: &str| wkt_vlr(Some(crs)).map(|payload| String::from_utf8(payload).unwrap());
+        assert_eq!(
+            wkt("epsg:25832").unwrap(),
+            "PROJCS[\"EPSG:25832\",AUTHORITY[\"EPSG\",\"25832\"]]\0"
+        );
+        assert_eq!(
+            wkt("EPSG:4326").unwrap(),
+            "GEOGCS[\"EPSG:4326\",AUTHORITY[\"EPSG\",\"4326\"]]\0"
+        );
+        assert!(wkt(" GEOGCS[\"WGS 84\"] ")
+            .unwrap()
+            .starts_with("GEOGCS[\"WGS 84\"]"));
+        assert_eq!(wkt("WGS 84"), None);
+        assert_eq!(wkt_vlr(None), None);
+    }
+}
@@ -0,0 +1,293 @@
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::Error;
+use std::path::{Path, PathBuf};
+
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use crate::model::attributes::{AttributeType, Attributes};
+use crate::model::cloud_js::{BoundingBox, CloudJs};
+use crate::model::node::Node;
+use crate::potree::Potree;
+use crate::writer::HRC_STEP_SIZE;
+
+const OCTREE_DIR: &str = "data";
+
+/// Potree 1.x attributes and the attribute names they are written from.
+const LEGACY_ATTRIBUTES: [(&[&str], &str, AttributeType, usize); 8] = [
+    (
+        &["rgb", "rgba", "color"],
+        "RGBA_PACKED",
+        AttributeType::UINT8,
+        4,
+    ),
+    (&["intensity"], "INTENSITY", AttributeType::UINT16, 1),
+    (
+        &["classification"],
+        "CLASSIFICATION",
+        AttributeType::UINT8,
+        1,
+    ),
+    (
+        &["return number", "return_number"],
+        "RETURN_NUMBER",
+        AttributeType::UINT8,
+        1,
+    ),
+    (
+        &["number of returns", "number_of_returns"],
+        "NUMBER_OF_RETURNS",
+        AttributeType::UINT8,
+        1,
+    ),
+    (
+        &["point source id", "point_source_id"],
+        "SOURCE_ID",
+        AttributeType::UINT16,
+        1,
+    ),
+    (
+        &["gps-time", "gps_time", "gps time", "timestamp"],
+        "GPS_TIME",
+        AttributeType::DOUBLE,
+        1,
+    ),
+    (&["normal"], "NORMAL", AttributeType::FLOAT, 3),
+];
+
+/// An attribute of the octree written in its Potree 1.x form.
+struct LegacyAttribute {
+    name: &'static str,
+    r#type: AttributeType,
+    elements: usize,
+    source_type: AttributeType,
+    source_offset: usize,
+    source_elements: usize,
+    /// Factor bringing the source values into the range of the written type.
+    scale: f64,
+}
+
+fn legacy_attributes(attributes: &Attributes) -> Vec<LegacyAttribute> {
+    let mut legacy = Vec::new();
+    for (sources, name, r#type, elements) in LEGACY_ATTRIBUTES {
+        let source = attributes
+            .list
+            .iter()
+            .find(|attribute| sources.contains(&attribute.name.to_lowercase().as_str()));
+        let source = match source {
+            Some(source) => source,
+            None => continue,
+        };
+        let max = source.max.to_array().into_iter().fold(0.0, f64::max);
+        legacy.push(LegacyAttribute {
+            name,
+            r#type,
+            elements,
+            source_type: source.r#type,
+            source_offset: attributes.get_offset(&source.name) as usize,
+            source_elements: source.num_elements as usize,
+            scale: if name == "RGBA_PACKED" && max > 255.0 {
+                1.0 / 257.0
+            } else {
+                1.0
+            },
+        });
+    }
+    legacy
+}
+
+impl LegacyAttribute {
+    fn write(&self, attributes: &[u8], buf: &mut Vec<u8>) {
+        let size = self.source_type.size() as usize;
+        for element in 0..self.elements {
+            let value = if element < self.source_elements {
+                let start = self.source_offset + element * size;
+                self.source_type.read_f64(&attributes[start..start + size]) * self.scale
+            } else {
+                // Alpha of colors without one.
+                255.0
+            };
+            let value = match self.r#type {
+                AttributeType::FLOAT | AttributeType::DOUBLE => value,
+                _ => value.round(),
+            };
+            self.r#type.write_f64(value, buf);
+        }
+    }
+}
+
+/// Directory of a node below the octree directory, one level deeper every
+/// `HRC_STEP_SIZE` levels, such as `r/01234/` for `r012345`.
+fn hierarchy_path(name: &str) -> PathBuf {
+    let digits = &name[1..];
+    let mut path = PathBuf::from("r");
+    for part in 0..digits.len() / HRC_STEP_SIZE {
+        path.push(&digits[part * HRC_STEP_SIZE..(part + 1) * HRC_STEP_SIZE]);
+    }
+    path
+}
+
+fn non_empty_children(node: &Node) -> impl Iterator<Item = (usize, &Node)> {
+    node.children
+        .iter()
+        .enumerate()
+        .filter_map(|(i, child)| child.as_deref().map(|child| (i, child)))
+        .filter(|(_, child)| child.num_points() > 0)
+}
+
+/// Writes the child mask and point count of `start` and its descendants up to
+/// `HRC_STEP_SIZE` levels deeper, breadth first.
+fn hrc_bytes(start: &Node) -> Vec<u8> {
+    let mut buf = Vec::new();
+    let mut queue = VecDeque::from([start]);
+    while let Some(node) = queue.pop_front() {
+        let mask = non_empty_children(node).fold(0u8, |mask, (i, _)| mask | 1 << i);
+        buf.push(mask);
+        buf.write_u32::<LittleEndian>(node.num_points() as u32)
+            .unwrap();
+        if node.level() < start.level() + HRC_STEP_SIZE {
+            queue.extend(non_empty_children(node).map(|(_, child)| child));
+        }
+    }
+    buf
+}
+
+fn write_node(
+    node: &Node,
+    potree: &Potree,
+    attributes: &[LegacyAttribute],
+    octree_dir: &Path,
+) -> Result<(), Error> {
+    let dir = octree_dir.join(hierarchy_path(&node.name));
+    fs::create_dir_all(&dir)?;
+
+    let mut buf = Vec::new();
+    for point in node.points() {
+        let position = point.position.to_array();
+        let min = [node.bounds.lx, node.bounds.ly, node.bounds.lz];
+        for axis in 0..3 {
+            let value = ((position[axis] - min[axis]) / potree.scale).round() as u32;
+            buf.write_u32::<LittleEndian>(value)?;
+        }
+        for attribute in attributes {
+            attribute.write(&point.attributes, &mut buf);
+        }
+    }
+    fs::write(dir.join(format!("{}.bin", node.name)), buf)?;
+
+    if node.level().is_multiple_of(HRC_STEP_SIZE) {
+        fs::write(dir.join(format!("{}.hrc", node.name)), hrc_bytes(node))?;
+    }
+
+    for (_, child) in non_empty_children(node) {
+        write_node(child, potree, attributes, octree_dir)?;
+    }
+    Ok(())
+}
+
+/// Writes the octree in the Potree 1.7 layout read by legacy viewers: `cloud.js` and a
+/// `data/r/...` tree holding a `.bin` file per node, with `.hrc` hierarchy files every
+/// `HRC_STEP_SIZE` levels.
+///
+/// Positions are stored relative to the node bounds. Colors, intensity, classification,
+/// returns, point source id, GPS time and normals are kept under their 1.x names, other
+/// attributes are left out.
+pub fn write_potree_legacy(potree: &Potree, dir: &Path) -> Result<(), Error> {
+    let attributes = legacy_attributes(&potree.attributes);
+    write_node(&potree.root, potree, &attributes, &dir.join(OCTREE_DIR))?;
+
+    let mut point_attributes = vec!["POSITION_CARTESIAN".to_string()];
+    point_attributes.extend(
+        attributes
+            .iter()
+            .map(|attribute| attribute.name.to_string()),
+    );
+    let cloud_js = CloudJs {
+        version: "1.7".to_string(),
+        octree_dir: OCTREE_DIR.to_string(),
+        projection: potree.crs.clone().unwrap_or_default(),
+        points: potree.size as u64,
+        bounding_box: BoundingBox::from_bounds(&potree.root.bounds),
+        tight_bounding_box: BoundingBox::from_bounds(&potree.bounds),
+        point_attributes,
+        spacing: potree.spacing,
+        scale: potree.scale,
+        hierarchy_step_size: HRC_STEP_SIZE,
+    };
+    let file = File::create(dir.join("cloud.js"))?;
+    serde_json::to_writer_pretty(file, &cloud_js)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::model::attributes::Attribute;
+    use crate::model::point::Point;
+    use crate::model::vector3::Vector3;
+
+    #[test]
+    fn test_hierarchy_path() {
+        assert_eq!(hierarchy_path("r"), PathBuf::from("r"));
+        assert_eq!(hierarchy_path("r0123"), PathBuf::from("r"));
+        assert_eq!(hierarchy_path("r01234"), PathBuf::from("r/01234"));
+        assert_eq!(
+            hierarchy_path("r0123456701"),
+            PathBuf::from("r/01234/56701")
+        );
+    }
+
+    #[test]
+    fn test_write_potree_legacy() -> Result<(), Box<dyn std::error::Error>> {
+        let points = (0..2000)
+            .map(|i| {
+                let position = Vector3 {
+                    x: (i % 40) as f64,
+                    y: (i / 40) as f64,
+                    z: (i % 7) as f64,
+                };
+                let mut attributes = Vec::new();
+                for channel in [65535.0, 0.0, 257.0] {
+                    AttributeType::UINT16.write_f64(channel, &mut attributes);
+                }
+                AttributeType::FLOAT.write_f64(i as f64, &mut attributes);
+                Point::with_attributes(position, attributes)
+            })
+            .collect();
+        let attributes = Attributes::from_attributes(vec![
+            Attribute::new("rgb", AttributeType::UINT16, 3),
+            Attribute::new("range", AttributeType::FLOAT, 1),
+        ]);
+        let potree = Potree::with_attributes(points, attributes, 100);
+
+        let dir = std::env::temp_dir().join("potree-legacy-writer-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir)?;
+        write_potree_legacy(&potree, &dir)?;
+
+        let cloud_js: CloudJs = serde_json::from_slice(&fs::read(dir.join("cloud.js"))?)?;
+        assert_eq!(cloud_js.points, 2000);
+        assert_eq!(
+            cloud_js.point_attributes,
+            ["POSITION_CARTESIAN", "RGBA_PACKED"]
+        );
+
+        let root = fs::read(dir.join("data/r/r.bin"))?;
+        assert_eq!(root.len(), potree.root.num_points() * 16);
+        assert_eq!(root[12..16], [255, 0, 1, 255]);
+
+        let hrc = fs::read(dir.join("data/r/r.hrc"))?;
+        assert_eq!(hrc[1..5], (potree.root.num_points() as u32).to_le_bytes());
+        let total: u32 = hrc
+            .chunks(5)
+            .map(|node| u32::from_le_bytes(node[1..5].try_into().unwrap()))
+            .sum();
+        assert_eq!(total, 2000);
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -1,6 +1,33 @@
 pub mod model;
+pub mod aggregate;
+pub mod append;
+pub mod archive;
+#[cfg(feature = "arrow")]
+pub mod arrow_reader;
+pub mod copc_writer;
 pub mod csv_reader;
+pub mod dataset;
+pub mod e57_reader;
+pub mod ept_writer;
+pub mod export;
+pub mod gltf_reader;
+pub mod labels;
+pub mod las;
+pub mod legacy_writer;
+pub mod merge;
+pub mod mesh;
+pub mod npy;
+pub mod npy_reader;
+pub mod obj_reader;
 pub mod pcd_reader;
+pub mod point_reader;
 pub mod potree;
+pub mod raw_reader;
 pub mod reader;
+pub mod ros_reader;
+pub(crate) mod spill;
+pub mod tiles_writer;
+pub mod tiling;
+pub mod viewer;
 pub mod writer;
+pub mod xyz_reader;
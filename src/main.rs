@@ -0,0 +1,111 @@
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use rusty_potree_converter::point_reader::Registry;
+use rusty_potree_converter::potree::{BuildOptions, Potree};
+use rusty_potree_converter::tiling::{write_tiles, TileOptions};
+use rusty_potree_converter::viewer::ViewerOptions;
+use rusty_potree_converter::writer::{write_potree_with_options, WriteOptions};
+
+const USAGE: &str = "Usage: rusty-potree-converter <input> <output-dir> [options]
+
+Options:
+  --html                  Write an index.html viewer page next to the dataset
+  --title <title>         Title of the viewer page
+  --point-budget <n>      Points the viewer renders at once
+  --potree-url <url>      Location of Potree's build and libs directories for the page
+  --tile-size <size>      Split the input into square tiles of this size in CRS units,
+                          each converted into its own directory and listed in index.json
+  --crs <crs>             Coordinate reference system of the input as WKT or a code such
+                          as EPSG:25832, replacing the one read from the file";
+
+struct Args {
+    input: PathBuf,
+    output: PathBuf,
+    write_options: WriteOptions,
+    build_options: BuildOptions,
+    tile_size: Option<f64>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut positional = Vec::new();
+    let mut html = false;
+    let mut viewer = ViewerOptions::default();
+    let mut tile_size = None;
+    let mut crs = None;
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("{} needs a value", name));
+        match arg.as_str() {
+            "--html" => html = true,
+            "--title" => viewer.title = value(&arg)?,
+            "--point-budget" => {
+                viewer.point_budget = value(&arg)?
+                    .parse()
+                    .map_err(|_| "--point-budget needs a number".to_string())?;
+            }
+            "--potree-url" => viewer.potree_url = value(&arg)?,
+            "--tile-size" => {
+                tile_size = Some(
+                    value(&arg)?
+                        .parse()
+                        .map_err(|_| "--tile-size needs a number".to_string())?,
+                );
+            }
+            "--crs" => crs = Some(value(&arg)?),
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+            _ => positional.push(arg),
+        }
+    }
+    match <[String; 2]>::try_from(positional) {
+        Ok([input, output]) => Ok(Args {
+            input: input.into(),
+            output: output.into(),
+            write_options: WriteOptions {
+                viewer: html.then_some(viewer),
+            },
+            build_options: BuildOptions {
+                crs,
+                ..BuildOptions::default()
+            },
+            tile_size,
+        }),
+        Err(_) => Err("Expected an input file and an output directory".to_string()),
+    }
+}
+
+fn convert(args: Args) -> Result<(), Box<dyn Error>> {
+    let buf = fs::read(&args.input)?;
+    let mut reader = Registry::default().open(&args.input.to_string_lossy(), &buf)?;
+    fs::create_dir_all(&args.output)?;
+    if let Some(tile_size) = args.tile_size {
+        let options = TileOptions {
+            tile_size,
+            build: args.build_options,
+            write: args.write_options,
+        };
+        write_tiles(reader.as_mut(), &args.output, &options)?;
+        return Ok(());
+    }
+    let potree = Potree::from_reader(reader.as_mut(), &args.build_options)?;
+    write_potree_with_options(potree, &args.output, &args.write_options)?;
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(msg) => {
+            eprintln!("{}\n\n{}", msg, USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+    match convert(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("{}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,484 @@
+use core::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::dataset::{DatasetError, PotreeDataset};
+use crate::model::attributes::{Attribute, AttributeType, Attributes};
+use crate::model::bounds::Bounds;
+use crate::model::node::Node;
+use crate::model::point::Point;
+use crate::model::vector3::Vector3;
+use crate::potree::{BuildOptions, Potree};
+use crate::spill::Spill;
+use crate::writer::{write_index_files, WriteOptions, Writer};
+
+#[derive(Debug)]
+pub enum MergeError {
+    NoInputs,
+    Dataset { path: PathBuf, error: DatasetError },
+    CrsMismatch { first: String, second: String },
+    Io(std::io::Error),
+}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MergeError::NoInputs => write!(f, "No datasets to merge"),
+            MergeError::Dataset { path, error } => write!(f, "{}: {}", path.display(), error),
+            MergeError::CrsMismatch { first, second } => write!(
+                f,
+                "Datasets in different coordinate systems: '{}' and '{}'",
+                first, second
+            ),
+            MergeError::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for MergeError {}
+
+impl From<std::io::Error> for MergeError {
+    fn from(error: std::io::Error) -> MergeError {
+        MergeError::Io(error)
+    }
+}
+
+#[derive(PartialEq)]
+enum Kind {
+    Signed,
+    Unsigned,
+    Float,
+}
+
+fn kind(r#type: AttributeType) -> Kind {
+    match r#type {
+        AttributeType::INT8
+        | AttributeType::INT16
+        | AttributeType::INT32
+        | AttributeType::INT64 => Kind::Signed,
+        AttributeType::FLOAT | AttributeType::DOUBLE => Kind::Float,
+        _ => Kind::Unsigned,
+    }
+}
+
+/// A signed type holding the values of an unsigned and a signed type, a double if no
+/// integer type is wide enough.
+fn signed_holding(unsigned: AttributeType, signed: AttributeType) -> AttributeType {
+    match (unsigned.size() * 2).max(signed.size()) {
+        1 => AttributeType::INT8,
+        2 => AttributeType::INT16,
+        4 => AttributeType::INT32,
+        8 => AttributeType::INT64,
+        _ => AttributeType::DOUBLE,
+    }
+}
+
+/// A type holding the values of both types: the wider one for the same kind of number,
+/// a signed type wider than the unsigned one for mixed integers and a double otherwise.
+fn common_type(a: AttributeType, b: AttributeType) -> AttributeType {
+    if a == b {
+        return a;
+    }
+    let wider = if a.size() >= b.size() { a } else { b };
+    match (kind(a), kind(b)) {
+        (Kind::Float, _) | (_, Kind::Float) => AttributeType::DOUBLE,
+        (ka, kb) if ka == kb => wider,
+        (Kind::Unsigned, _) => signed_holding(a, b),
+        _ => signed_holding(b, a),
+    }
+}
+
+fn is_color(attribute: &Attribute) -> bool {
+    matches!(
+        attribute.name.to_lowercase().as_str(),
+        "rgb" | "rgba" | "color"
+    )
+}
+
+/// Color attributes some dataset stores with 16 bit values, judged by their ranges as
+/// 8 bit colors are often stored in 16 bit types. Their 8 bit values in other datasets
+/// are scaled to 16 bit.
+fn wide_colors(datasets: &[PotreeDataset]) -> Vec<String> {
+    let mut names = Vec::new();
+    for dataset in datasets {
+        for attribute in &dataset.attributes.list {
+            let max = attribute.max.to_array().into_iter().fold(0.0, f64::max);
+            if is_color(attribute) && max > 255.0 && !names.contains(&attribute.name) {
+                names.push(attribute.name.clone());
+            }
+        }
+    }
+    names
+}
+
+/// Attributes of every dataset by name, in order of first appearance, with types that
+/// hold the values of every dataset and the most elements any dataset has.
+fn unify_attributes(datasets: &[PotreeDataset]) -> Attributes {
+    let mut list: Vec<Attribute> = Vec::new();
+    for dataset in datasets {
+        for attribute in &dataset.attributes.list {
+            match list.iter_mut().find(|a| a.name == attribute.name) {
+                Some(existing) => {
+                    let r#type = common_type(existing.r#type, attribute.r#type);
+                    let num_elements = existing.num_elements.max(attribute.num_elements);
+                    if r#type != existing.r#type || num_elements != existing.num_elements {
+                        *existing = Attribute {
+                            description: existing.description.clone(),
+                            ..Attribute::new(&existing.name, r#type, num_elements)
+                        };
+                    }
+                }
+                None => list.push(Attribute {
+                    description: attribute.description.clone(),
+                    ..Attribute::new(&attribute.name, attribute.r#type, attribute.num_elements)
+                }),
+            }
+        }
+    }
+    Attributes::from_attributes(list)
+}
+
+/// Where an element of the unified attributes is read from in the points of one dataset.
+struct Source {
+    offset: usize,
+    r#type: AttributeType,
+    /// Factor the values are scaled by, 257 for 8 bit colors widened to 16 bit.
+    factor: f64,
+}
+
+/// Where each element of the unified attributes comes from in the points of one dataset.
+struct Conversion {
+    /// Per unified element: its type, and its source if the dataset has it.
+    elements: Vec<(AttributeType, Option<Source>)>,
+}
+
+impl Conversion {
+    fn new(source: &Attributes, target: &Attributes, wide_colors: &[String]) -> Conversion {
+        let mut elements = Vec::new();
+        for attribute in &target.list {
+            let found = source.list.iter().find(|a| a.name == attribute.name);
+            for element in 0..attribute.num_elements {
+                let from = found.filter(|a| element < a.num_elements).map(|a| {
+                    let max = a.max.to_array().into_iter().fold(0.0, f64::max);
+                    Source {
+                        offset: (source.get_offset(&a.name) + element * a.element_size) as usize,
+                        r#type: a.r#type,
+                        factor: if wide_colors.contains(&a.name) && max <= 255.0 {
+                            257.0
+                        } else {
+                            1.0
+                        },
+                    }
+                });
+                elements.push((attribute.r#type, from));
+            }
+        }
+        Conversion { elements }
+    }
+
+    /// Attributes of a point in the unified layout, zero for values the dataset lacks.
+    fn convert(&self, attributes: &[u8]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for (r#type, from) in &self.elements {
+            match from {
+                Some(source) if source.r#type == *r#type && source.factor == 1.0 => {
+                    let end = source.offset + r#type.size() as usize;
+                    buf.extend_from_slice(&attributes[source.offset..end])
+                }
+                Some(source) => {
+                    let value = source.r#type.read_f64(&attributes[source.offset..]);
+                    r#type.write_f64(value * source.factor, &mut buf)
+                }
+                None => buf.resize(buf.len() + r#type.size() as usize, 0),
+            }
+        }
+        buf
+    }
+}
+
+/// Bounds of the points of a dataset, from the range of its `position` attribute or,
+/// without one, its bounding box.
+fn point_bounds(dataset: &PotreeDataset) -> Bounds {
+    let metadata = &dataset.metadata;
+    let (min, max) = metadata
+        .attributes
+        .iter()
+        .find(|attribute| {
+            attribute.name == "position" && attribute.min.len() == 3 && attribute.max.len() == 3
+        })
+        .map(|position| {
+            let min: [f64; 3] = position.min[..].try_into().unwrap();
+            let max: [f64; 3] = position.max[..].try_into().unwrap();
+            (min, max)
+        })
+        .unwrap_or((metadata.bounding_box.min, metadata.bounding_box.max));
+    Bounds::new(max[0], max[1], max[2], min[0], min[1], min[2])
+}
+
+/// The projection shared by the datasets, ignoring datasets without one.
+fn common_crs(datasets: &[PotreeDataset]) -> Result<Option<String>, MergeError> {
+    let mut crs: Option<&str> = None;
+    for projection in datasets
+        .iter()
+        .map(|dataset| dataset.metadata.projection.as_str())
+    {
+        match crs {
+            _ if projection.is_empty() => {}
+            None => crs = Some(projection),
+            Some(first) if first != projection => {
+                return Err(MergeError::CrsMismatch {
+                    first: first.to_string(),
+                    second: projection.to_string(),
+                })
+            }
+            Some(_) => {}
+        }
+    }
+    Ok(crs.map(str::to_string))
+}
+
+/// Merges datasets into one dataset written to `dir`, over a common cube and with the
+/// union of their attributes.
+///
+/// The converted points are spilled to a file per octant of the root in `dir` first. Each
+/// octant is then read back, indexed and written on its own, so memory holds the points of
+/// one octant and of the root node rather than every input. Attributes of the same name
+/// are converted to a type holding the values of every dataset and attributes missing
+/// from a dataset are zero for its points. 8 bit colors are scaled to 16 bit when another
+/// dataset has 16 bit colors. Positions keep the finest scale of the inputs that still
+/// fits the merged bounds.
+pub fn merge(
+    datasets: &[PotreeDataset],
+    dir: &Path,
+    options: &BuildOptions,
+) -> Result<(), MergeError> {
+    if datasets.is_empty() {
+        return Err(MergeError::NoInputs);
+    }
+    let crs = common_crs(datasets)?;
+    let mut attributes = unify_attributes(datasets);
+    let wide_colors = wide_colors(datasets);
+
+    let mut bounds = point_bounds(&datasets[0]);
+    for dataset in &datasets[1..] {
+        let other = point_bounds(dataset);
+        bounds = Bounds::new(
+            bounds.ux.max(other.ux),
+            bounds.uy.max(other.uy),
+            bounds.uz.max(other.uz),
+            bounds.lx.min(other.lx),
+            bounds.ly.min(other.ly),
+            bounds.lz.min(other.lz),
+        );
+    }
+    let cube = bounds.cubic();
+
+    let mut spill = Spill::new(dir.join(".spill"), &attributes)?;
+    let mut size = 0;
+    for dataset in datasets {
+        let conversion = Conversion::new(&dataset.attributes, &attributes, &wide_colors);
+        for node in &dataset.nodes {
+            let points = dataset
+                .read_node(node)
+                .map_err(|error| MergeError::Dataset {
+                    path: dataset.dir().to_path_buf(),
+                    error,
+                })?;
+            for point in points {
+                let point =
+                    Point::with_attributes(point.position, conversion.convert(&point.attributes));
+                attributes.update_ranges(&point.attributes);
+                spill.push(Node::find_grid_index(&point.position, &cube), &point)?;
+                size += 1;
+            }
+        }
+    }
+    spill.flush()?;
+
+    let mut potree = Potree::builder(cube, attributes, options.point_per_leaf_node_limit).build();
+    let finest = datasets
+        .iter()
+        .flat_map(|dataset| dataset.metadata.scale)
+        .fold(potree.scale, f64::min);
+    if bounds.size_x.max(bounds.size_y).max(bounds.size_z) / finest < i32::MAX as f64 {
+        potree.scale = finest;
+    }
+    potree.size = size;
+    potree.crs = crs;
+    let offset = Vector3 {
+        x: bounds.lx,
+        y: bounds.ly,
+        z: bounds.lz,
+    };
+    potree.bounds = bounds;
+
+    let mut file = BufWriter::new(File::create(dir.join("octree.bin"))?);
+    let bytes_per_point = 12 + potree.attributes.bytes as u32;
+    let mut writer = Writer::appending(&mut file, 0, bytes_per_point);
+    let root = &mut potree.root;
+    for index in spill.keys() {
+        // The child makes the root an inner node, which samples the points of each octant
+        // into its grid independently of the other octants.
+        root.children[index] = Some(Box::new(root.new_child_node(index)));
+        for point in spill.read(index)? {
+            root.add_point(point);
+        }
+        let child = root.children[index].as_deref_mut().unwrap();
+        if child.num_points() == 0 && child.is_leaf_node() {
+            root.children[index] = None;
+            continue;
+        }
+        writer.write_nodes(vec![child], potree.scale, &offset);
+        child.clear_points();
+    }
+    writer.write_points(root, potree.scale, &offset);
+    let node_hierarchy = writer.node_hierarchy;
+    file.flush()?;
+
+    write_index_files(&potree, node_hierarchy, dir, &WriteOptions::default())?;
+    Ok(())
+}
+
+/// Merges the datasets in `inputs` into a single dataset written to `output`.
+pub fn merge_datasets<P: AsRef<Path>>(
+    inputs: &[P],
+    output: &Path,
+    options: &BuildOptions,
+) -> Result<(), MergeError> {
+    let datasets = inputs
+        .iter()
+        .map(|path| {
+            PotreeDataset::open(path.as_ref()).map_err(|error| MergeError::Dataset {
+                path: path.as_ref().to_path_buf(),
+                error,
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    merge(&datasets, output, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::dataset::Query;
+    use crate::writer::write_potree;
+
+    fn write_dataset(
+        dir: &Path,
+        origin: f64,
+        attributes: Vec<Attribute>,
+        values: impl Fn(usize) -> Vec<u8>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let points = (0..2000)
+            .map(|i| {
+                let position = Vector3 {
+                    x: origin + (i % 40) as f64,
+                    y: origin + (i / 40 % 10) as f64,
+                    z: (i % 3) as f64,
+                };
+                Point::with_attributes(position, values(i))
+            })
+            .collect();
+        let mut potree =
+            Potree::with_attributes(points, Attributes::from_attributes(attributes), 200);
+        potree.crs = Some("EPSG:25832".to_string());
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir_all(dir)?;
+        write_potree(potree, dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_common_type() {
+        use AttributeType::*;
+        assert_eq!(common_type(UINT8, UINT16), UINT16);
+        assert_eq!(common_type(UINT16, INT8), INT32);
+        assert_eq!(common_type(INT32, UINT8), INT32);
+        assert_eq!(common_type(FLOAT, UINT8), DOUBLE);
+        assert_eq!(common_type(UINT32, INT64), INT64);
+        assert_eq!(common_type(INT64, UINT64), DOUBLE);
+    }
+
+    #[test]
+    fn test_merge_datasets() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = std::env::temp_dir().join("potree-merge-test");
+        let (a, b, merged) = (dir.join("a"), dir.join("b"), dir.join("merged"));
+        write_dataset(
+            &a,
+            0.0,
+            vec![
+                Attribute::new("intensity", AttributeType::UINT16, 1),
+                Attribute::new("rgb", AttributeType::UINT8, 3),
+            ],
+            |i| {
+                let mut values = (i as u16).to_le_bytes().to_vec();
+                values.extend([255, 0, 1]);
+                values
+            },
+        )?;
+        write_dataset(
+            &b,
+            500.0,
+            vec![
+                Attribute::new("classification", AttributeType::UINT8, 1),
+                Attribute::new("intensity", AttributeType::UINT32, 1),
+                Attribute::new("rgb", AttributeType::UINT16, 3),
+            ],
+            |i| {
+                let mut values = vec![2];
+                values.extend((100_000 + i as u32).to_le_bytes());
+                for channel in [65535u16, 1000, 0] {
+                    values.extend(channel.to_le_bytes());
+                }
+                values
+            },
+        )?;
+
+        fs::create_dir_all(&merged)?;
+        let options = BuildOptions {
+            point_per_leaf_node_limit: 200,
+            ..BuildOptions::default()
+        };
+        merge_datasets(&[&a, &b], &merged, &options)?;
+        assert!(!merged.join(".spill").exists());
+
+        let dataset = PotreeDataset::open(&merged)?;
+        assert_eq!(dataset.metadata.points, 4000);
+        assert_eq!(dataset.metadata.projection, "EPSG:25832");
+        let names: Vec<(&str, AttributeType)> = dataset
+            .attributes
+            .list
+            .iter()
+            .map(|a| (a.name.as_str(), a.r#type))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("intensity", AttributeType::UINT32),
+                ("rgb", AttributeType::UINT16),
+                ("classification", AttributeType::UINT8)
+            ]
+        );
+        let (min, max) = (
+            dataset.metadata.bounding_box.min,
+            dataset.metadata.bounding_box.max,
+        );
+        assert_eq!(max[0] - min[0], max[2] - min[2]);
+
+        let cloud = dataset.query(&Query::default())?;
+        assert_eq!(cloud.len(), 4000);
+        assert!(dataset.nodes.len() > 8);
+        let from_a = cloud.points.iter().find(|p| p.position.x < 100.0).unwrap();
+        assert_eq!(from_a.attributes[4..], [255, 255, 0, 0, 1, 1, 0]);
+        let from_b = cloud.points.iter().find(|p| p.position.x > 100.0).unwrap();
+        let intensity = u32::from_le_bytes(from_b.attributes[0..4].try_into().unwrap());
+        assert!(intensity >= 100_000);
+        assert_eq!(from_b.attributes[4..], [255, 255, 232, 3, 0, 0, 2]);
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
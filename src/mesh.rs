@@ -0,0 +1,199 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::model::attributes::{Attribute, AttributeType, Attributes};
+use crate::model::point::Point;
+use crate::model::point_cloud::PointCloud;
+use crate::model::vector3::Vector3;
+
+/// Color of vertices without one in a mesh where others have colors, as glTF defines it.
+const WHITE: [f64; 3] = [65535.0; 3];
+
+#[derive(Default)]
+pub struct MeshOptions {
+    /// Points to sample per square unit of triangle surface, in addition to the vertices.
+    pub sample_density: Option<f64>,
+    /// Seed of the random generator placing the samples.
+    pub seed: u64,
+}
+
+/// Vertices and triangles gathered from a mesh file, with colors in the uint16 range.
+#[derive(Default)]
+pub struct Mesh {
+    pub positions: Vec<Vector3>,
+    pub colors: Vec<Option<[f64; 3]>>,
+    pub normals: Vec<Option<[f64; 3]>>,
+    pub triangles: Vec<[usize; 3]>,
+}
+
+impl Mesh {
+    pub fn push_vertex(
+        &mut self,
+        position: Vector3,
+        color: Option<[f64; 3]>,
+        normal: Option<[f64; 3]>,
+    ) {
+        self.positions.push(position);
+        self.colors.push(color);
+        self.normals.push(normal);
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// Turns every vertex into a point and, with a sample density, adds points spread
+    /// uniformly over the triangles with colors and normals interpolated from their corners.
+    ///
+    /// The mesh gets a uint16 `rgb` attribute if any vertex has a color and a float
+    /// `normal` attribute if any vertex has a normal.
+    pub fn into_point_cloud(self, options: &MeshOptions) -> PointCloud {
+        let has_colors = self.colors.iter().any(Option::is_some);
+        let has_normals = self.normals.iter().any(Option::is_some);
+        let mut attribute_list = Vec::new();
+        if has_colors {
+            attribute_list.push(Attribute::new("rgb", AttributeType::UINT16, 3));
+        }
+        if has_normals {
+            attribute_list.push(Attribute::new("normal", AttributeType::FLOAT, 3));
+        }
+
+        let color = |i: usize| self.colors[i].unwrap_or(WHITE);
+        let normal = |i: usize| self.normals[i].unwrap_or([0.0; 3]);
+        let point = |position: Vector3, color: [f64; 3], normal: [f64; 3]| {
+            let mut attributes = Vec::new();
+            if has_colors {
+                for channel in color {
+                    AttributeType::UINT16.write_f64(channel.round(), &mut attributes);
+                }
+            }
+            if has_normals {
+                for component in normal {
+                    AttributeType::FLOAT.write_f64(component, &mut attributes);
+                }
+            }
+            Point::with_attributes(position, attributes)
+        };
+
+        let mut points: Vec<Point> = (0..self.len())
+            .map(|i| point(self.positions[i].clone(), color(i), normal(i)))
+            .collect();
+
+        if let Some(density) = options.sample_density {
+            let mut rng = StdRng::seed_from_u64(options.seed);
+            for &[a, b, c] in &self.triangles {
+                let corners = [a, b, c].map(|i| self.positions[i].to_array());
+                let expected = triangle_area(&corners) * density;
+                let mut count = expected.floor() as usize;
+                if rng.gen::<f64>() < expected.fract() {
+                    count += 1;
+                }
+                for _ in 0..count {
+                    let r1 = rng.gen::<f64>().sqrt();
+                    let r2 = rng.gen::<f64>();
+                    let weights = [1.0 - r1, r1 * (1.0 - r2), r1 * r2];
+                    let [x, y, z] = interpolate(&corners, &weights);
+                    let mut n = interpolate(&[a, b, c].map(normal), &weights);
+                    let length = n.iter().map(|v| v * v).sum::<f64>().sqrt();
+                    if length > 0.0 {
+                        n = n.map(|v| v / length);
+                    }
+                    points.push(point(
+                        Vector3 { x, y, z },
+                        interpolate(&[a, b, c].map(color), &weights),
+                        n,
+                    ));
+                }
+            }
+        }
+
+        PointCloud::new(points, Attributes::from_attributes(attribute_list))
+    }
+}
+
+fn interpolate(values: &[[f64; 3]; 3], weights: &[f64; 3]) -> [f64; 3] {
+    [0, 1, 2].map(|axis| (0..3).map(|i| values[i][axis] * weights[i]).sum())
+}
+
+fn triangle_area([a, b, c]: &[[f64; 3]; 3]) -> f64 {
+    let u = [0, 1, 2].map(|i| b[i] - a[i]);
+    let v = [0, 1, 2].map(|i| c[i] - a[i]);
+    let cross = [
+        u[1] * v[2] - u[2] * v[1],
+        u[2] * v[0] - u[0] * v[2],
+        u[0] * v[1] - u[1] * v[0],
+    ];
+    cross.iter().map(|v| v * v).sum::<f64>().sqrt() / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangle() -> Mesh {
+        let mut mesh = Mesh::default();
+        mesh.push_vertex(
+            Vector3 {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            Some([0.0; 3]),
+            None,
+        );
+        mesh.push_vertex(
+            Vector3 {
+                x: 10.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            None,
+            None,
+        );
+        mesh.push_vertex(
+            Vector3 {
+                x: 0.0,
+                y: 10.0,
+                z: 0.0,
+            },
+            None,
+            None,
+        );
+        mesh.triangles.push([0, 1, 2]);
+        mesh
+    }
+
+    #[test]
+    fn test_vertices_only() {
+        let cloud = triangle().into_point_cloud(&MeshOptions::default());
+        assert_eq!(cloud.len(), 3);
+        assert!(cloud.attributes.get("normal").is_none());
+        let white = &cloud.points[1].attributes;
+        assert_eq!(white[..], [255, 255, 255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn test_sample_surface() {
+        let options = MeshOptions {
+            sample_density: Some(2.0),
+            seed: 7,
+        };
+        let cloud = triangle().into_point_cloud(&options);
+        // Area 50 at 2 points per square unit.
+        assert_eq!(cloud.len(), 103);
+        for point in &cloud.points[3..] {
+            let Vector3 { x, y, z } = point.position;
+            assert!(x >= 0.0 && y >= 0.0 && x + y <= 10.0 && z == 0.0);
+        }
+
+        let again = triangle().into_point_cloud(&options);
+        let positions = |cloud: &PointCloud| -> Vec<[f64; 3]> {
+            cloud.points.iter().map(|p| p.position.to_array()).collect()
+        };
+        assert_eq!(positions(&cloud), positions(&again));
+    }
+}
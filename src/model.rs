@@ -1,9 +1,15 @@
 pub mod attributes;
 pub mod bounds;
+pub mod cloud_js;
+pub mod ept;
 pub mod hierarchy;
 pub mod metadata;
 pub mod node;
 pub mod options;
+pub mod point;
+pub mod point_cloud;
+pub mod tile_index;
+pub mod tileset;
 pub mod vector3;
 
 pub struct State {
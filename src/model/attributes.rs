@@ -1,7 +1,8 @@
 use crate::model::vector3::Vector3;
+use byteorder::{ByteOrder, LittleEndian};
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
 pub enum AttributeType {
 	INT8 = 0,
 	INT16 = 1,
@@ -19,6 +20,85 @@ pub enum AttributeType {
 	UNDEFINED = 123456,
 }
 
+impl AttributeType {
+	/// Size in bytes of a single element of this type.
+	pub fn size(&self) -> i32 {
+		match self {
+			AttributeType::INT8 | AttributeType::UINT8 => 1,
+			AttributeType::INT16 | AttributeType::UINT16 => 2,
+			AttributeType::INT32 | AttributeType::UINT32 | AttributeType::FLOAT => 4,
+			AttributeType::INT64 | AttributeType::UINT64 | AttributeType::DOUBLE => 8,
+			AttributeType::UNDEFINED => 1,
+		}
+	}
+
+	/// Type name as used in potree's `metadata.json`.
+	pub fn name(&self) -> &'static str {
+		match self {
+			AttributeType::INT8 => "int8",
+			AttributeType::INT16 => "int16",
+			AttributeType::INT32 => "int32",
+			AttributeType::INT64 => "int64",
+			AttributeType::UINT8 => "uint8",
+			AttributeType::UINT16 => "uint16",
+			AttributeType::UINT32 => "uint32",
+			AttributeType::UINT64 => "uint64",
+			AttributeType::FLOAT => "float",
+			AttributeType::DOUBLE => "double",
+			AttributeType::UNDEFINED => "undefined",
+		}
+	}
+
+	pub fn from_name(name: &str) -> AttributeType {
+		match name {
+			"int8" => AttributeType::INT8,
+			"int16" => AttributeType::INT16,
+			"int32" => AttributeType::INT32,
+			"int64" => AttributeType::INT64,
+			"uint8" => AttributeType::UINT8,
+			"uint16" => AttributeType::UINT16,
+			"uint32" => AttributeType::UINT32,
+			"uint64" => AttributeType::UINT64,
+			"float" => AttributeType::FLOAT,
+			"double" => AttributeType::DOUBLE,
+			_ => AttributeType::UNDEFINED,
+		}
+	}
+
+	/// Appends `value` converted to this type, saturating at the bounds of integer types.
+	pub fn write_f64(&self, value: f64, buf: &mut Vec<u8>) {
+		match self {
+			AttributeType::INT8 => buf.push(value as i8 as u8),
+			AttributeType::UINT8 | AttributeType::UNDEFINED => buf.push(value as u8),
+			AttributeType::INT16 => buf.extend_from_slice(&(value as i16).to_le_bytes()),
+			AttributeType::UINT16 => buf.extend_from_slice(&(value as u16).to_le_bytes()),
+			AttributeType::INT32 => buf.extend_from_slice(&(value as i32).to_le_bytes()),
+			AttributeType::UINT32 => buf.extend_from_slice(&(value as u32).to_le_bytes()),
+			AttributeType::INT64 => buf.extend_from_slice(&(value as i64).to_le_bytes()),
+			AttributeType::UINT64 => buf.extend_from_slice(&(value as u64).to_le_bytes()),
+			AttributeType::FLOAT => buf.extend_from_slice(&(value as f32).to_le_bytes()),
+			AttributeType::DOUBLE => buf.extend_from_slice(&value.to_le_bytes()),
+		}
+	}
+
+	/// Reads a single element of this type from the start of `bytes`.
+	pub fn read_f64(&self, bytes: &[u8]) -> f64 {
+		match self {
+			AttributeType::INT8 => bytes[0] as i8 as f64,
+			AttributeType::UINT8 | AttributeType::UNDEFINED => bytes[0] as f64,
+			AttributeType::INT16 => LittleEndian::read_i16(bytes) as f64,
+			AttributeType::UINT16 => LittleEndian::read_u16(bytes) as f64,
+			AttributeType::INT32 => LittleEndian::read_i32(bytes) as f64,
+			AttributeType::UINT32 => LittleEndian::read_u32(bytes) as f64,
+			AttributeType::INT64 => LittleEndian::read_i64(bytes) as f64,
+			AttributeType::UINT64 => LittleEndian::read_u64(bytes) as f64,
+			AttributeType::FLOAT => LittleEndian::read_f32(bytes) as f64,
+			AttributeType::DOUBLE => LittleEndian::read_f64(bytes),
+		}
+	}
+}
+
+#[derive(Clone)]
 pub struct Attribute {
 	pub name: String,
 	pub description: String,
@@ -31,6 +111,35 @@ pub struct Attribute {
 	pub max: Vector3,
 }
 
+impl Attribute {
+	pub fn new(name: &str, r#type: AttributeType, num_elements: i32) -> Attribute {
+		Attribute {
+			name: name.to_string(),
+			description: "".to_string(),
+			size: r#type.size() * num_elements,
+			num_elements,
+			element_size: r#type.size(),
+			r#type,
+			min: Vector3::infinity(),
+			max: Vector3::infinity() * -1.0,
+		}
+	}
+
+	fn update_range(&mut self, bytes: &[u8]) {
+		let element_size = self.element_size as usize;
+		let min = [&mut self.min.x, &mut self.min.y, &mut self.min.z];
+		for (i, min) in min.into_iter().enumerate().take(self.num_elements as usize) {
+			*min = min.min(self.r#type.read_f64(&bytes[i * element_size..]));
+		}
+		let max = [&mut self.max.x, &mut self.max.y, &mut self.max.z];
+		for (i, max) in max.into_iter().enumerate().take(self.num_elements as usize) {
+			*max = max.max(self.r#type.read_f64(&bytes[i * element_size..]));
+		}
+	}
+}
+
+/// Per-point attributes stored after the position, in the order of `list`.
+#[derive(Clone)]
 pub struct Attributes {
 	pub list: Vec<Attribute>,
 	pub bytes: i32,
@@ -59,7 +168,7 @@ impl Attributes {
 
 	pub fn from_attributes(attributes: Vec<Attribute>) -> Attributes {
 		Attributes {
-			bytes: 0,
+			bytes: attributes.iter().map(|attribute| attribute.size).sum(),
 			pos_scale: Vector3 {
 				x: 1.0,
 				y: 1.0,
@@ -74,7 +183,7 @@ impl Attributes {
 		}
 	}
 
-	fn get_offset(&self, name: String) -> i32 {
+	pub fn get_offset(&self, name: &str) -> i32 {
 		let mut offset = 0;
 
 		for attribute in &self.list {
@@ -88,7 +197,7 @@ impl Attributes {
 		return -1;
 	}
 
-	fn get(&self, name: String) -> Option<&Attribute> {
+	pub fn get(&self, name: &str) -> Option<&Attribute> {
 		for attribute in &self.list {
 			if attribute.name == name {
 				return Some(&attribute);
@@ -96,4 +205,13 @@ impl Attributes {
 		}
 		return None;
 	}
+
+	/// Widens the min and max of every attribute to include the packed values of a point.
+	pub fn update_ranges(&mut self, point_attributes: &[u8]) {
+		let mut offset = 0;
+		for attribute in &mut self.list {
+			attribute.update_range(&point_attributes[offset..]);
+			offset += attribute.size as usize;
+		}
+	}
 }
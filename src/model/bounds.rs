@@ -1,3 +1,4 @@
+use crate::model::point::Point;
 use crate::model::vector3::Vector3;
 use ord_subset::OrdSubsetIterExt;
 
@@ -39,13 +40,46 @@ impl Bounds {
         let new_uz = self.lz + max_size;
         Bounds::new(new_ux, new_uy, new_uz, self.lx, self.ly, self.lz)
     }
+
+    /// Bounds of the octant `index`, where x, y and z select the upper half with bits 4, 2
+    /// and 1 as in node names.
+    pub fn child(&self, index: usize) -> Bounds {
+        let mid = [
+            (self.lx + self.ux) / 2.0,
+            (self.ly + self.uy) / 2.0,
+            (self.lz + self.uz) / 2.0,
+        ];
+        let (mut lower, mut upper) = ([self.lx, self.ly, self.lz], mid);
+        for (axis, bit) in [4, 2, 1].into_iter().enumerate() {
+            if index & bit != 0 {
+                lower[axis] = mid[axis];
+                upper[axis] = [self.ux, self.uy, self.uz][axis];
+            }
+        }
+        Bounds::new(upper[0], upper[1], upper[2], lower[0], lower[1], lower[2])
+    }
+
+    pub fn contains(&self, position: &Vector3) -> bool {
+        (self.lx..=self.ux).contains(&position.x)
+            && (self.ly..=self.uy).contains(&position.y)
+            && (self.lz..=self.uz).contains(&position.z)
+    }
+
+    pub fn intersects(&self, other: &Bounds) -> bool {
+        self.lx <= other.ux
+            && other.lx <= self.ux
+            && self.ly <= other.uy
+            && other.ly <= self.uy
+            && self.lz <= other.uz
+            && other.lz <= self.uz
+    }
 }
 
 #[allow(dead_code)]
-pub fn find_bounds(points: &Vec<Vector3>) -> Bounds {
-    let xs: Vec<f64> = points.iter().map(|p| p.x).collect();
-    let ys: Vec<f64> = points.iter().map(|p| p.y).collect();
-    let zs: Vec<f64> = points.iter().map(|p| p.z).collect();
+pub fn find_bounds(points: &[Point]) -> Bounds {
+    let xs: Vec<f64> = points.iter().map(|p| p.position.x).collect();
+    let ys: Vec<f64> = points.iter().map(|p| p.position.y).collect();
+    let zs: Vec<f64> = points.iter().map(|p| p.position.z).collect();
 
     Bounds::new(
         *xs.iter().ord_subset_max().unwrap(),
@@ -0,0 +1,42 @@
+use serde::{Deserialize, Serialize};
+
+use crate::model::bounds::Bounds;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BoundingBox {
+    pub lx: f64,
+    pub ly: f64,
+    pub lz: f64,
+    pub ux: f64,
+    pub uy: f64,
+    pub uz: f64,
+}
+
+impl BoundingBox {
+    pub fn from_bounds(bounds: &Bounds) -> BoundingBox {
+        BoundingBox {
+            lx: bounds.lx,
+            ly: bounds.ly,
+            lz: bounds.lz,
+            ux: bounds.ux,
+            uy: bounds.uy,
+            uz: bounds.uz,
+        }
+    }
+}
+
+/// The `cloud.js` describing a Potree 1.x dataset.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CloudJs {
+    pub version: String,
+    pub octree_dir: String,
+    pub projection: String,
+    pub points: u64,
+    pub bounding_box: BoundingBox,
+    pub tight_bounding_box: BoundingBox,
+    pub point_attributes: Vec<String>,
+    pub spacing: f64,
+    pub scale: f64,
+    pub hierarchy_step_size: usize,
+}
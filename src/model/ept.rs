@@ -0,0 +1,43 @@
+use serde::{Deserialize, Serialize};
+
+/// A dimension of the point records in `ept-data`, in record order.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Dimension {
+    pub name: String,
+    /// `signed`, `unsigned` or `float`.
+    #[serde(rename = "type")]
+    pub r#type: String,
+    pub size: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scale: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Srs {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub authority: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub horizontal: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wkt: Option<String>,
+}
+
+/// The `ept.json` of an Entwine Point Tile dataset.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Ept {
+    /// Cubic bounds of the root node as min x, y, z then max x, y, z.
+    pub bounds: [f64; 6],
+    /// Bounds of the points.
+    pub bounds_conforming: [f64; 6],
+    pub data_type: String,
+    pub hierarchy_type: String,
+    pub points: u64,
+    pub schema: Vec<Dimension>,
+    pub span: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub srs: Option<Srs>,
+    pub version: String,
+}
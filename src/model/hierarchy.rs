@@ -1,7 +1,6 @@
 use byteorder::{LittleEndian, WriteBytesExt};
 use std::cmp::max;
-use std::collections::HashMap;
-use std::convert::TryInto;
+use std::collections::BTreeMap;
 use std::mem;
 
 use super::node::Node;
@@ -96,7 +95,9 @@ fn create_hierarchy_chunks(root: &Node, hierarchy_step_size: usize) -> Vec<Hiera
     hierarchy_chunks
 }
 
-pub fn create_hierarchy(root: &Node, node_hierarchy: HashMap<String, (u32, u32)>) -> Hierarchy {
+/// Encodes the hierarchy of the tree below `root`, with the byte size, byte offset and
+/// point count each node was written with in `node_hierarchy`.
+pub fn create_hierarchy(root: &Node, node_hierarchy: BTreeMap<String, (u32, u32, u32)>) -> Hierarchy {
     const HIERARCHY_STEP_SIZE: u8 = 4;
     // type + childMask + numPoints + offset + size
     const BYTES_PER_NODE: usize = 1 + 1 + 4 + 8 + 8;
@@ -105,7 +106,7 @@ pub fn create_hierarchy(root: &Node, node_hierarchy: HashMap<String, (u32, u32)>
         chunk.nodes.len() * BYTES_PER_NODE
     }
     let mut chunks = create_hierarchy_chunks(root, HIERARCHY_STEP_SIZE.into());
-    let mut chunk_pointers: HashMap<String, usize> = HashMap::new();
+    let mut chunk_pointers: BTreeMap<String, usize> = BTreeMap::new();
     let mut chunk_byte_offsets = vec![0; chunks.len()];
     let mut hierarchy_buffer_size = 0;
     let mut depth = 0;
@@ -131,7 +132,9 @@ pub fn create_hierarchy(root: &Node, node_hierarchy: HashMap<String, (u32, u32)>
             let child_mask = child_mask_of(&node);
             let target_offset: u64;
             let target_size: u64;
-            let num_points: u32 = node.num_points().try_into().unwrap();
+            // Empty nodes are not written to the octree.
+            let (byte_size, byte_offset, num_points) =
+                node_hierarchy.get(&node.name).copied().unwrap_or((0, 0, 0));
             let mut node_type: u8 = if node.is_leaf_node() {
                 Type::Leaf as u8
             } else {
@@ -144,7 +147,6 @@ pub fn create_hierarchy(root: &Node, node_hierarchy: HashMap<String, (u32, u32)>
                 target_offset = chunk_byte_offsets[target_chunk_index] as u64;
                 target_size = chunk_size(target_chunk) as u64;
             } else {
-                let (byte_size, byte_offset) = node_hierarchy[&node.name];
                 target_offset = byte_offset as u64;
                 target_size = byte_size as u64;
             }
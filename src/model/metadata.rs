@@ -6,16 +6,21 @@ use crate::model::options::Encoding;
 use crate::model::options::Options;
 use crate::model::State;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+#[cfg(feature = "tsify")]
+use tsify::Tsify;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "tsify", derive(Tsify))]
 #[serde(rename_all = "camelCase")]
 pub struct Hierarchy {
-    first_chunk_size: u16,
-    step_size: u8,
-    depth: u8,
+    pub first_chunk_size: u16,
+    pub step_size: u8,
+    pub depth: u8,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "tsify", derive(Tsify))]
 #[serde(rename_all = "camelCase")]
 pub struct Attribute {
     pub name: String,
@@ -29,7 +34,7 @@ pub struct Attribute {
 }
 
 impl Attribute {
-    fn from_attribute(attribute: &InternalAttribute) -> Attribute {
+    pub fn from_attribute(attribute: &InternalAttribute) -> Attribute {
         let InternalAttribute {
             name,
             description,
@@ -59,20 +64,22 @@ impl Attribute {
             size: *size as u8,
             num_elements: *num_elements as u8,
             element_size: *element_size as u8,
-            r#type: serde_json::to_string(&r#type).unwrap(),
+            r#type: r#type.name().to_string(),
             min,
             max,
         }
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "tsify", derive(Tsify))]
 pub struct BoundingBox {
-    min: [f64; 3],
-    max: [f64; 3],
+    pub min: [f64; 3],
+    pub max: [f64; 3],
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "tsify", derive(Tsify), tsify(into_wasm_abi))]
 #[serde(rename_all = "camelCase")]
 pub struct Metadata {
     pub version: String,
@@ -87,6 +94,9 @@ pub struct Metadata {
     pub bounding_box: BoundingBox,
     pub encoding: Encoding,
     pub attributes: Vec<Attribute>,
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    #[cfg_attr(feature = "tsify", tsify(type = "Record<string, unknown>", optional))]
+    pub extras: Map<String, Value>,
 }
 
 impl Metadata {
@@ -121,6 +131,7 @@ impl Metadata {
             },
             encoding: options.encoding,
             attributes,
+            extras: Map::new(),
         }
     }
 }
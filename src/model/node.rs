@@ -1,3 +1,4 @@
+use crate::model::point::Point;
 use crate::model::vector3::Vector3;
 
 use super::bounds::Bounds;
@@ -8,7 +9,7 @@ pub struct Node {
     pub children: [Option<Box<Node>>; 8],
     max_points_per_leaf_node: u32,
     pub grid: NodeGrid,
-    pub initial_store: Vec<Vector3>,
+    pub initial_store: Vec<Point>,
     squared_spacing: f64,
     pub name: String,
     pub byte_size: u32,
@@ -41,15 +42,28 @@ impl Node {
         return self.name.len() - 1;
     }
 
+    /// The key `[level, x, y, z]` addressing the node among the octants of its level, as
+    /// used by COPC and EPT, read from the child indices in its name.
+    pub fn voxel_key(&self) -> [i32; 4] {
+        let mut key = [self.level() as i32, 0, 0, 0];
+        for digit in self.name[1..].bytes() {
+            let index = (digit - b'0') as i32;
+            key[1] = key[1] << 1 | (index >> 2 & 1);
+            key[2] = key[2] << 1 | (index >> 1 & 1);
+            key[3] = key[3] << 1 | (index & 1);
+        }
+        key
+    }
+
     pub fn is_leaf_node(&self) -> bool {
         self.children.iter().all(|child| child.is_none())
     }
 
-    pub fn points(&self) -> Vec<&Vector3> {
+    pub fn points(&self) -> Vec<&Point> {
         if self.is_leaf_node() {
             return self.initial_store.iter().collect();
         } else {
-            let mut point_vectors: Vec<&Vector3> = Vec::new();
+            let mut point_vectors: Vec<&Point> = Vec::new();
             for i in 0..8 {
                 for j in 0..8 {
                     point_vectors.append(&mut self.grid[i][j].iter().collect());
@@ -60,6 +74,30 @@ impl Node {
         }
     }
 
+    /// Consumes the node and its children, returning every point they hold.
+    pub fn into_points(self) -> Vec<Point> {
+        let mut points = self.initial_store;
+        for row in self.grid {
+            for cell in row {
+                points.extend(cell);
+            }
+        }
+        for child in self.children.into_iter().flatten() {
+            points.extend(child.into_points());
+        }
+        points
+    }
+
+    /// Drops the points of the node and its children once they are written, keeping the
+    /// nodes for the hierarchy.
+    pub(crate) fn clear_points(&mut self) {
+        self.initial_store = Vec::new();
+        self.grid = empty_grid_array();
+        for child in self.children.iter_mut().flatten() {
+            child.clear_points();
+        }
+    }
+
     pub fn num_points(&self) -> usize {
         if self.is_leaf_node() {
             self.initial_store.len()
@@ -74,20 +112,25 @@ impl Node {
         }
     }
 
-    pub fn add_point(&mut self, point: Vector3) {
+    /// Points are sampled in the order they are added: a point stays in this node unless an
+    /// earlier point of its grid cell is within the spacing, and points on a mid plane go to
+    /// the upper octant. The same points in the same order always give the same tree.
+    pub fn add_point(&mut self, point: Point) {
         if self.is_leaf_node() {
-            self.initial_store.push(point.clone());
+            let index = Node::find_grid_index(&point.position, &self.bounds);
+            self.initial_store.push(point);
             if self.initial_store.len() >= self.max_points_per_leaf_node as usize {
-                let index = Node::find_grid_index(&point, &self.bounds);
                 self.split(index.into())
             }
         } else {
-            let grid_index_outer = Node::find_grid_index(&point, &self.bounds);
-            let grid_index_inner =
-                Node::find_grid_index(&point, &self.compute_child_bounds(grid_index_outer));
+            let grid_index_outer = Node::find_grid_index(&point.position, &self.bounds);
+            let grid_index_inner = Node::find_grid_index(
+                &point.position,
+                &self.compute_child_bounds(grid_index_outer),
+            );
             if self.grid[grid_index_outer][grid_index_inner]
                 .iter()
-                .any(|p| Node::within_distance(&p, &point, self.squared_spacing))
+                .any(|p| Node::within_distance(&p.position, &point.position, self.squared_spacing))
             {
                 match &mut self.children[grid_index_outer] {
                     None => {
@@ -103,6 +146,21 @@ impl Node {
         }
     }
 
+    /// Puts back points read from a written node, into the store of a leaf or the grid
+    /// cells of an inner node, without sampling them again.
+    pub fn restore_points(&mut self, points: Vec<Point>) {
+        if self.is_leaf_node() {
+            self.initial_store.extend(points);
+            return;
+        }
+        for point in points {
+            let outer = Node::find_grid_index(&point.position, &self.bounds);
+            let inner =
+                Node::find_grid_index(&point.position, &self.compute_child_bounds(outer));
+            self.grid[outer][inner].push(point);
+        }
+    }
+
     fn split(&mut self, index: usize) {
         self.children[index] = Some(Box::new(self.new_child_node(index)));
         // We should probably not clone here and deal with how to do this better
@@ -112,7 +170,7 @@ impl Node {
         self.initial_store = Vec::new();
     }
 
-    fn new_child_node(&self, index: usize) -> Node {
+    pub(crate) fn new_child_node(&self, index: usize) -> Node {
         Node::new(
             format!("{}{}", &self.name, index.to_string()),
             self.spacing / 2.0,
@@ -128,7 +186,7 @@ impl Node {
         let z_diff = (a.z - b.z) * (a.z - b.z);
         (x_diff + y_diff + z_diff) < squared_distance
     }
-    fn find_grid_index(point: &Vector3, bounds: &Bounds) -> usize {
+    pub(crate) fn find_grid_index(point: &Vector3, bounds: &Bounds) -> usize {
         let low_x = point.x < (bounds.lx + bounds.ux) / 2.0; //lower than mid x
         let low_y = point.y < (bounds.ly + bounds.uy) / 2.0; //lower than mid y
         let low_z = point.z < (bounds.lz + bounds.uz) / 2.0; //lower than mid z
@@ -232,7 +290,7 @@ impl Node {
     }
 }
 
-pub type GridRow = [Vec<Vector3>; 8];
+pub type GridRow = [Vec<Point>; 8];
 pub type NodeGrid = [GridRow; 8];
 
 pub fn empty_child_node_array() -> [Option<Box<Node>>; 8] {
@@ -1,4 +1,6 @@
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "tsify")]
+use tsify::Tsify;
 
 pub struct Options {
 	pub keep_chunks: bool,
@@ -8,6 +10,7 @@ pub struct Options {
 
 #[derive(Serialize, Deserialize)]
 #[derive(Copy, Clone)]
+#[cfg_attr(feature = "tsify", derive(Tsify))]
 pub enum Encoding {
 	DEFAULT,
 	BROTLI,
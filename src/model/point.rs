@@ -0,0 +1,24 @@
+use crate::model::vector3::Vector3;
+
+#[derive(Clone)]
+pub struct Point {
+    pub position: Vector3,
+    /// Attribute values packed in the order of `Attributes::list`, little endian.
+    pub attributes: Vec<u8>,
+}
+
+impl Point {
+    pub fn new(position: Vector3) -> Point {
+        Point {
+            position,
+            attributes: Vec::new(),
+        }
+    }
+
+    pub fn with_attributes(position: Vector3, attributes: Vec<u8>) -> Point {
+        Point {
+            position,
+            attributes,
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use crate::model::attributes::Attributes;
+use crate::model::point::Point;
+use crate::potree::{BuildOptions, Potree};
+
+/// Points in the order they were read, before they are indexed into an octree.
+pub struct PointCloud {
+    pub points: Vec<Point>,
+    pub attributes: Attributes,
+    /// Coordinate reference system as WKT or an authority code, if the input records one.
+    pub crs: Option<String>,
+    /// Resolution the positions were stored with, if they were read from integer
+    /// coordinates.
+    pub scale: Option<f64>,
+}
+
+impl PointCloud {
+    pub fn new(points: Vec<Point>, attributes: Attributes) -> PointCloud {
+        PointCloud {
+            points,
+            attributes,
+            crs: None,
+            scale: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// Indexes the points into an octree with the default build options.
+    pub fn into_potree(self) -> Potree {
+        self.into_potree_with_options(&BuildOptions::default())
+    }
+
+    /// Indexes points that are already in memory, as [`Potree::from_reader`] does for a
+    /// [`PointCloudReader`](crate::point_reader::PointCloudReader) over them.
+    pub fn into_potree_with_options(self, options: &BuildOptions) -> Potree {
+        let mut potree = Potree::with_attributes(
+            self.points,
+            self.attributes,
+            options.point_per_leaf_node_limit,
+        );
+        potree.crs = options.crs.clone().or(self.crs);
+        potree
+    }
+}
@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+
+use crate::model::metadata::BoundingBox;
+
+/// A dataset of the tile grid, stored in the directory `name`.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Tile {
+    pub name: String,
+    /// Bounds of the points in the tile, not of its grid cell.
+    pub bounds: BoundingBox,
+    pub points: u64,
+}
+
+/// The `index.json` listing the tiles a tiled conversion wrote, for viewers that load them
+/// on demand.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TileIndex {
+    /// Edge length of the grid cells in units of the coordinate reference system.
+    pub tile_size: f64,
+    pub projection: String,
+    pub tiles: Vec<Tile>,
+}
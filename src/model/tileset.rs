@@ -0,0 +1,41 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Asset {
+    pub version: String,
+}
+
+/// A `box` of center and half axes, or a `region` of west, south, east, north in radians
+/// and heights in meters.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub enum BoundingVolume {
+    Box([f64; 12]),
+    Region([f64; 6]),
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Content {
+    pub uri: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Tile {
+    pub bounding_volume: BoundingVolume,
+    pub geometric_error: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refine: Option<String>,
+    pub content: Content,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<Tile>,
+}
+
+/// The `tileset.json` of a 3D Tiles dataset.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Tileset {
+    pub asset: Asset,
+    pub geometric_error: f64,
+    pub root: Tile,
+}
@@ -0,0 +1,473 @@
+use core::fmt;
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+use crate::model::attributes::AttributeType;
+
+const MAGIC: &[u8] = b"\x93NUMPY";
+
+#[derive(Debug)]
+pub enum NpyError {
+    InvalidHeader { msg: String },
+    UnsupportedDtype { descr: String },
+    Truncated,
+}
+
+impl fmt::Display for NpyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NpyError::InvalidHeader { msg } => write!(f, "Invalid npy header: {}", msg),
+            NpyError::UnsupportedDtype { descr } => write!(f, "Unsupported npy dtype '{}'", descr),
+            NpyError::Truncated => write!(f, "npy data is shorter than its shape"),
+        }
+    }
+}
+
+impl std::error::Error for NpyError {}
+
+fn invalid(msg: &str) -> NpyError {
+    NpyError::InvalidHeader {
+        msg: msg.to_string(),
+    }
+}
+
+/// The Python literals used in npy headers.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Literal {
+    Str(String),
+    Int(i64),
+    Bool(bool),
+    /// A tuple or list.
+    Seq(Vec<Literal>),
+    Dict(Vec<(String, Literal)>),
+}
+
+impl Literal {
+    fn get(&self, key: &str) -> Option<&Literal> {
+        match self {
+            Literal::Dict(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+}
+
+struct LiteralParser<'a> {
+    text: &'a [u8],
+    position: usize,
+}
+
+impl<'a> LiteralParser<'a> {
+    fn skip_whitespace(&mut self) {
+        while self
+            .text
+            .get(self.position)
+            .is_some_and(|c| c.is_ascii_whitespace())
+        {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.text.get(self.position).copied()
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), NpyError> {
+        if self.peek() != Some(c) {
+            return Err(invalid(&format!("expected '{}'", c as char)));
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    /// Parses items up to `close`, allowing a trailing comma.
+    fn items<T>(
+        &mut self,
+        close: u8,
+        mut item: impl FnMut(&mut Self) -> Result<T, NpyError>,
+    ) -> Result<Vec<T>, NpyError> {
+        let mut items = Vec::new();
+        loop {
+            if self.peek() == Some(close) {
+                self.position += 1;
+                return Ok(items);
+            }
+            items.push(item(self)?);
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(c) if c == close => {}
+                _ => return Err(invalid("unterminated sequence")),
+            }
+        }
+    }
+
+    fn literal(&mut self) -> Result<Literal, NpyError> {
+        match self.peek() {
+            Some(quote @ (b'\'' | b'"')) => {
+                let start = self.position + 1;
+                let len = self.text[start..]
+                    .iter()
+                    .position(|&c| c == quote)
+                    .ok_or_else(|| invalid("unterminated string"))?;
+                self.position = start + len + 1;
+                Ok(Literal::Str(
+                    String::from_utf8_lossy(&self.text[start..start + len]).to_string(),
+                ))
+            }
+            Some(open @ (b'(' | b'[')) => {
+                self.position += 1;
+                let close = if open == b'(' { b')' } else { b']' };
+                Ok(Literal::Seq(self.items(close, Self::literal)?))
+            }
+            Some(b'{') => {
+                self.position += 1;
+                let entries = self.items(b'}', |parser| {
+                    let key = match parser.literal()? {
+                        Literal::Str(key) => key,
+                        _ => return Err(invalid("dict keys must be strings")),
+                    };
+                    parser.expect(b':')?;
+                    Ok((key, parser.literal()?))
+                })?;
+                Ok(Literal::Dict(entries))
+            }
+            Some(_) => {
+                let start = self.position;
+                while self
+                    .text
+                    .get(self.position)
+                    .is_some_and(|c| c.is_ascii_alphanumeric() || *c == b'-')
+                {
+                    self.position += 1;
+                }
+                match &self.text[start..self.position] {
+                    b"True" => Ok(Literal::Bool(true)),
+                    b"False" => Ok(Literal::Bool(false)),
+                    token => String::from_utf8_lossy(token)
+                        .parse::<i64>()
+                        .map(Literal::Int)
+                        .map_err(|_| invalid("unexpected token")),
+                }
+            }
+            None => Err(invalid("unexpected end of header")),
+        }
+    }
+}
+
+/// A numeric element type such as `<f4` or `|u1`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScalarType {
+    /// `f`, `i`, `u` or `b` for float, signed, unsigned and bool.
+    pub kind: u8,
+    pub size: usize,
+    pub big_endian: bool,
+}
+
+impl ScalarType {
+    pub fn parse(descr: &str) -> Result<ScalarType, NpyError> {
+        let unsupported = || NpyError::UnsupportedDtype {
+            descr: descr.to_string(),
+        };
+        let bytes = descr.as_bytes();
+        let (big_endian, rest) = match bytes.first() {
+            Some(b'>') => (true, &bytes[1..]),
+            Some(b'<' | b'|' | b'=') => (false, &bytes[1..]),
+            _ => (false, bytes),
+        };
+        let (&kind, size) = rest.split_first().ok_or_else(unsupported)?;
+        let size: usize = std::str::from_utf8(size)
+            .ok()
+            .and_then(|size| size.parse().ok())
+            .ok_or_else(unsupported)?;
+        match (kind, size) {
+            (b'f', 4 | 8) | (b'i' | b'u', 1 | 2 | 4 | 8) | (b'b', 1) => Ok(ScalarType {
+                kind,
+                size,
+                big_endian,
+            }),
+            _ => Err(unsupported()),
+        }
+    }
+
+    pub fn attribute_type(&self) -> AttributeType {
+        match (self.kind, self.size) {
+            (b'f', 4) => AttributeType::FLOAT,
+            (b'f', _) => AttributeType::DOUBLE,
+            (b'i', 1) => AttributeType::INT8,
+            (b'i', 2) => AttributeType::INT16,
+            (b'i', 4) => AttributeType::INT32,
+            (b'i', _) => AttributeType::INT64,
+            (_, 1) => AttributeType::UINT8,
+            (_, 2) => AttributeType::UINT16,
+            (_, 4) => AttributeType::UINT32,
+            _ => AttributeType::UINT64,
+        }
+    }
+
+    pub fn read_f64(&self, bytes: &[u8]) -> f64 {
+        macro_rules! read {
+            ($method:ident) => {
+                if self.big_endian {
+                    BigEndian::$method(bytes) as f64
+                } else {
+                    LittleEndian::$method(bytes) as f64
+                }
+            };
+        }
+        match (self.kind, self.size) {
+            (b'f', 4) => read!(read_f32),
+            (b'f', _) => read!(read_f64),
+            (b'i', 1) => bytes[0] as i8 as f64,
+            (b'i', 2) => read!(read_i16),
+            (b'i', 4) => read!(read_i32),
+            (b'i', _) => read!(read_i64),
+            (_, 1) => bytes[0] as f64,
+            (_, 2) => read!(read_u16),
+            (_, 4) => read!(read_u32),
+            _ => read!(read_u64),
+        }
+    }
+}
+
+/// A parsed `.npy` file with a numeric dtype.
+pub struct NpyArray<'a> {
+    pub dtype: ScalarType,
+    pub fortran_order: bool,
+    pub shape: Vec<usize>,
+    pub data: &'a [u8],
+}
+
+/// Splits an npy file into its header dictionary and data.
+pub fn parse_header(buf: &[u8]) -> Result<(Literal, &[u8]), NpyError> {
+    if !buf.starts_with(MAGIC) || buf.len() < 10 {
+        return Err(invalid("missing \\x93NUMPY magic"));
+    }
+    let (header_len, header_start) = match buf[6] {
+        1 => (LittleEndian::read_u16(&buf[8..10]) as usize, 10),
+        2 | 3 => (
+            LittleEndian::read_u32(buf.get(8..12).ok_or(NpyError::Truncated)?) as usize,
+            12,
+        ),
+        version => return Err(invalid(&format!("unknown version {}", version))),
+    };
+    let header = buf
+        .get(header_start..header_start + header_len)
+        .ok_or(NpyError::Truncated)?;
+    let mut parser = LiteralParser {
+        text: header,
+        position: 0,
+    };
+    let dict = parser.literal()?;
+    if !matches!(dict, Literal::Dict(_)) {
+        return Err(invalid("header is not a dict"));
+    }
+    Ok((dict, &buf[header_start + header_len..]))
+}
+
+/// Reads the `fortran_order` and `shape` entries of a header.
+pub fn header_layout(header: &Literal) -> Result<(bool, Vec<usize>), NpyError> {
+    let fortran_order = match header.get("fortran_order") {
+        Some(Literal::Bool(fortran_order)) => *fortran_order,
+        _ => return Err(invalid("missing 'fortran_order'")),
+    };
+    let shape = match header.get("shape") {
+        Some(Literal::Seq(dims)) => dims
+            .iter()
+            .map(|dim| match dim {
+                Literal::Int(dim) if *dim >= 0 => Ok(*dim as usize),
+                _ => Err(invalid("shape must hold non-negative integers")),
+            })
+            .collect::<Result<Vec<usize>, NpyError>>()?,
+        _ => return Err(invalid("missing 'shape'")),
+    };
+    Ok((fortran_order, shape))
+}
+
+/// A field of a structured dtype.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NpyField {
+    pub name: String,
+    pub r#type: ScalarType,
+    /// Number of elements, from the optional subarray shape.
+    pub count: usize,
+    /// Byte offset within a record.
+    pub offset: usize,
+}
+
+/// A parsed `.npy` file with a structured dtype, one record per element.
+pub struct StructuredArray<'a> {
+    pub fields: Vec<NpyField>,
+    pub itemsize: usize,
+    pub shape: Vec<usize>,
+    pub data: &'a [u8],
+}
+
+impl StructuredArray<'_> {
+    pub fn records(&self) -> impl Iterator<Item = &[u8]> {
+        self.data.chunks_exact(self.itemsize)
+    }
+}
+
+pub enum Npy<'a> {
+    Array(NpyArray<'a>),
+    Structured(StructuredArray<'a>),
+}
+
+/// Parses the fields of a structured dtype such as `[('x', '<f4'), ('rgb', '|u1', (3,))]`.
+///
+/// Void entries like `('', '|V4')`, which numpy adds for aligned dtypes, only advance the offset.
+fn parse_fields(descr: &[Literal]) -> Result<(Vec<NpyField>, usize), NpyError> {
+    let unsupported = |entry: &Literal| NpyError::UnsupportedDtype {
+        descr: format!("{:?}", entry),
+    };
+    let mut fields = Vec::new();
+    let mut offset = 0;
+    for entry in descr {
+        let (name, r#type, shape) = match entry {
+            Literal::Seq(items) => match items.as_slice() {
+                [Literal::Str(name), Literal::Str(r#type)] => (name, r#type, None),
+                [Literal::Str(name), Literal::Str(r#type), shape] => (name, r#type, Some(shape)),
+                _ => return Err(unsupported(entry)),
+            },
+            _ => return Err(unsupported(entry)),
+        };
+        if let Some(void) = r#type
+            .trim_start_matches(['|', '<', '>', '='])
+            .strip_prefix('V')
+        {
+            offset += void.parse::<usize>().map_err(|_| unsupported(entry))?;
+            continue;
+        }
+        let count = match shape {
+            None => 1,
+            Some(Literal::Int(count)) if *count >= 0 => *count as usize,
+            Some(Literal::Seq(dims)) => dims.iter().try_fold(1, |count, dim| match dim {
+                Literal::Int(dim) if *dim >= 0 => Ok(count * *dim as usize),
+                _ => Err(unsupported(entry)),
+            })?,
+            Some(_) => return Err(unsupported(entry)),
+        };
+        let r#type = ScalarType::parse(r#type)?;
+        fields.push(NpyField {
+            name: name.to_string(),
+            r#type,
+            count,
+            offset,
+        });
+        offset += r#type.size * count;
+    }
+    Ok((fields, offset))
+}
+
+/// Parses an npy file with either a numeric or a structured dtype.
+pub fn parse(buf: &[u8]) -> Result<Npy<'_>, NpyError> {
+    let (header, data) = parse_header(buf)?;
+    let (fortran_order, shape) = header_layout(&header)?;
+    let elements = shape.iter().product::<usize>();
+
+    match header.get("descr") {
+        Some(Literal::Str(descr)) => {
+            let dtype = ScalarType::parse(descr)?;
+            let data = data
+                .get(..elements * dtype.size)
+                .ok_or(NpyError::Truncated)?;
+            Ok(Npy::Array(NpyArray {
+                dtype,
+                fortran_order,
+                shape,
+                data,
+            }))
+        }
+        Some(Literal::Seq(descr)) => {
+            let (fields, itemsize) = parse_fields(descr)?;
+            if itemsize == 0 {
+                return Err(invalid("structured dtype without fields"));
+            }
+            let data = data.get(..elements * itemsize).ok_or(NpyError::Truncated)?;
+            Ok(Npy::Structured(StructuredArray {
+                fields,
+                itemsize,
+                shape,
+                data,
+            }))
+        }
+        Some(other) => Err(NpyError::UnsupportedDtype {
+            descr: format!("{:?}", other),
+        }),
+        None => Err(invalid("missing 'descr'")),
+    }
+}
+
+impl<'a> NpyArray<'a> {
+    /// Parses an npy file whose dtype is a single numeric type.
+    pub fn parse(buf: &'a [u8]) -> Result<NpyArray<'a>, NpyError> {
+        match parse(buf)? {
+            Npy::Array(array) => Ok(array),
+            Npy::Structured(_) => Err(NpyError::UnsupportedDtype {
+                descr: "structured".to_string(),
+            }),
+        }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.shape.first().copied().unwrap_or(1)
+    }
+
+    pub fn columns(&self) -> usize {
+        self.shape.iter().skip(1).product()
+    }
+
+    /// Value at `row` and `column` of a one or two dimensional array.
+    pub fn get(&self, row: usize, column: usize) -> f64 {
+        let index = if self.fortran_order {
+            column * self.rows() + row
+        } else {
+            row * self.columns() + column
+        };
+        let offset = index * self.dtype.size;
+        self.dtype
+            .read_f64(&self.data[offset..offset + self.dtype.size])
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::npy::{NpyArray, NpyError};
+
+    /// Builds a version 1.0 npy file around `data`.
+    pub(crate) fn npy(descr: &str, fortran_order: bool, shape: &str, data: &[u8]) -> Vec<u8> {
+        let order = if fortran_order { "True" } else { "False" };
+        let mut header = format!(
+            "{{'descr': {}, 'fortran_order': {}, 'shape': {}, }}",
+            descr, order, shape
+        );
+        while (10 + header.len() + 1) % 64 != 0 {
+            header.push(' ');
+        }
+        header.push('\n');
+        let mut buf = b"\x93NUMPY\x01\x00".to_vec();
+        buf.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        buf.extend_from_slice(header.as_bytes());
+        buf.extend_from_slice(data);
+        buf
+    }
+
+    #[test]
+    fn test_parse_npy() -> Result<(), NpyError> {
+        let data: Vec<u8> = (0..6i16).flat_map(|v| v.to_be_bytes()).collect();
+        let buf = npy("'>i2'", true, "(3, 2)", &data);
+        let array = NpyArray::parse(&buf)?;
+
+        assert_eq!(array.shape, [3, 2]);
+        assert_eq!((array.rows(), array.columns()), (3, 2));
+        // Column-major, so the second column holds 3, 4, 5.
+        assert_eq!(array.get(1, 1), 4.0);
+
+        assert!(matches!(
+            NpyArray::parse(&npy("'<c8'", false, "(1,)", &[0; 8])),
+            Err(NpyError::UnsupportedDtype { .. })
+        ));
+
+        Ok(())
+    }
+}
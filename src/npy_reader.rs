@@ -0,0 +1,384 @@
+use core::fmt;
+use std::io::{Cursor, Read};
+
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+use crate::model::attributes::{Attribute, Attributes};
+use crate::model::point::Point;
+use crate::model::point_cloud::PointCloud;
+use crate::npy::{self, Npy, NpyArray, NpyError, ScalarType, StructuredArray};
+use crate::model::vector3::Vector3;
+
+/// Names of the array holding the points in an npz archive, in order of preference.
+const NPZ_POINT_ARRAYS: [&str; 2] = ["points", "xyz"];
+
+/// Names of a structured field holding all three coordinates.
+const POSITION_FIELDS: [&str; 4] = ["xyz", "position", "positions", "points"];
+
+#[derive(Debug)]
+pub enum NpyReadError {
+    Npy { name: String, error: NpyError },
+    Zip(ZipError),
+    InvalidShape { name: String, shape: Vec<usize> },
+    NoArrays,
+    NoPoints,
+}
+
+impl fmt::Display for NpyReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NpyReadError::Npy { name, error } => write!(f, "Array '{}': {}", name, error),
+            NpyReadError::Zip(error) => write!(f, "Could not read npz archive: {}", error),
+            NpyReadError::InvalidShape { name, shape } => write!(
+                f,
+                "Array '{}' of shape {:?} does not hold points with at least 3 columns",
+                name, shape
+            ),
+            NpyReadError::NoArrays => write!(f, "No .npy arrays in npz archive"),
+            NpyReadError::NoPoints => write!(f, "No points in array"),
+        }
+    }
+}
+
+impl std::error::Error for NpyReadError {}
+
+impl From<ZipError> for NpyReadError {
+    fn from(error: ZipError) -> Self {
+        NpyReadError::Zip(error)
+    }
+}
+
+enum Source<'b, 'a> {
+    Array(&'b NpyArray<'a>),
+    Field(&'b StructuredArray<'a>, usize),
+}
+
+/// The values of one attribute or coordinate: `count` consecutive columns of a plain
+/// array or elements of a structured field, starting at `first`.
+struct Channel<'b, 'a> {
+    name: String,
+    r#type: ScalarType,
+    count: usize,
+    first: usize,
+    source: Source<'b, 'a>,
+}
+
+impl Channel<'_, '_> {
+    fn value(&self, row: usize, element: usize) -> f64 {
+        let index = self.first + element;
+        match self.source {
+            Source::Array(array) => array.get(row, index),
+            Source::Field(array, field) => {
+                let size = self.r#type.size;
+                let offset = row * array.itemsize + array.fields[field].offset + index * size;
+                self.r#type.read_f64(&array.data[offset..offset + size])
+            }
+        }
+    }
+}
+
+fn rows(npy: &Npy) -> usize {
+    let shape = match npy {
+        Npy::Array(array) => &array.shape,
+        Npy::Structured(array) => &array.shape,
+    };
+    shape.first().copied().unwrap_or(1)
+}
+
+/// One channel for a plain array, named `name`, or one per field of a structured array.
+fn channels<'b, 'a>(name: &str, npy: &'b Npy<'a>) -> Vec<Channel<'b, 'a>> {
+    match npy {
+        Npy::Array(array) => vec![Channel {
+            name: name.to_string(),
+            r#type: array.dtype,
+            count: array.columns(),
+            first: 0,
+            source: Source::Array(array),
+        }],
+        Npy::Structured(array) => array
+            .fields
+            .iter()
+            .enumerate()
+            .map(|(field, npy_field)| Channel {
+                name: npy_field.name.clone(),
+                r#type: npy_field.r#type,
+                count: npy_field.count,
+                first: 0,
+                source: Source::Field(array, field),
+            })
+            .collect(),
+    }
+}
+
+type PositionChannels<'b, 'a> = (Vec<Channel<'b, 'a>>, Vec<Channel<'b, 'a>>);
+
+/// Splits the point array into its three position channels and the remaining attributes.
+///
+/// Plain arrays hold the position in their first three columns. Structured arrays hold it
+/// in fields named x, y and z, a three element field such as `xyz` or `position`, or
+/// otherwise their first three single value fields.
+fn position_channels<'b, 'a>(
+    name: &str,
+    npy: &'b Npy<'a>,
+) -> Result<PositionChannels<'b, 'a>, NpyReadError> {
+    let invalid_shape = |shape: &[usize]| NpyReadError::InvalidShape {
+        name: name.to_string(),
+        shape: shape.to_vec(),
+    };
+    let array = match npy {
+        Npy::Array(array) => {
+            if array.shape.len() != 2 || array.columns() < 3 {
+                return Err(invalid_shape(&array.shape));
+            }
+            let column = |first: usize, name: String| Channel {
+                name,
+                r#type: array.dtype,
+                count: 1,
+                first,
+                source: Source::Array(array),
+            };
+            let position = (0..3).map(|i| column(i, String::new())).collect();
+            let attributes = (3..array.columns())
+                .map(|i| column(i, format!("column_{}", i)))
+                .collect();
+            return Ok((position, attributes));
+        }
+        Npy::Structured(array) => array,
+    };
+
+    let mut channels = channels(name, npy);
+    let find = |channels: &[Channel], names: &[&str], count: usize| {
+        channels.iter().position(|channel| {
+            channel.count == count && names.iter().any(|n| channel.name.eq_ignore_ascii_case(n))
+        })
+    };
+
+    if let Some(index) = find(&channels, &POSITION_FIELDS, 3) {
+        let field = channels.remove(index);
+        let Source::Field(array, field_index) = field.source else {
+            unreachable!("structured arrays only have field channels")
+        };
+        let position = (0..3)
+            .map(|i| Channel {
+                name: String::new(),
+                r#type: field.r#type,
+                count: 1,
+                first: i,
+                source: Source::Field(array, field_index),
+            })
+            .collect();
+        return Ok((position, channels));
+    }
+
+    let indices = match ["x", "y", "z"].map(|axis| find(&channels, &[axis], 1)) {
+        [Some(x), Some(y), Some(z)] => vec![x, y, z],
+        _ => (0..channels.len())
+            .filter(|&i| channels[i].count == 1)
+            .take(3)
+            .collect(),
+    };
+    if indices.len() != 3 {
+        return Err(invalid_shape(&array.shape));
+    }
+    let mut slots: Vec<Option<Channel>> = channels.into_iter().map(Some).collect();
+    let position = indices.iter().map(|&i| slots[i].take().unwrap()).collect();
+    Ok((position, slots.into_iter().flatten().collect()))
+}
+
+fn parse_npy<'a>(name: &str, buf: &'a [u8]) -> Result<Npy<'a>, NpyReadError> {
+    npy::parse(buf).map_err(|error| NpyReadError::Npy {
+        name: name.to_string(),
+        error,
+    })
+}
+
+/// Builds the points from the position array and attribute arrays of the same length.
+fn read_arrays(
+    name: &str,
+    points: &Npy,
+    others: &[(String, Npy)],
+) -> Result<PointCloud, NpyReadError> {
+    let (position, mut attribute_channels) = position_channels(name, points)?;
+    for (name, npy) in others {
+        attribute_channels.extend(channels(name, npy));
+    }
+    let attribute_list: Vec<Attribute> = attribute_channels
+        .iter()
+        .map(|channel| {
+            Attribute::new(
+                &channel.name,
+                channel.r#type.attribute_type(),
+                channel.count as i32,
+            )
+        })
+        .collect();
+
+    let mut cloud_points = Vec::with_capacity(rows(points));
+    for row in 0..rows(points) {
+        let [x, y, z] = [0, 1, 2].map(|i| position[i].value(row, 0));
+        if x.is_nan() || y.is_nan() || z.is_nan() {
+            continue;
+        }
+        let mut attributes = Vec::new();
+        for (channel, attribute) in attribute_channels.iter().zip(&attribute_list) {
+            for element in 0..channel.count {
+                attribute
+                    .r#type
+                    .write_f64(channel.value(row, element), &mut attributes);
+            }
+        }
+        cloud_points.push(Point::with_attributes(Vector3 { x, y, z }, attributes));
+    }
+    if cloud_points.is_empty() {
+        return Err(NpyReadError::NoPoints);
+    }
+
+    Ok(PointCloud::new(
+        cloud_points,
+        Attributes::from_attributes(attribute_list),
+    ))
+}
+
+/// Reads an `.npy` array of shape (N, C) or a structured array of N records.
+///
+/// Plain arrays hold x, y and z in their first three columns and keep the rest as
+/// attributes named `column_<index>`. Structured arrays take the position from fields
+/// named x, y and z or a three element `xyz`/`position` field, falling back to their
+/// first three fields, and keep all other fields as attributes of the same type and count.
+/// Rows with a NaN coordinate are skipped.
+pub fn read_npy(buf: &[u8]) -> Result<PointCloud, NpyReadError> {
+    let npy = parse_npy("", buf)?;
+    read_arrays("", &npy, &[])
+}
+
+/// Reads an `.npz` archive as written by `numpy.savez`.
+///
+/// The points come from the array named `points` or `xyz`, or the first array if there is
+/// neither, and are read as by [`read_npy`]. Every other array with one row per point
+/// becomes an attribute named after it, such as `intensity` of shape (N,) or `colors` of
+/// shape (N, 3). Arrays of other lengths are ignored.
+pub fn read_npz(buf: &[u8]) -> Result<PointCloud, NpyReadError> {
+    let mut archive = ZipArchive::new(Cursor::new(buf))?;
+    let mut files: Vec<(String, Vec<u8>)> = Vec::new();
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        let name = match file.name().strip_suffix(".npy") {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+        let mut data = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut data).map_err(ZipError::Io)?;
+        files.push((name, data));
+    }
+
+    let mut arrays = Vec::with_capacity(files.len());
+    for (name, data) in &files {
+        arrays.push((name.clone(), parse_npy(name, data)?));
+    }
+    let index = NPZ_POINT_ARRAYS
+        .iter()
+        .find_map(|point_name| arrays.iter().position(|(name, _)| name == point_name))
+        .unwrap_or(0);
+    if arrays.is_empty() {
+        return Err(NpyReadError::NoArrays);
+    }
+    let (name, points) = arrays.remove(index);
+    arrays.retain(|(_, npy)| rows(npy) == rows(&points));
+
+    read_arrays(&name, &points, &arrays)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::archive::{write_archive, Compression};
+    use crate::model::attributes::AttributeType;
+    use crate::npy::tests::npy;
+    use crate::npy_reader::{read_npy, read_npz, NpyReadError};
+
+    fn f32_bytes(values: &[f32]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn test_read_npy_columns() -> Result<(), NpyReadError> {
+        let data = f32_bytes(&[
+            0.0,
+            1.0,
+            2.0,
+            0.5,
+            3.0,
+            4.0,
+            5.0,
+            0.7,
+            f32::NAN,
+            0.0,
+            0.0,
+            0.0,
+        ]);
+        let potree = read_npy(&npy("'<f4'", false, "(3, 4)", &data))?.into_potree();
+
+        assert_eq!(potree.size, 2);
+        assert_eq!((potree.bounds.lx, potree.bounds.ux), (0.0, 3.0));
+        let column = potree.attributes.get("column_3").unwrap();
+        assert_eq!(column.r#type, AttributeType::FLOAT);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_npy_structured() -> Result<(), NpyReadError> {
+        let mut data = Vec::new();
+        for i in 0..2u8 {
+            data.extend_from_slice(&(i as f64).to_be_bytes());
+            data.extend_from_slice(&[0; 2]);
+            data.extend(f32_bytes(&[10.0, 20.0]));
+            data.extend_from_slice(&[i, 2 * i, 3 * i]);
+        }
+        let buf = npy(
+            "[('z', '>f8'), ('', '|V2'), ('x', '<f4'), ('y', '<f4'), ('rgb', '|u1', (3,))]",
+            false,
+            "(2,)",
+            &data,
+        );
+        let cloud = read_npy(&buf)?;
+
+        assert_eq!(cloud.points[1].position.to_array(), [10.0, 20.0, 1.0]);
+        let rgb = cloud.attributes.get("rgb").unwrap();
+        assert_eq!((rgb.r#type, rgb.num_elements), (AttributeType::UINT8, 3));
+        assert_eq!(cloud.points[1].attributes, [1, 2, 3]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_npz() -> Result<(), Box<dyn std::error::Error>> {
+        let xyz = npy(
+            "[('xyz', '<f4', (3,))]",
+            false,
+            "(2,)",
+            &f32_bytes(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]),
+        );
+        let labels = npy("'<u2'", false, "(2,)", &[7, 0, 9, 0]);
+        let pose = npy("'<f8'", false, "(4, 4)", &[0; 128]);
+        let files: [(&str, &[u8]); 3] = [
+            ("labels.npy", &labels),
+            ("pose.npy", &pose),
+            ("xyz.npy", &xyz),
+        ];
+        let archive = write_archive(Cursor::new(Vec::new()), &files, Compression::Deflate)?;
+        let potree = read_npz(&archive.into_inner())?.into_potree();
+
+        assert_eq!(potree.size, 2);
+        assert_eq!((potree.bounds.uz, potree.attributes.list.len()), (6.0, 1));
+        let labels = potree.attributes.get("labels").unwrap();
+        assert_eq!((labels.min.x, labels.max.x), (7.0, 9.0));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,183 @@
+use core::fmt;
+
+use crate::mesh::{Mesh, MeshOptions};
+use crate::model::point_cloud::PointCloud;
+use crate::model::vector3::Vector3;
+
+#[derive(Debug)]
+pub enum ObjReadError {
+    InvalidValue { line: usize, value: String },
+    InvalidIndex { line: usize, index: String },
+    NoPoints,
+}
+
+impl fmt::Display for ObjReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjReadError::InvalidValue { line, value } => {
+                write!(f, "Line {}: could not parse '{}' as a number", line, value)
+            }
+            ObjReadError::InvalidIndex { line, index } => {
+                write!(f, "Line {}: invalid vertex index '{}'", line, index)
+            }
+            ObjReadError::NoPoints => write!(f, "No vertices in obj"),
+        }
+    }
+}
+
+impl std::error::Error for ObjReadError {}
+
+/// Resolves a one-based or negative relative OBJ index against `count` elements.
+fn resolve_index(index: &str, count: usize, line: usize) -> Result<usize, ObjReadError> {
+    let invalid = || ObjReadError::InvalidIndex {
+        line,
+        index: index.to_string(),
+    };
+    let value: i64 = index.parse().map_err(|_| invalid())?;
+    let resolved = if value < 0 {
+        count as i64 + value
+    } else {
+        value - 1
+    };
+    if resolved < 0 || resolved >= count as i64 {
+        return Err(invalid());
+    }
+    Ok(resolved as usize)
+}
+
+/// Reads the vertices of a Wavefront OBJ file as points.
+///
+/// Vertices may carry a color as `v x y z r g b`, in the range 0..1 or 0..255. Vertex
+/// normals are taken from the first face referencing each vertex. With a sample density in
+/// `options` the faces are triangulated and sampled as well.
+pub fn read_obj(buf: &[u8], options: &MeshOptions) -> Result<PointCloud, ObjReadError> {
+    let text = String::from_utf8_lossy(buf);
+    let mut mesh = Mesh::default();
+    let mut normals: Vec<[f64; 3]> = Vec::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let line_number = i + 1;
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let values: Vec<&str> = tokens.collect();
+        let numbers = |values: &[&str]| {
+            values
+                .iter()
+                .map(|value| {
+                    value
+                        .parse::<f64>()
+                        .map_err(|_| ObjReadError::InvalidValue {
+                            line: line_number,
+                            value: value.to_string(),
+                        })
+                })
+                .collect::<Result<Vec<f64>, _>>()
+        };
+
+        match keyword {
+            "v" if values.len() >= 3 => {
+                let numbers = numbers(&values)?;
+                // Four values are a homogeneous coordinate, six or seven a color.
+                let color = (numbers.len() >= 6).then(|| [numbers[3], numbers[4], numbers[5]]);
+                mesh.push_vertex(
+                    Vector3 {
+                        x: numbers[0],
+                        y: numbers[1],
+                        z: numbers[2],
+                    },
+                    color,
+                    None,
+                );
+            }
+            "vn" if values.len() >= 3 => {
+                let numbers = numbers(&values[..3])?;
+                normals.push([numbers[0], numbers[1], numbers[2]]);
+            }
+            "f" => {
+                let mut corners = Vec::with_capacity(values.len());
+                for value in &values {
+                    let mut parts = value.split('/');
+                    let vertex = resolve_index(parts.next().unwrap(), mesh.len(), line_number)?;
+                    if let Some(normal) = parts.nth(1).filter(|normal| !normal.is_empty()) {
+                        let normal = resolve_index(normal, normals.len(), line_number)?;
+                        mesh.normals[vertex].get_or_insert(normals[normal]);
+                    }
+                    corners.push(vertex);
+                }
+                for j in 2..corners.len() {
+                    mesh.triangles
+                        .push([corners[0], corners[j - 1], corners[j]]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if mesh.is_empty() {
+        return Err(ObjReadError::NoPoints);
+    }
+
+    let max_channel = mesh
+        .colors
+        .iter()
+        .flatten()
+        .flatten()
+        .fold(0.0_f64, |max, &channel| max.max(channel));
+    let scale = if max_channel <= 1.0 { 65535.0 } else { 257.0 };
+    for color in mesh.colors.iter_mut().flatten() {
+        *color = color.map(|channel| (channel * scale).clamp(0.0, 65535.0));
+    }
+
+    Ok(mesh.into_point_cloud(options))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CUBE_FACE: &str = "# quad
+v 0 0 0 1 0 0
+v 1 0 0 0 1 0
+v 1 1 0 0 0 1
+v 0 1 0 1 1 1
+vn 0 0 1
+f 1//1 2//1 3//1 -1//1
+";
+
+    #[test]
+    fn test_read_obj() {
+        let cloud = read_obj(CUBE_FACE.as_bytes(), &MeshOptions::default()).unwrap();
+        assert_eq!(cloud.len(), 4);
+        let names: Vec<&str> = cloud
+            .attributes
+            .list
+            .iter()
+            .map(|a| a.name.as_str())
+            .collect();
+        assert_eq!(names, ["rgb", "normal"]);
+        assert_eq!(cloud.points[0].attributes[..6], [255, 255, 0, 0, 0, 0]);
+        assert_eq!(cloud.points[3].attributes[14..18], 1.0_f32.to_le_bytes());
+    }
+
+    #[test]
+    fn test_sample_faces() {
+        let options = MeshOptions {
+            sample_density: Some(10.0),
+            seed: 1,
+        };
+        let cloud = read_obj(CUBE_FACE.as_bytes(), &options).unwrap();
+        assert_eq!(cloud.len(), 14);
+    }
+
+    #[test]
+    fn test_invalid_index() {
+        let result = read_obj(b"v 0 0 0\nf 1 2 3\n", &MeshOptions::default());
+        assert!(matches!(
+            result,
+            Err(ObjReadError::InvalidIndex { line: 2, .. })
+        ));
+    }
+}
@@ -1,22 +1,432 @@
-use pcd_rs::{anyhow, DynReader, DynRecord};
+use core::fmt;
 
-use crate::{model::vector3::Vector3, potree::Potree};
+use byteorder::{ByteOrder, LittleEndian};
 
-pub fn from_pcd(buf: &[u8]) -> Result<Potree, Box<dyn std::error::Error>> {
-    let reader = DynReader::from_bytes(buf)?;
-    let pcd: Result<Vec<DynRecord>, anyhow::Error> = reader.collect();
+use crate::model::attributes::{Attribute, AttributeType, Attributes};
+use crate::model::point::Point;
+use crate::model::point_cloud::PointCloud;
+use crate::model::vector3::Vector3;
 
-    let mut points: Vec<Vector3> = Vec::new();
+#[derive(Debug)]
+pub enum PcdReadError {
+    InvalidHeader { line: usize, msg: String },
+    InvalidValue { line: usize, value: String },
+    Unsupported { msg: String },
+    Truncated,
+    Decompression { msg: String },
+    NoPoints,
+}
+
+impl fmt::Display for PcdReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PcdReadError::InvalidHeader { line, msg } => {
+                write!(f, "Line {}: invalid pcd header: {}", line, msg)
+            }
+            PcdReadError::InvalidValue { line, value } => {
+                write!(f, "Line {}: could not parse '{}' as a number", line, value)
+            }
+            PcdReadError::Unsupported { msg } => write!(f, "Unsupported pcd: {}", msg),
+            PcdReadError::Truncated => write!(f, "Pcd data is truncated"),
+            PcdReadError::Decompression { msg } => {
+                write!(f, "Could not decompress binary_compressed data: {}", msg)
+            }
+            PcdReadError::NoPoints => write!(f, "No points in pcd"),
+        }
+    }
+}
+
+impl std::error::Error for PcdReadError {}
+
+#[derive(Copy, Clone, PartialEq)]
+enum DataKind {
+    Ascii,
+    Binary,
+    BinaryCompressed,
+}
+
+struct PcdField {
+    name: String,
+    r#type: AttributeType,
+    count: usize,
+}
+
+impl PcdField {
+    fn size(&self) -> usize {
+        self.r#type.size() as usize * self.count
+    }
+
+    fn is_color(&self) -> bool {
+        (self.name == "rgb" || self.name == "rgba") && self.r#type.size() == 4 && self.count == 1
+    }
+}
+
+struct PcdHeader {
+    fields: Vec<PcdField>,
+    points: usize,
+    data: DataKind,
+    /// Byte offset of the data section.
+    data_start: usize,
+    /// Line number of the first data line, for ascii errors.
+    data_line: usize,
+}
+
+fn field_type(size: usize, type_char: &str) -> Option<AttributeType> {
+    match (type_char, size) {
+        ("I", 1) => Some(AttributeType::INT8),
+        ("I", 2) => Some(AttributeType::INT16),
+        ("I", 4) => Some(AttributeType::INT32),
+        ("I", 8) => Some(AttributeType::INT64),
+        ("U", 1) => Some(AttributeType::UINT8),
+        ("U", 2) => Some(AttributeType::UINT16),
+        ("U", 4) => Some(AttributeType::UINT32),
+        ("U", 8) => Some(AttributeType::UINT64),
+        ("F", 4) => Some(AttributeType::FLOAT),
+        ("F", 8) => Some(AttributeType::DOUBLE),
+        _ => None,
+    }
+}
 
-    let mut num_points = 0;
-    for point in pcd?.iter() {
-        let [x, y, z] = point.to_owned().xyz::<f64>().unwrap();
-        points.push(Vector3 { x, y, z });
+fn parse_header(buf: &[u8]) -> Result<PcdHeader, PcdReadError> {
+    let mut names: Vec<String> = Vec::new();
+    let mut sizes: Vec<usize> = Vec::new();
+    let mut types: Vec<String> = Vec::new();
+    let mut counts: Vec<usize> = Vec::new();
+    let mut width = 0;
+    let mut height = 1;
+    let mut points = None;
 
-        num_points = num_points + 1;
+    let mut offset = 0;
+    let mut line = 0;
+    while offset < buf.len() {
+        let end = buf[offset..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(buf.len(), |i| offset + i + 1);
+        let text = String::from_utf8_lossy(&buf[offset..end]);
+        offset = end;
+        line += 1;
+
+        let mut tokens = text.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) if !keyword.starts_with('#') => keyword.to_ascii_uppercase(),
+            _ => continue,
+        };
+        let values: Vec<&str> = tokens.collect();
+        let invalid = |msg: &str| PcdReadError::InvalidHeader {
+            line,
+            msg: msg.to_string(),
+        };
+        let numbers = |values: &[&str]| {
+            values
+                .iter()
+                .map(|v| v.parse::<usize>().map_err(|_| invalid(&format!("invalid number '{}'", v))))
+                .collect::<Result<Vec<usize>, PcdReadError>>()
+        };
+
+        match keyword.as_str() {
+            "FIELDS" | "COLUMNS" => names = values.iter().map(|v| v.to_string()).collect(),
+            "SIZE" => sizes = numbers(&values)?,
+            "TYPE" => types = values.iter().map(|v| v.to_ascii_uppercase()).collect(),
+            "COUNT" => counts = numbers(&values)?,
+            "WIDTH" => width = *numbers(&values)?.first().ok_or_else(|| invalid("missing WIDTH"))?,
+            "HEIGHT" => height = *numbers(&values)?.first().ok_or_else(|| invalid("missing HEIGHT"))?,
+            "POINTS" => points = numbers(&values)?.first().copied(),
+            "VERSION" | "VIEWPOINT" => {}
+            "DATA" => {
+                let data = match values.first().map(|v| v.to_ascii_lowercase()).as_deref() {
+                    Some("ascii") => DataKind::Ascii,
+                    Some("binary") => DataKind::Binary,
+                    Some("binary_compressed") => DataKind::BinaryCompressed,
+                    _ => return Err(invalid("unknown DATA kind")),
+                };
+                if counts.is_empty() {
+                    counts = vec![1; names.len()];
+                }
+                if sizes.len() != names.len() || types.len() != names.len() || counts.len() != names.len() {
+                    return Err(invalid("SIZE, TYPE and COUNT must have one entry per field"));
+                }
+                let mut fields = Vec::new();
+                for i in 0..names.len() {
+                    let r#type = field_type(sizes[i], &types[i]).ok_or_else(|| {
+                        invalid(&format!("unsupported TYPE {} with SIZE {}", types[i], sizes[i]))
+                    })?;
+                    fields.push(PcdField {
+                        name: names[i].clone(),
+                        r#type,
+                        count: counts[i],
+                    });
+                }
+                return Ok(PcdHeader {
+                    fields,
+                    points: points.unwrap_or(width * height),
+                    data,
+                    data_start: offset,
+                    data_line: line + 1,
+                });
+            }
+            _ => return Err(invalid(&format!("unknown keyword {}", keyword))),
+        }
     }
 
-    let potree = Potree::new(points, 20000);
+    Err(PcdReadError::InvalidHeader {
+        line,
+        msg: "missing DATA line".to_string(),
+    })
+}
 
-    Ok(potree)
+/// Decompresses LZF data as written by PCL for `binary_compressed`.
+fn lzf_decompress(input: &[u8], output_size: usize) -> Result<Vec<u8>, PcdReadError> {
+    let error = |msg: &str| PcdReadError::Decompression {
+        msg: msg.to_string(),
+    };
+    let mut output: Vec<u8> = Vec::with_capacity(output_size);
+    let mut ip = 0;
+    while ip < input.len() {
+        let ctrl = input[ip] as usize;
+        ip += 1;
+        if ctrl < 32 {
+            let run = ctrl + 1;
+            let literal = input.get(ip..ip + run).ok_or_else(|| error("literal past end of input"))?;
+            output.extend_from_slice(literal);
+            ip += run;
+        } else {
+            let mut len = ctrl >> 5;
+            if len == 7 {
+                len += *input.get(ip).ok_or_else(|| error("length past end of input"))? as usize;
+                ip += 1;
+            }
+            let low = *input.get(ip).ok_or_else(|| error("reference past end of input"))? as usize;
+            ip += 1;
+            let distance = ((ctrl & 0x1f) << 8) + low + 1;
+            if distance > output.len() {
+                return Err(error("back reference before start of output"));
+            }
+            let start = output.len() - distance;
+            for i in 0..len + 2 {
+                output.push(output[start + i]);
+            }
+        }
+        if output.len() > output_size {
+            return Err(error("output larger than declared size"));
+        }
+    }
+    if output.len() != output_size {
+        return Err(error("output smaller than declared size"));
+    }
+    Ok(output)
+}
+
+/// Converts data of any kind into row-major little endian records.
+fn records(buf: &[u8], header: &PcdHeader) -> Result<Vec<u8>, PcdReadError> {
+    let record_size: usize = header.fields.iter().map(|f| f.size()).sum();
+    let data = &buf[header.data_start..];
+
+    match header.data {
+        DataKind::Binary => data
+            .get(..header.points * record_size)
+            .map(|records| records.to_vec())
+            .ok_or(PcdReadError::Truncated),
+        DataKind::BinaryCompressed => {
+            if data.len() < 8 {
+                return Err(PcdReadError::Truncated);
+            }
+            let compressed_size = LittleEndian::read_u32(&data[0..4]) as usize;
+            let uncompressed_size = LittleEndian::read_u32(&data[4..8]) as usize;
+            let compressed = data.get(8..8 + compressed_size).ok_or(PcdReadError::Truncated)?;
+            let columns = lzf_decompress(compressed, uncompressed_size)?;
+            if columns.len() < header.points * record_size {
+                return Err(PcdReadError::Truncated);
+            }
+
+            // Compressed data stores every field for all points before the next field.
+            let mut records = vec![0u8; header.points * record_size];
+            let mut column_start = 0;
+            let mut field_offset = 0;
+            for field in &header.fields {
+                let size = field.size();
+                for i in 0..header.points {
+                    let source = column_start + i * size;
+                    let target = i * record_size + field_offset;
+                    records[target..target + size].copy_from_slice(&columns[source..source + size]);
+                }
+                column_start += size * header.points;
+                field_offset += size;
+            }
+            Ok(records)
+        }
+        DataKind::Ascii => {
+            let text = String::from_utf8_lossy(data);
+            let mut records = Vec::with_capacity(header.points * record_size);
+            let mut read = 0;
+            for (i, line) in text.lines().enumerate() {
+                let values: Vec<&str> = line.split_whitespace().collect();
+                if values.is_empty() || read == header.points {
+                    continue;
+                }
+                let mut values = values.into_iter();
+                for field in &header.fields {
+                    for _ in 0..field.count {
+                        let token = values.next().ok_or(PcdReadError::Truncated)?;
+                        let invalid = || PcdReadError::InvalidValue {
+                            line: header.data_line + i,
+                            value: token.to_string(),
+                        };
+                        if field.is_color() && field.r#type == AttributeType::FLOAT {
+                            // Packed colors are written as the float with the same bits.
+                            let value = token.parse::<f32>().map_err(|_| invalid())?;
+                            records.extend_from_slice(&value.to_le_bytes());
+                        } else {
+                            let value = token.parse::<f64>().map_err(|_| invalid())?;
+                            field.r#type.write_f64(value, &mut records);
+                        }
+                    }
+                }
+                read += 1;
+            }
+            if read < header.points {
+                return Err(PcdReadError::Truncated);
+            }
+            Ok(records)
+        }
+    }
+}
+
+/// Reads pcd files with ascii, binary or binary_compressed data.
+///
+/// Every field except the position becomes an attribute of the matching type and count.
+/// Packed `rgb`/`rgba` fields are decoded into a uint16 `rgb` attribute, padding fields
+/// named `_` are dropped and points with a NaN coordinate, as found in organized clouds,
+/// are skipped.
+pub fn read_pcd(buf: &[u8]) -> Result<PointCloud, PcdReadError> {
+    let header = parse_header(buf)?;
+    let records = records(buf, &header)?;
+
+    let mut offsets = Vec::new();
+    let mut offset = 0;
+    for field in &header.fields {
+        offsets.push(offset);
+        offset += field.size();
+    }
+    let record_size = offset;
+
+    let position_field = |name: &str| {
+        header
+            .fields
+            .iter()
+            .position(|f| f.name == name)
+            .ok_or_else(|| PcdReadError::Unsupported {
+                msg: format!("missing field '{}'", name),
+            })
+    };
+    let position = [position_field("x")?, position_field("y")?, position_field("z")?];
+
+    let mut attribute_fields = Vec::new();
+    let mut attribute_list = Vec::new();
+    for (i, field) in header.fields.iter().enumerate() {
+        if position.contains(&i) || field.name == "_" {
+            continue;
+        }
+        if field.is_color() {
+            attribute_list.push(Attribute::new("rgb", AttributeType::UINT16, 3));
+        } else {
+            attribute_list.push(Attribute::new(&field.name, field.r#type, field.count as i32));
+        }
+        attribute_fields.push(i);
+    }
+
+    let mut points: Vec<Point> = Vec::with_capacity(header.points);
+    for record in records.chunks_exact(record_size.max(1)).take(header.points) {
+        let coordinate = |i: usize| header.fields[i].r#type.read_f64(&record[offsets[i]..]);
+        let (x, y, z) = (coordinate(position[0]), coordinate(position[1]), coordinate(position[2]));
+        if x.is_nan() || y.is_nan() || z.is_nan() {
+            continue;
+        }
+
+        let mut attributes = Vec::new();
+        for &i in &attribute_fields {
+            let field = &header.fields[i];
+            let bytes = &record[offsets[i]..offsets[i] + field.size()];
+            if field.is_color() {
+                let packed = LittleEndian::read_u32(bytes);
+                for shift in [16, 8, 0] {
+                    AttributeType::UINT16.write_f64(((packed >> shift) & 0xff) as f64, &mut attributes);
+                }
+            } else {
+                attributes.extend_from_slice(bytes);
+            }
+        }
+
+        points.push(Point::with_attributes(Vector3 { x, y, z }, attributes));
+    }
+    if points.is_empty() {
+        return Err(PcdReadError::NoPoints);
+    }
+
+    Ok(PointCloud::new(
+        points,
+        Attributes::from_attributes(attribute_list),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::model::attributes::AttributeType;
+    use crate::pcd_reader::{read_pcd, lzf_decompress, PcdReadError};
+    use crate::potree::Potree;
+
+    fn assert_fixture(potree: &Potree) {
+        // The organized 4x2 cloud has one NaN point.
+        assert_eq!(potree.size, 7);
+        let names: Vec<&str> = potree.attributes.list.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, ["intensity", "ring", "timestamp", "label", "rgb"]);
+        assert_eq!(potree.attributes.bytes, 4 + 2 + 8 + 4 + 6);
+
+        let ring = potree.attributes.get("ring").unwrap();
+        assert_eq!(ring.r#type, AttributeType::UINT16);
+        assert_eq!((ring.min.x, ring.max.x), (0.0, 1.0));
+        let timestamp = potree.attributes.get("timestamp").unwrap();
+        assert_eq!(timestamp.r#type, AttributeType::DOUBLE);
+        assert_eq!(timestamp.max.x, 1600000000.75);
+        let rgb = potree.attributes.get("rgb").unwrap();
+        assert_eq!(rgb.max.to_array(), [255.0, 128.0, 64.0]);
+        assert_eq!(rgb.min.to_array(), [0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_read_pcd_ascii() -> Result<(), Box<dyn std::error::Error>> {
+        let potree = read_pcd(&fs::read("resources/points_ascii.pcd")?)?.into_potree();
+        assert_fixture(&potree);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_pcd_binary() -> Result<(), Box<dyn std::error::Error>> {
+        let potree = read_pcd(&fs::read("resources/points_binary.pcd")?)?.into_potree();
+        assert_fixture(&potree);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_pcd_binary_compressed() -> Result<(), Box<dyn std::error::Error>> {
+        let potree = read_pcd(&fs::read("resources/points_binary_compressed.pcd")?)?.into_potree();
+        assert_fixture(&potree);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lzf_decompress() {
+        // "abc" literal followed by a back reference of length 6 at distance 3.
+        let compressed = [2, b'a', b'b', b'c', 4 << 5, 2];
+        assert_eq!(lzf_decompress(&compressed, 9).unwrap(), b"abcabcabc");
+        assert!(matches!(
+            lzf_decompress(&compressed, 12),
+            Err(PcdReadError::Decompression { .. })
+        ));
+    }
 }
@@ -0,0 +1,299 @@
+use core::fmt;
+use std::vec;
+
+use crate::csv_reader::read_csv;
+use crate::e57_reader::read_e57;
+use crate::gltf_reader::read_gltf;
+use crate::mesh::MeshOptions;
+use crate::model::attributes::Attributes;
+use crate::model::bounds::{find_bounds, Bounds};
+use crate::model::point::Point;
+use crate::model::point_cloud::PointCloud;
+use crate::npy_reader::{read_npy, read_npz};
+use crate::obj_reader::read_obj;
+use crate::pcd_reader::read_pcd;
+use crate::xyz_reader::{read_pts, read_xyz};
+
+/// Points handed out per batch by readers that hold all their points in memory.
+const BATCH_SIZE: usize = 65536;
+
+#[derive(Debug)]
+pub enum ReadError {
+    UnknownFormat { name: String },
+    Format(Box<dyn std::error::Error + Send + Sync>),
+    NoPoints,
+}
+
+impl ReadError {
+    pub fn format<E: std::error::Error + Send + Sync + 'static>(error: E) -> ReadError {
+        ReadError::Format(Box::new(error))
+    }
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadError::UnknownFormat { name } => write!(f, "No reader for '{}'", name),
+            ReadError::Format(error) => write!(f, "{}", error),
+            ReadError::NoPoints => write!(f, "No points to index"),
+        }
+    }
+}
+
+impl std::error::Error for ReadError {}
+
+/// What a reader knows about its points before handing out the first batch.
+#[derive(Clone)]
+pub struct PointHeader {
+    /// Number of points, if the format records it.
+    pub count: Option<u64>,
+    /// Bounds of every point, if the format records them. Readers with bounds are indexed
+    /// while reading, others are collected first.
+    pub bounds: Option<Bounds>,
+    /// Attributes packed into every point, in order.
+    pub attributes: Attributes,
+    /// Coordinate reference system as WKT or an authority code such as `EPSG:4326`.
+    pub crs: Option<String>,
+}
+
+pub trait PointReader {
+    fn header(&self) -> &PointHeader;
+
+    /// Reads the next batch of points, `None` once all points are read.
+    fn next_batch(&mut self) -> Option<Result<Vec<Point>, ReadError>>;
+}
+
+/// Hands out points a reader has already parsed in batches.
+pub struct PointCloudReader {
+    header: PointHeader,
+    points: vec::IntoIter<Point>,
+}
+
+impl PointCloudReader {
+    pub fn new(cloud: PointCloud) -> PointCloudReader {
+        let bounds = (!cloud.is_empty()).then(|| find_bounds(&cloud.points));
+        PointCloudReader {
+            header: PointHeader {
+                count: Some(cloud.len() as u64),
+                bounds,
+                attributes: cloud.attributes,
+                crs: cloud.crs,
+            },
+            points: cloud.points.into_iter(),
+        }
+    }
+}
+
+impl PointReader for PointCloudReader {
+    fn header(&self) -> &PointHeader {
+        &self.header
+    }
+
+    fn next_batch(&mut self) -> Option<Result<Vec<Point>, ReadError>> {
+        let batch: Vec<Point> = self.points.by_ref().take(BATCH_SIZE).collect();
+        (!batch.is_empty()).then_some(Ok(batch))
+    }
+}
+
+type OpenFn = for<'a> fn(&'a [u8]) -> Result<Box<dyn PointReader + 'a>, ReadError>;
+
+/// A file format the registry can open.
+pub struct Format {
+    pub name: &'static str,
+    /// Lowercase file extensions without the dot.
+    pub extensions: &'static [&'static str],
+    /// Bytes every file of the format starts with, if any.
+    pub magic: &'static [&'static [u8]],
+    pub open: OpenFn,
+}
+
+/// Opens point files with the reader registered for their format.
+pub struct Registry {
+    formats: Vec<Format>,
+}
+
+fn cloud_reader<'a, E: std::error::Error + Send + Sync + 'static>(
+    result: Result<PointCloud, E>,
+) -> Result<Box<dyn PointReader + 'a>, ReadError> {
+    let cloud = result.map_err(ReadError::format)?;
+    Ok(Box::new(PointCloudReader::new(cloud)))
+}
+
+impl Registry {
+    pub fn new() -> Registry {
+        Registry {
+            formats: Vec::new(),
+        }
+    }
+
+    /// Adds a format, taking precedence over the formats registered before it.
+    pub fn register(&mut self, format: Format) {
+        self.formats.insert(0, format);
+    }
+
+    pub fn formats(&self) -> &[Format] {
+        &self.formats
+    }
+
+    pub fn by_extension(&self, extension: &str) -> Option<&Format> {
+        let extension = extension.to_ascii_lowercase();
+        self.formats
+            .iter()
+            .find(|format| format.extensions.contains(&extension.as_str()))
+    }
+
+    pub fn by_magic(&self, buf: &[u8]) -> Option<&Format> {
+        self.formats
+            .iter()
+            .find(|format| format.magic.iter().any(|magic| buf.starts_with(magic)))
+    }
+
+    /// Finds the format of a file by its magic bytes, falling back to the extension of
+    /// `name`, which may be a path, a file name or a bare extension.
+    pub fn detect(&self, name: &str, buf: &[u8]) -> Option<&Format> {
+        let extension = name.rsplit('.').next().unwrap_or(name);
+        self.by_magic(buf).or_else(|| self.by_extension(extension))
+    }
+
+    pub fn open<'a>(
+        &self,
+        name: &str,
+        buf: &'a [u8],
+    ) -> Result<Box<dyn PointReader + 'a>, ReadError> {
+        match self.detect(name, buf) {
+            Some(format) => (format.open)(buf),
+            None => Err(ReadError::UnknownFormat {
+                name: name.to_string(),
+            }),
+        }
+    }
+}
+
+impl Default for Registry {
+    /// Registers every format that can be read without options. Raw binary layouts and
+    /// ROS topics have to be chosen by the caller and are left out.
+    ///
+    /// Only the Parquet and Arrow readers stream: they decode one record batch at a time
+    /// and know their bounds up front, so
+    /// [`Potree::from_reader`](crate::potree::Potree::from_reader) indexes their points as
+    /// they are read. The other readers parse the whole file into a [`PointCloud`] that is
+    /// then handed out in batches.
+    fn default() -> Registry {
+        let mut registry = Registry::new();
+        registry.register(Format {
+            name: "XYZ",
+            extensions: &["xyz", "txt"],
+            magic: &[],
+            open: |buf| cloud_reader(read_xyz(buf)),
+        });
+        registry.register(Format {
+            name: "PTS",
+            extensions: &["pts"],
+            magic: &[],
+            open: |buf| cloud_reader(read_pts(buf)),
+        });
+        registry.register(Format {
+            name: "CSV",
+            extensions: &["csv"],
+            magic: &[],
+            open: |buf| cloud_reader(read_csv(buf)),
+        });
+        registry.register(Format {
+            name: "PCD",
+            extensions: &["pcd"],
+            magic: &[b"# .PCD", b"VERSION"],
+            open: |buf| cloud_reader(read_pcd(buf)),
+        });
+        registry.register(Format {
+            name: "E57",
+            extensions: &["e57"],
+            magic: &[b"ASTM-E57"],
+            open: |buf| cloud_reader(read_e57(buf)),
+        });
+        registry.register(Format {
+            name: "NPY",
+            extensions: &["npy"],
+            magic: &[b"\x93NUMPY"],
+            open: |buf| cloud_reader(read_npy(buf)),
+        });
+        registry.register(Format {
+            name: "NPZ",
+            extensions: &["npz"],
+            magic: &[],
+            open: |buf| cloud_reader(read_npz(buf)),
+        });
+        registry.register(Format {
+            name: "OBJ",
+            extensions: &["obj"],
+            magic: &[],
+            open: |buf| cloud_reader(read_obj(buf, &MeshOptions::default())),
+        });
+        registry.register(Format {
+            name: "glTF",
+            extensions: &["gltf", "glb"],
+            magic: &[b"glTF"],
+            open: |buf| cloud_reader(read_gltf(buf, &MeshOptions::default())),
+        });
+        #[cfg(feature = "arrow")]
+        {
+            use crate::arrow_reader::{open_arrow_ipc, open_parquet, ArrowOptions};
+            registry.register(Format {
+                name: "Parquet",
+                extensions: &["parquet"],
+                magic: &[b"PAR1"],
+                open: |buf| {
+                    let reader =
+                        open_parquet(buf, &ArrowOptions::default()).map_err(ReadError::format)?;
+                    Ok(Box::new(reader))
+                },
+            });
+            registry.register(Format {
+                name: "Arrow",
+                extensions: &["arrow", "arrows", "feather", "ipc"],
+                magic: &[b"ARROW1"],
+                open: |buf| {
+                    let reader =
+                        open_arrow_ipc(buf, &ArrowOptions::default()).map_err(ReadError::format)?;
+                    Ok(Box::new(reader))
+                },
+            });
+        }
+        registry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::potree::{BuildOptions, Potree};
+
+    #[test]
+    fn test_detect() {
+        let registry = Registry::default();
+        let pcd = fs::read("resources/points_binary.pcd").unwrap();
+        assert_eq!(registry.detect("upload.bin", &pcd).unwrap().name, "PCD");
+        assert_eq!(registry.detect("scan.PTS", b"1\n").unwrap().name, "PTS");
+        assert_eq!(registry.detect("glb", b"").unwrap().name, "glTF");
+        assert!(matches!(
+            registry.open("points.las", b""),
+            Err(ReadError::UnknownFormat { .. })
+        ));
+    }
+
+    #[test]
+    fn test_build_from_reader() {
+        let buf = fs::read("resources/points.pts").unwrap();
+        let mut reader = Registry::default().open("points.pts", &buf).unwrap();
+        assert_eq!(reader.header().count, Some(5));
+
+        let options = BuildOptions {
+            point_per_leaf_node_limit: 2,
+            ..BuildOptions::default()
+        };
+        let potree = Potree::from_reader(reader.as_mut(), &options).unwrap();
+        assert_eq!(potree.size, 5);
+        assert!(potree.attributes.get("rgb").is_some());
+    }
+}
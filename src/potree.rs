@@ -1,7 +1,11 @@
+use crate::model::attributes::Attributes;
 use crate::model::bounds::{find_bounds, Bounds};
 use crate::model::node::empty_child_node_array;
 use crate::model::node::Node;
+use crate::model::point::Point;
 use crate::model::vector3::Vector3;
+use crate::point_reader::{PointReader, ReadError};
+use serde_json::{Map, Value};
 
 pub struct Potree {
     pub bounds: Bounds,
@@ -14,14 +18,79 @@ pub struct Potree {
     pub scale: f64,
     pub size: u32,
 
+    pub attributes: Attributes,
     pub root: Node,
+    /// Free-form values written to the `extras` of `metadata.json`.
+    pub extras: Map<String, Value>,
+    /// Coordinate reference system, written as the `projection` of `metadata.json`.
+    pub crs: Option<String>,
 }
 
 const DIAGONAL_FRACTION: f64 = 200.0;
 
+/// How the octree is built, shared by every input format.
+pub struct BuildOptions {
+    pub point_per_leaf_node_limit: u32,
+    /// Coordinate reference system of the input as WKT or an authority code, replacing the
+    /// one its reader found.
+    pub crs: Option<String>,
+}
+
+impl Default for BuildOptions {
+    fn default() -> BuildOptions {
+        BuildOptions {
+            point_per_leaf_node_limit: 20000,
+            crs: None,
+        }
+    }
+}
+
+/// Adds points to an octree one at a time, for readers that stream their input.
+pub struct PotreeBuilder {
+    potree: Potree,
+}
+
+impl PotreeBuilder {
+    pub fn add_point(&mut self, point: Point) {
+        self.potree.attributes.update_ranges(&point.attributes);
+        self.potree.root.add_point(point);
+        self.potree.size += 1;
+    }
+
+    pub fn build(self) -> Potree {
+        self.potree
+    }
+}
+
 impl Potree {
     pub fn new(points: Vec<Vector3>, point_per_leaf_node_limit: u32) -> Potree {
-        let bounds = find_bounds(&points);
+        Potree::with_attributes(
+            points.into_iter().map(Point::new).collect(),
+            Attributes::new(),
+            point_per_leaf_node_limit,
+        )
+    }
+
+    /// Builds the octree from points carrying values for every attribute in `attributes`.
+    pub fn with_attributes(
+        points: Vec<Point>,
+        attributes: Attributes,
+        point_per_leaf_node_limit: u32,
+    ) -> Potree {
+        let mut builder =
+            Potree::builder(find_bounds(&points), attributes, point_per_leaf_node_limit);
+        for point in points {
+            builder.add_point(point);
+        }
+        builder.build()
+    }
+
+    /// Starts an octree covering `bounds`, which must contain every point added to it.
+    pub fn builder(
+        bounds: Bounds,
+        attributes: Attributes,
+        point_per_leaf_node_limit: u32,
+    ) -> PotreeBuilder {
         let cubic_bounds = bounds.cubic();
         let size_len = ((cubic_bounds.size_x * cubic_bounds.size_x)
             + (cubic_bounds.size_y * cubic_bounds.size_y)
@@ -29,7 +98,7 @@ impl Potree {
             .sqrt();
         let spacing = size_len / DIAGONAL_FRACTION;
 
-        let mut root_node = Node::new(
+        let root = Node::new(
             "r".to_string(),
             spacing,
             bounds.clone(),
@@ -37,48 +106,111 @@ impl Potree {
             point_per_leaf_node_limit,
         );
 
-        let size = points.len() as u32;
+        PotreeBuilder {
+            potree: Potree {
+                spacing,
+                scale: if size_len > 1_000_000.0 {
+                    0.01
+                } else if size_len > 1.0 {
+                    0.001
+                } else {
+                    0.0001
+                },
+                size: 0,
+                bounds,
+                cubic_bounds,
+                size_len,
+                point_per_leaf_node_limit,
+                attributes,
+                root,
+                extras: Map::new(),
+                crs: None,
+            },
+        }
+    }
 
-        for point in points {
-            root_node.add_point(point);
+    /// Builds the octree from the batches of a reader, indexing them as they are read when
+    /// the header knows the bounds and collecting them first otherwise.
+    pub fn from_reader(
+        reader: &mut dyn PointReader,
+        options: &BuildOptions,
+    ) -> Result<Potree, ReadError> {
+        let header = reader.header().clone();
+        let mut collected = Vec::new();
+        let mut builder = header.bounds.map(|bounds| {
+            Potree::builder(
+                bounds,
+                header.attributes.clone(),
+                options.point_per_leaf_node_limit,
+            )
+        });
+        while let Some(batch) = reader.next_batch() {
+            match builder.as_mut() {
+                Some(builder) => batch?.into_iter().for_each(|point| builder.add_point(point)),
+                None => collected.extend(batch?),
+            }
         }
 
-        Potree {
-            spacing,
-            scale: if size_len > 1_000_000.0 {
-                0.01
-            } else if size_len > 1.0 {
-                0.001
-            } else {
-                0.0001
-            },
-            size,
-            bounds,
-            cubic_bounds,
-            size_len,
-            point_per_leaf_node_limit,
-            root: root_node,
+        let mut potree = match builder {
+            Some(builder) => builder.build(),
+            None if collected.is_empty() => return Err(ReadError::NoPoints),
+            None => Potree::with_attributes(
+                collected,
+                header.attributes,
+                options.point_per_leaf_node_limit,
+            ),
+        };
+        potree.crs = options.crs.clone().or(header.crs);
+        Ok(potree)
+    }
+
+    /// Rebuilds the octree with a cube as its root node, as formats that key nodes by their
+    /// position in a cube require. Octrees that already have one are returned unchanged.
+    pub fn into_cubic(self) -> Potree {
+        let (root, cubic) = (&self.root.bounds, &self.cubic_bounds);
+        if [root.lx, root.ly, root.lz, root.ux, root.uy, root.uz]
+            == [cubic.lx, cubic.ly, cubic.lz, cubic.ux, cubic.uy, cubic.uz]
+        {
+            return self;
         }
+
+        let mut builder = Potree::builder(
+            self.cubic_bounds.clone(),
+            self.attributes.clone(),
+            self.point_per_leaf_node_limit,
+        );
+        let (bounds, extras, crs) = (self.bounds.clone(), self.extras.clone(), self.crs.clone());
+        for point in self.into_points() {
+            builder.add_point(point);
+        }
+        let mut potree = builder.build();
+        potree.bounds = bounds;
+        potree.extras = extras;
+        potree.crs = crs;
+        potree
+    }
+
+    /// Consumes the octree, returning its points in no particular order.
+    pub fn into_points(self) -> Vec<Point> {
+        self.root.into_points()
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::io::Cursor;
-
     use crate::model::node::Node;
     use crate::model::node::NodeGrid;
     use crate::potree::Potree;
     use crate::potree::Vector3;
+    use crate::raw_reader::{read_raw, RawLayout};
     use crate::writer::write_potree;
-    use byteorder::LittleEndian;
-    use byteorder::ReadBytesExt;
     use rand::prelude::*;
+    use rand::rngs::StdRng;
     use std::fs;
     use std::path::Path;
 
     fn setup_potree(point_count: u32, node_limit: u32) -> Potree {
-        let mut rng = rand::thread_rng();
+        let mut rng = StdRng::seed_from_u64(u64::from(point_count));
 
         let point_per_leaf_node_limit = node_limit;
         let mut points = Vec::new();
@@ -107,27 +239,10 @@ mod tests {
     #[test]
     fn test_write_binary_points() {
         let buffer = fs::read("resources/points.bin").unwrap();
-        let length = buffer.len();
-        let mut cursor = Cursor::new(buffer);
-        let pos = 0;
-        let mut points: Vec<Vector3> = Vec::new();
-
-        while cursor.position() < (length - 1) as u64 {
-            points.push(Vector3 {
-                x: cursor.read_f64::<LittleEndian>().unwrap(),
-                y: cursor.read_f64::<LittleEndian>().unwrap(),
-                z: cursor.read_f64::<LittleEndian>().unwrap(),
-            })
-        }
-        for p in 0..5 {
-            let point = &points[p];
-            println!("x: {}, y: {}, z: {}", point.x, point.y, point.z);
-        }
+        let potree = read_raw(&buffer, &RawLayout::xyz_f64()).unwrap().into_potree();
 
-        println!("{}", points.len());
         let expected_points = 495934;
-        assert_eq!(points.len(), expected_points);
-        let potree = Potree::new(points, 20000);
+        assert_eq!(potree.size, expected_points);
 
         let dir = Path::new("/tmp/test-output");
         write_potree(potree, dir);
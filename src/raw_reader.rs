@@ -0,0 +1,261 @@
+use core::fmt;
+
+use crate::model::attributes::{Attribute, AttributeType, Attributes};
+use crate::model::point::Point;
+use crate::model::point_cloud::PointCloud;
+use crate::model::vector3::Vector3;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+#[derive(Clone)]
+pub struct RawField {
+    pub name: String,
+    pub r#type: AttributeType,
+    /// Byte offset of the field within a record.
+    pub offset: usize,
+}
+
+/// Describes the record layout of a raw point buffer.
+#[derive(Clone)]
+pub struct RawLayout {
+    pub fields: Vec<RawField>,
+    pub endianness: Endianness,
+    /// Bytes per record, at least the end of the last field.
+    pub stride: usize,
+    /// Names of the fields holding x, y and z. All other fields become attributes.
+    pub position: [String; 3],
+}
+
+impl RawLayout {
+    /// Packed layout of `fields` in order, with the position in fields named x, y and z.
+    pub fn packed(fields: &[(&str, AttributeType)], endianness: Endianness) -> RawLayout {
+        let mut offset = 0;
+        let mut raw_fields = Vec::new();
+        for (name, r#type) in fields {
+            raw_fields.push(RawField {
+                name: name.to_string(),
+                r#type: *r#type,
+                offset,
+            });
+            offset += r#type.size() as usize;
+        }
+
+        RawLayout {
+            fields: raw_fields,
+            endianness,
+            stride: offset,
+            position: ["x".to_string(), "y".to_string(), "z".to_string()],
+        }
+    }
+
+    /// Little endian f64 x, y, z triples.
+    pub fn xyz_f64() -> RawLayout {
+        RawLayout::packed(
+            &[
+                ("x", AttributeType::DOUBLE),
+                ("y", AttributeType::DOUBLE),
+                ("z", AttributeType::DOUBLE),
+            ],
+            Endianness::Little,
+        )
+    }
+
+    /// KITTI velodyne `.bin` scans: little endian f32 x, y, z, intensity.
+    pub fn kitti() -> RawLayout {
+        RawLayout::packed(
+            &[
+                ("x", AttributeType::FLOAT),
+                ("y", AttributeType::FLOAT),
+                ("z", AttributeType::FLOAT),
+                ("intensity", AttributeType::FLOAT),
+            ],
+            Endianness::Little,
+        )
+    }
+
+    /// nuScenes `.pcd.bin` lidar sweeps: little endian f32 x, y, z, intensity, ring index.
+    pub fn nuscenes() -> RawLayout {
+        RawLayout::packed(
+            &[
+                ("x", AttributeType::FLOAT),
+                ("y", AttributeType::FLOAT),
+                ("z", AttributeType::FLOAT),
+                ("intensity", AttributeType::FLOAT),
+                ("ring_index", AttributeType::FLOAT),
+            ],
+            Endianness::Little,
+        )
+    }
+}
+
+#[derive(Debug)]
+pub enum RawReadError {
+    NoPoints,
+    InvalidLayout { msg: String },
+    TrailingBytes { len: usize, stride: usize },
+}
+
+impl fmt::Display for RawReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RawReadError::NoPoints => write!(f, "No points in buffer"),
+            RawReadError::InvalidLayout { msg } => write!(f, "Invalid layout: {}", msg),
+            RawReadError::TrailingBytes { len, stride } => write!(
+                f,
+                "Buffer of {} bytes is not a multiple of the {} byte record size",
+                len, stride
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RawReadError {}
+
+/// Copies a field value into `out` as little endian.
+fn read_field(record: &[u8], field: &RawField, endianness: Endianness, out: &mut Vec<u8>) {
+    let size = field.r#type.size() as usize;
+    let bytes = &record[field.offset..field.offset + size];
+    match endianness {
+        Endianness::Little => out.extend_from_slice(bytes),
+        Endianness::Big => out.extend(bytes.iter().rev()),
+    }
+}
+
+pub fn read_raw(buf: &[u8], layout: &RawLayout) -> Result<PointCloud, RawReadError> {
+    if layout.stride == 0 {
+        return Err(RawReadError::InvalidLayout {
+            msg: "stride must be greater than zero".to_string(),
+        });
+    }
+    if let Some(field) = layout
+        .fields
+        .iter()
+        .find(|field| field.offset + field.r#type.size() as usize > layout.stride)
+    {
+        return Err(RawReadError::InvalidLayout {
+            msg: format!("field '{}' extends past the record stride", field.name),
+        });
+    }
+    let mut position_fields = Vec::new();
+    for name in &layout.position {
+        match layout.fields.iter().find(|field| &field.name == name) {
+            Some(field) => position_fields.push(field),
+            None => {
+                return Err(RawReadError::InvalidLayout {
+                    msg: format!("position field '{}' not in layout", name),
+                })
+            }
+        }
+    }
+    if !buf.len().is_multiple_of(layout.stride) {
+        return Err(RawReadError::TrailingBytes {
+            len: buf.len(),
+            stride: layout.stride,
+        });
+    }
+
+    let attribute_fields: Vec<&RawField> = layout
+        .fields
+        .iter()
+        .filter(|field| !layout.position.contains(&field.name))
+        .collect();
+    let attributes = Attributes::from_attributes(
+        attribute_fields
+            .iter()
+            .map(|field| Attribute::new(&field.name, field.r#type, 1))
+            .collect(),
+    );
+
+    let mut points: Vec<Point> = Vec::with_capacity(buf.len() / layout.stride);
+    let mut value = Vec::with_capacity(8);
+    for record in buf.chunks_exact(layout.stride) {
+        let mut position = [0.0; 3];
+        for (i, field) in position_fields.iter().enumerate() {
+            value.clear();
+            read_field(record, field, layout.endianness, &mut value);
+            position[i] = field.r#type.read_f64(&value);
+        }
+        let mut point_attributes = Vec::with_capacity(attributes.bytes as usize);
+        for field in &attribute_fields {
+            read_field(record, field, layout.endianness, &mut point_attributes);
+        }
+
+        points.push(Point::with_attributes(
+            Vector3 {
+                x: position[0],
+                y: position[1],
+                z: position[2],
+            },
+            point_attributes,
+        ));
+    }
+    if points.is_empty() {
+        return Err(RawReadError::NoPoints);
+    }
+
+    Ok(PointCloud::new(points, attributes))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::attributes::AttributeType;
+    use crate::raw_reader::{read_raw, Endianness, RawLayout, RawReadError};
+
+    #[test]
+    fn test_read_kitti() {
+        let mut buffer = Vec::new();
+        for i in 0..10 {
+            for value in [i as f32, 2.0, 3.0, 0.5] {
+                buffer.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        let potree = read_raw(&buffer, &RawLayout::kitti()).unwrap().into_potree();
+
+        assert_eq!(potree.size, 10);
+        assert_eq!(potree.bounds.ux, 9.0);
+        let intensity = potree.attributes.get("intensity").unwrap();
+        assert_eq!((intensity.min.x, intensity.max.x), (0.5, 0.5));
+    }
+
+    #[test]
+    fn test_read_big_endian_with_padding() {
+        let layout = RawLayout {
+            stride: 16,
+            ..RawLayout::packed(
+                &[
+                    ("x", AttributeType::INT16),
+                    ("y", AttributeType::INT16),
+                    ("z", AttributeType::INT16),
+                    ("label", AttributeType::UINT32),
+                ],
+                Endianness::Big,
+            )
+        };
+        let mut buffer = Vec::new();
+        for i in 0..4i16 {
+            buffer.extend_from_slice(&i.to_be_bytes());
+            buffer.extend_from_slice(&(-i).to_be_bytes());
+            buffer.extend_from_slice(&7i16.to_be_bytes());
+            buffer.extend_from_slice(&(1000 + i as u32).to_be_bytes());
+            buffer.extend_from_slice(&[0; 6]);
+        }
+        let potree = read_raw(&buffer, &layout).unwrap().into_potree();
+
+        assert_eq!(potree.size, 4);
+        assert_eq!((potree.bounds.ly, potree.bounds.uy), (-3.0, 0.0));
+        let label = potree.attributes.get("label").unwrap();
+        assert_eq!((label.min.x, label.max.x), (1000.0, 1003.0));
+    }
+
+    #[test]
+    fn test_read_trailing_bytes() {
+        match read_raw(&[0; 30], &RawLayout::xyz_f64()) {
+            Err(RawReadError::TrailingBytes { len, stride }) => assert_eq!((len, stride), (30, 24)),
+            _ => panic!("Expected trailing bytes error"),
+        }
+    }
+}
@@ -0,0 +1,901 @@
+use core::fmt;
+use std::collections::HashMap;
+use std::io::Read;
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+use crate::model::attributes::{Attribute, AttributeType, Attributes};
+use crate::model::point::Point;
+use crate::model::point_cloud::PointCloud;
+use crate::model::vector3::Vector3;
+
+const BAG_MAGIC: &[u8] = b"#ROSBAG V2.0\n";
+const MCAP_MAGIC: &[u8] = b"\x89MCAP0\r\n";
+
+const BAG_OP_MESSAGE_DATA: u8 = 0x02;
+const BAG_OP_CHUNK: u8 = 0x05;
+const BAG_OP_CONNECTION: u8 = 0x07;
+
+const MCAP_OP_FOOTER: u8 = 0x02;
+const MCAP_OP_SCHEMA: u8 = 0x03;
+const MCAP_OP_CHANNEL: u8 = 0x04;
+const MCAP_OP_MESSAGE: u8 = 0x05;
+const MCAP_OP_CHUNK: u8 = 0x06;
+const MCAP_OP_DATA_END: u8 = 0x0f;
+
+/// Selects the PointCloud2 messages to aggregate.
+pub struct PointCloud2Options {
+    pub topic: String,
+    /// Only messages logged at or after this time, in seconds.
+    pub start: Option<f64>,
+    /// Only messages logged at or before this time, in seconds.
+    pub end: Option<f64>,
+    /// Keep every nth message on the topic within the time range, 1 keeps all.
+    pub every_nth: usize,
+}
+
+impl PointCloud2Options {
+    pub fn new(topic: &str) -> PointCloud2Options {
+        PointCloud2Options {
+            topic: topic.to_string(),
+            start: None,
+            end: None,
+            every_nth: 1,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum RosReadError {
+    InvalidFormat { msg: String },
+    UnsupportedCompression { compression: String },
+    Decompression { msg: String },
+    Truncated,
+    TopicNotFound { topic: String },
+    SchemaMismatch { frame: usize },
+    NoPoints,
+}
+
+impl fmt::Display for RosReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RosReadError::InvalidFormat { msg } => write!(f, "Invalid recording: {}", msg),
+            RosReadError::UnsupportedCompression { compression } => {
+                write!(f, "Unsupported chunk compression '{}'", compression)
+            }
+            RosReadError::Decompression { msg } => write!(f, "Could not decompress chunk: {}", msg),
+            RosReadError::Truncated => write!(f, "Recording is truncated"),
+            RosReadError::TopicNotFound { topic } => {
+                write!(f, "No PointCloud2 messages on topic '{}'", topic)
+            }
+            RosReadError::SchemaMismatch { frame } => {
+                write!(
+                    f,
+                    "Frame {} has different PointCloud2 fields than the first frame",
+                    frame
+                )
+            }
+            RosReadError::NoPoints => write!(f, "No points in selected messages"),
+        }
+    }
+}
+
+impl std::error::Error for RosReadError {}
+
+fn invalid(msg: &str) -> RosReadError {
+    RosReadError::InvalidFormat {
+        msg: msg.to_string(),
+    }
+}
+
+fn decompress(compression: &str, data: &[u8], size: usize) -> Result<Vec<u8>, RosReadError> {
+    let mut output = Vec::with_capacity(size);
+    let result = match compression {
+        "" | "none" => return Ok(data.to_vec()),
+        "lz4" => lz4_flex::frame::FrameDecoder::new(data)
+            .read_to_end(&mut output)
+            .map_err(|e| e.to_string()),
+        "bz2" => bzip2_rs::DecoderReader::new(data)
+            .read_to_end(&mut output)
+            .map_err(|e| e.to_string()),
+        "zstd" => ruzstd::StreamingDecoder::new(data)
+            .map_err(|e| e.to_string())
+            .and_then(|mut decoder| decoder.read_to_end(&mut output).map_err(|e| e.to_string())),
+        other => {
+            return Err(RosReadError::UnsupportedCompression {
+                compression: other.to_string(),
+            })
+        }
+    };
+    result.map_err(|msg| RosReadError::Decompression { msg })?;
+    if output.len() != size {
+        return Err(RosReadError::Decompression {
+            msg: format!("expected {} bytes, got {}", size, output.len()),
+        });
+    }
+    Ok(output)
+}
+
+/// Reads ROS1 serialized and CDR encoded messages.
+struct MessageReader<'a> {
+    buf: &'a [u8],
+    position: usize,
+    /// Start of the payload that CDR alignment is relative to, `None` for ROS1.
+    cdr_origin: Option<usize>,
+    big_endian: bool,
+}
+
+impl<'a> MessageReader<'a> {
+    fn ros1(buf: &'a [u8]) -> MessageReader<'a> {
+        MessageReader {
+            buf,
+            position: 0,
+            cdr_origin: None,
+            big_endian: false,
+        }
+    }
+
+    fn cdr(buf: &'a [u8]) -> Result<MessageReader<'a>, RosReadError> {
+        if buf.len() < 4 {
+            return Err(RosReadError::Truncated);
+        }
+        Ok(MessageReader {
+            buf,
+            position: 4,
+            cdr_origin: Some(4),
+            big_endian: buf[1] & 1 == 0,
+        })
+    }
+
+    fn align(&mut self, size: usize) {
+        if let Some(origin) = self.cdr_origin {
+            let offset = self.position - origin;
+            self.position = origin + offset.div_ceil(size) * size;
+        }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], RosReadError> {
+        let bytes = self
+            .buf
+            .get(self.position..self.position + len)
+            .ok_or(RosReadError::Truncated)?;
+        self.position += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, RosReadError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, RosReadError> {
+        self.align(4);
+        let bytes = self.bytes(4)?;
+        Ok(if self.big_endian {
+            BigEndian::read_u32(bytes)
+        } else {
+            LittleEndian::read_u32(bytes)
+        })
+    }
+
+    fn string(&mut self) -> Result<String, RosReadError> {
+        let len = self.u32()? as usize;
+        let bytes = self.bytes(len)?;
+        let bytes = match (self.cdr_origin, bytes.split_last()) {
+            (Some(_), Some((0, string))) => string,
+            _ => bytes,
+        };
+        Ok(String::from_utf8_lossy(bytes).to_string())
+    }
+}
+
+#[derive(Clone, PartialEq)]
+struct PointField {
+    name: String,
+    offset: usize,
+    r#type: AttributeType,
+    count: usize,
+}
+
+struct PointCloud2<'a> {
+    stamp: f64,
+    height: usize,
+    width: usize,
+    fields: Vec<PointField>,
+    big_endian: bool,
+    point_step: usize,
+    row_step: usize,
+    data: &'a [u8],
+}
+
+fn datatype(datatype: u8) -> Result<AttributeType, RosReadError> {
+    match datatype {
+        1 => Ok(AttributeType::INT8),
+        2 => Ok(AttributeType::UINT8),
+        3 => Ok(AttributeType::INT16),
+        4 => Ok(AttributeType::UINT16),
+        5 => Ok(AttributeType::INT32),
+        6 => Ok(AttributeType::UINT32),
+        7 => Ok(AttributeType::FLOAT),
+        8 => Ok(AttributeType::DOUBLE),
+        other => Err(invalid(&format!("unknown PointField datatype {}", other))),
+    }
+}
+
+/// Decodes a `sensor_msgs/PointCloud2`, which only differs in the header between ROS1 and ROS2.
+fn decode_point_cloud2<'a>(
+    reader: &mut MessageReader<'a>,
+    ros1: bool,
+) -> Result<PointCloud2<'a>, RosReadError> {
+    if ros1 {
+        reader.u32()?; // seq
+    }
+    let sec = reader.u32()? as f64;
+    let nsec = reader.u32()? as f64;
+    reader.string()?; // frame_id
+    let height = reader.u32()? as usize;
+    let width = reader.u32()? as usize;
+    let field_count = reader.u32()? as usize;
+    let mut fields = Vec::with_capacity(field_count);
+    for _ in 0..field_count {
+        let name = reader.string()?;
+        let offset = reader.u32()? as usize;
+        let r#type = datatype(reader.u8()?)?;
+        let count = reader.u32()? as usize;
+        fields.push(PointField {
+            name,
+            offset,
+            r#type,
+            count,
+        });
+    }
+    let big_endian = reader.u8()? != 0;
+    let point_step = reader.u32()? as usize;
+    let row_step = reader.u32()? as usize;
+    let data_len = reader.u32()? as usize;
+    let data = reader.bytes(data_len)?;
+
+    Ok(PointCloud2 {
+        stamp: sec + nsec * 1e-9,
+        height,
+        width,
+        fields,
+        big_endian,
+        point_step,
+        row_step,
+        data,
+    })
+}
+
+/// Collects the points of all selected messages into one point list.
+struct Aggregator<'o> {
+    options: &'o PointCloud2Options,
+    matched: usize,
+    fields: Option<Vec<PointField>>,
+    attribute_list: Vec<Attribute>,
+    points: Vec<Point>,
+    frames: usize,
+}
+
+impl<'o> Aggregator<'o> {
+    fn new(options: &'o PointCloud2Options) -> Aggregator<'o> {
+        Aggregator {
+            options,
+            matched: 0,
+            fields: None,
+            attribute_list: Vec::new(),
+            points: Vec::new(),
+            frames: 0,
+        }
+    }
+
+    /// Applies the time range and every nth filter to a message logged at `time` seconds.
+    fn select(&mut self, time: f64) -> bool {
+        if self.options.start.is_some_and(|start| time < start)
+            || self.options.end.is_some_and(|end| time > end)
+        {
+            return false;
+        }
+        self.matched += 1;
+        (self.matched - 1).is_multiple_of(self.options.every_nth.max(1))
+    }
+
+    fn add(&mut self, cloud: PointCloud2) -> Result<(), RosReadError> {
+        let frame_index = self.frames;
+        self.frames += 1;
+        match &self.fields {
+            Some(fields) if *fields != cloud.fields => {
+                return Err(RosReadError::SchemaMismatch { frame: frame_index })
+            }
+            Some(_) => {}
+            None => {
+                for field in &cloud.fields {
+                    if !is_reserved(&field.name) {
+                        self.attribute_list.push(Attribute::new(
+                            &field.name,
+                            field.r#type,
+                            field.count as i32,
+                        ));
+                    }
+                }
+                self.attribute_list
+                    .push(Attribute::new("frame_index", AttributeType::UINT32, 1));
+                self.attribute_list
+                    .push(Attribute::new("timestamp", AttributeType::DOUBLE, 1));
+                self.fields = Some(cloud.fields.clone());
+            }
+        }
+
+        let position_field = |name: &str| {
+            cloud
+                .fields
+                .iter()
+                .find(|f| f.name == name)
+                .ok_or_else(|| invalid(&format!("PointCloud2 without field '{}'", name)))
+        };
+        let position = [
+            position_field("x")?,
+            position_field("y")?,
+            position_field("z")?,
+        ];
+        let timestamp_field = cloud.fields.iter().find(|f| f.name == "timestamp");
+
+        let mut value = Vec::with_capacity(8);
+        let read = |record: &[u8], field: &PointField, index: usize, out: &mut Vec<u8>| {
+            let size = field.r#type.size() as usize;
+            let start = field.offset + index * size;
+            let bytes = record
+                .get(start..start + size)
+                .ok_or(RosReadError::Truncated)?;
+            if cloud.big_endian {
+                out.extend(bytes.iter().rev());
+            } else {
+                out.extend_from_slice(bytes);
+            }
+            Ok::<(), RosReadError>(())
+        };
+
+        for row in 0..cloud.height {
+            for column in 0..cloud.width {
+                let start = row * cloud.row_step + column * cloud.point_step;
+                let record = cloud
+                    .data
+                    .get(start..start + cloud.point_step)
+                    .ok_or(RosReadError::Truncated)?;
+
+                let mut coordinates = [0.0; 3];
+                for (i, field) in position.iter().enumerate() {
+                    value.clear();
+                    read(record, field, 0, &mut value)?;
+                    coordinates[i] = field.r#type.read_f64(&value);
+                }
+                if coordinates.iter().any(|c| c.is_nan()) {
+                    continue;
+                }
+
+                let mut attributes = Vec::new();
+                for field in cloud.fields.iter().filter(|f| !is_reserved(&f.name)) {
+                    for index in 0..field.count {
+                        read(record, field, index, &mut attributes)?;
+                    }
+                }
+                AttributeType::UINT32.write_f64(frame_index as f64, &mut attributes);
+                let timestamp = match timestamp_field {
+                    Some(field) => {
+                        value.clear();
+                        read(record, field, 0, &mut value)?;
+                        field.r#type.read_f64(&value)
+                    }
+                    None => cloud.stamp,
+                };
+                AttributeType::DOUBLE.write_f64(timestamp, &mut attributes);
+
+                self.points.push(Point::with_attributes(
+                    Vector3 {
+                        x: coordinates[0],
+                        y: coordinates[1],
+                        z: coordinates[2],
+                    },
+                    attributes,
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<PointCloud, RosReadError> {
+        if self.frames == 0 {
+            return Err(RosReadError::TopicNotFound {
+                topic: self.options.topic.clone(),
+            });
+        }
+        if self.points.is_empty() {
+            return Err(RosReadError::NoPoints);
+        }
+        Ok(PointCloud::new(
+            self.points,
+            Attributes::from_attributes(self.attribute_list),
+        ))
+    }
+}
+
+/// Fields that are not copied as attributes, position and the aggregated timestamp.
+fn is_reserved(name: &str) -> bool {
+    matches!(name, "x" | "y" | "z" | "timestamp" | "frame_index")
+}
+
+fn read_u32(buf: &[u8], position: usize) -> Result<u32, RosReadError> {
+    buf.get(position..position + 4)
+        .map(LittleEndian::read_u32)
+        .ok_or(RosReadError::Truncated)
+}
+
+fn read_u64(buf: &[u8], position: usize) -> Result<u64, RosReadError> {
+    buf.get(position..position + 8)
+        .map(LittleEndian::read_u64)
+        .ok_or(RosReadError::Truncated)
+}
+
+/// Splits a bag record header into its `name=value` fields.
+fn bag_header_fields(header: &[u8]) -> Result<HashMap<String, &[u8]>, RosReadError> {
+    let mut fields = HashMap::new();
+    let mut position = 0;
+    while position < header.len() {
+        let len = read_u32(header, position)? as usize;
+        let field = header
+            .get(position + 4..position + 4 + len)
+            .ok_or(RosReadError::Truncated)?;
+        let separator = field
+            .iter()
+            .position(|&b| b == b'=')
+            .ok_or_else(|| invalid("bag header field without '='"))?;
+        fields.insert(
+            String::from_utf8_lossy(&field[..separator]).to_string(),
+            &field[separator + 1..],
+        );
+        position += 4 + len;
+    }
+    Ok(fields)
+}
+
+fn bag_field<'a>(fields: &HashMap<String, &'a [u8]>, name: &str) -> Result<&'a [u8], RosReadError> {
+    fields
+        .get(name)
+        .copied()
+        .ok_or_else(|| invalid(&format!("bag record without '{}'", name)))
+}
+
+fn read_bag_records(
+    buf: &[u8],
+    connections: &mut HashMap<u32, String>,
+    aggregator: &mut Aggregator,
+) -> Result<(), RosReadError> {
+    let mut position = 0;
+    while position < buf.len() {
+        let header_len = read_u32(buf, position)? as usize;
+        let header = buf
+            .get(position + 4..position + 4 + header_len)
+            .ok_or(RosReadError::Truncated)?;
+        position += 4 + header_len;
+        let data_len = read_u32(buf, position)? as usize;
+        let data = buf
+            .get(position + 4..position + 4 + data_len)
+            .ok_or(RosReadError::Truncated)?;
+        position += 4 + data_len;
+
+        let fields = bag_header_fields(header)?;
+        match bag_field(&fields, "op")?.first() {
+            Some(&BAG_OP_CONNECTION) => {
+                let connection = read_u32(bag_field(&fields, "conn")?, 0)?;
+                let topic = String::from_utf8_lossy(bag_field(&fields, "topic")?).to_string();
+                let connection_header = bag_header_fields(data)?;
+                let is_point_cloud =
+                    connection_header.get("type").copied() == Some(&b"sensor_msgs/PointCloud2"[..]);
+                if topic == aggregator.options.topic && is_point_cloud {
+                    connections.insert(connection, topic);
+                }
+            }
+            Some(&BAG_OP_CHUNK) => {
+                let compression = String::from_utf8_lossy(bag_field(&fields, "compression")?);
+                let size = read_u32(bag_field(&fields, "size")?, 0)? as usize;
+                let chunk = decompress(&compression, data, size)?;
+                read_bag_records(&chunk, connections, aggregator)?;
+            }
+            Some(&BAG_OP_MESSAGE_DATA) => {
+                let connection = read_u32(bag_field(&fields, "conn")?, 0)?;
+                let time = bag_field(&fields, "time")?;
+                let time = read_u32(time, 0)? as f64 + read_u32(time, 4)? as f64 * 1e-9;
+                if connections.contains_key(&connection) && aggregator.select(time) {
+                    let cloud = decode_point_cloud2(&mut MessageReader::ros1(data), true)?;
+                    aggregator.add(cloud)?;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Aggregates the `sensor_msgs/PointCloud2` messages on a topic of a ROS1 bag into one point cloud.
+///
+/// Chunks may be uncompressed, lz4 or bz2 compressed. Every PointCloud2 field becomes an
+/// attribute, followed by `frame_index`, the index of the message among the selected ones,
+/// and `timestamp`, the header stamp in seconds unless the cloud has a `timestamp` field.
+pub fn read_rosbag(buf: &[u8], options: &PointCloud2Options) -> Result<PointCloud, RosReadError> {
+    if !buf.starts_with(BAG_MAGIC) {
+        return Err(invalid("missing '#ROSBAG V2.0' magic"));
+    }
+    let mut aggregator = Aggregator::new(options);
+    read_bag_records(
+        &buf[BAG_MAGIC.len()..],
+        &mut HashMap::new(),
+        &mut aggregator,
+    )?;
+    aggregator.finish()
+}
+
+struct McapChannel {
+    topic: String,
+    schema_id: u16,
+    encoding: String,
+}
+
+struct McapState {
+    schemas: HashMap<u16, String>,
+    channels: HashMap<u16, McapChannel>,
+}
+
+fn mcap_string(reader: &mut MessageReader) -> Result<String, RosReadError> {
+    let len = reader.u32()? as usize;
+    Ok(String::from_utf8_lossy(reader.bytes(len)?).to_string())
+}
+
+/// Reads mcap records, returning `false` once the end of the data section is reached.
+fn read_mcap_records(
+    buf: &[u8],
+    state: &mut McapState,
+    aggregator: &mut Aggregator,
+) -> Result<bool, RosReadError> {
+    let mut position = 0;
+    while position < buf.len() {
+        let opcode = buf[position];
+        let len = read_u64(buf, position + 1)? as usize;
+        let content = buf
+            .get(position + 9..position + 9 + len)
+            .ok_or(RosReadError::Truncated)?;
+        position += 9 + len;
+        let mut reader = MessageReader::ros1(content);
+
+        match opcode {
+            MCAP_OP_SCHEMA => {
+                let id = LittleEndian::read_u16(reader.bytes(2)?);
+                state.schemas.insert(id, mcap_string(&mut reader)?);
+            }
+            MCAP_OP_CHANNEL => {
+                let id = LittleEndian::read_u16(reader.bytes(2)?);
+                let schema_id = LittleEndian::read_u16(reader.bytes(2)?);
+                let topic = mcap_string(&mut reader)?;
+                let encoding = mcap_string(&mut reader)?;
+                state.channels.insert(
+                    id,
+                    McapChannel {
+                        topic,
+                        schema_id,
+                        encoding,
+                    },
+                );
+            }
+            MCAP_OP_MESSAGE => {
+                let channel_id = LittleEndian::read_u16(reader.bytes(2)?);
+                let log_time = read_u64(content, 6)? as f64 * 1e-9;
+                let data = content.get(22..).ok_or(RosReadError::Truncated)?;
+                let channel = match state.channels.get(&channel_id) {
+                    Some(channel) if channel.topic == aggregator.options.topic => channel,
+                    _ => continue,
+                };
+                let schema = state.schemas.get(&channel.schema_id).map(|s| s.as_str());
+                if !matches!(
+                    schema,
+                    Some("sensor_msgs/PointCloud2") | Some("sensor_msgs/msg/PointCloud2")
+                ) || !aggregator.select(log_time)
+                {
+                    continue;
+                }
+                let cloud = match channel.encoding.as_str() {
+                    "ros1" => decode_point_cloud2(&mut MessageReader::ros1(data), true)?,
+                    "cdr" => decode_point_cloud2(&mut MessageReader::cdr(data)?, false)?,
+                    other => {
+                        return Err(invalid(&format!(
+                            "unsupported message encoding '{}'",
+                            other
+                        )))
+                    }
+                };
+                aggregator.add(cloud)?;
+            }
+            MCAP_OP_CHUNK => {
+                let uncompressed_size = read_u64(content, 16)? as usize;
+                reader.position = 28;
+                let compression = mcap_string(&mut reader)?;
+                let records_len = read_u64(content, reader.position)? as usize;
+                let records = content
+                    .get(reader.position + 8..reader.position + 8 + records_len)
+                    .ok_or(RosReadError::Truncated)?;
+                let chunk = decompress(&compression, records, uncompressed_size)?;
+                if !read_mcap_records(&chunk, state, aggregator)? {
+                    return Ok(false);
+                }
+            }
+            MCAP_OP_DATA_END | MCAP_OP_FOOTER => return Ok(false),
+            _ => {}
+        }
+    }
+    Ok(true)
+}
+
+/// Aggregates the `sensor_msgs/PointCloud2` messages on a topic of an mcap file into one point cloud.
+///
+/// Messages may be ROS1 or CDR (ROS2) encoded and chunks uncompressed, lz4 or zstd
+/// compressed. Attributes are the same as for [`read_rosbag`].
+pub fn read_mcap(buf: &[u8], options: &PointCloud2Options) -> Result<PointCloud, RosReadError> {
+    if !buf.starts_with(MCAP_MAGIC) {
+        return Err(invalid("missing mcap magic"));
+    }
+    let mut aggregator = Aggregator::new(options);
+    let mut state = McapState {
+        schemas: HashMap::new(),
+        channels: HashMap::new(),
+    };
+    read_mcap_records(&buf[MCAP_MAGIC.len()..], &mut state, &mut aggregator)?;
+    aggregator.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use crate::ros_reader::{read_mcap, read_rosbag, PointCloud2Options, RosReadError};
+
+    /// Serializes a PointCloud2 with float x, y, z and uint16 ring fields.
+    fn point_cloud2(points: &[[f32; 3]], stamp: u32, cdr: bool) -> Vec<u8> {
+        let mut message = Vec::new();
+        let string = |message: &mut Vec<u8>, s: &str| {
+            if cdr {
+                while !(message.len() - 4).is_multiple_of(4) {
+                    message.push(0);
+                }
+                message.extend_from_slice(&(s.len() as u32 + 1).to_le_bytes());
+                message.extend_from_slice(s.as_bytes());
+                message.push(0);
+            } else {
+                message.extend_from_slice(&(s.len() as u32).to_le_bytes());
+                message.extend_from_slice(s.as_bytes());
+            }
+        };
+        let u32 = |message: &mut Vec<u8>, value: u32| {
+            if cdr {
+                while !(message.len() - 4).is_multiple_of(4) {
+                    message.push(0);
+                }
+            }
+            message.extend_from_slice(&value.to_le_bytes());
+        };
+
+        if cdr {
+            message.extend_from_slice(&[0, 1, 0, 0]);
+        } else {
+            u32(&mut message, 0);
+        }
+        u32(&mut message, stamp);
+        u32(&mut message, 500_000_000);
+        string(&mut message, "lidar");
+        u32(&mut message, 1);
+        u32(&mut message, points.len() as u32);
+        u32(&mut message, 4);
+        for (name, offset, datatype) in [("x", 0, 7), ("y", 4, 7), ("z", 8, 7), ("ring", 12, 4)] {
+            string(&mut message, name);
+            u32(&mut message, offset);
+            message.push(datatype);
+            u32(&mut message, 1);
+        }
+        message.push(0);
+        u32(&mut message, 16);
+        u32(&mut message, 16 * points.len() as u32);
+        u32(&mut message, 16 * points.len() as u32);
+        for (i, point) in points.iter().enumerate() {
+            for value in point {
+                message.extend_from_slice(&value.to_le_bytes());
+            }
+            message.extend_from_slice(&(i as u16).to_le_bytes());
+            message.extend_from_slice(&[0, 0]);
+        }
+        message.push(1);
+        message
+    }
+
+    fn bag_record(fields: &[(&str, &[u8])], data: &[u8]) -> Vec<u8> {
+        let mut header = Vec::new();
+        for (name, value) in fields {
+            header.extend_from_slice(&((name.len() + 1 + value.len()) as u32).to_le_bytes());
+            header.extend_from_slice(name.as_bytes());
+            header.push(b'=');
+            header.extend_from_slice(value);
+        }
+        let mut record = (header.len() as u32).to_le_bytes().to_vec();
+        record.extend_from_slice(&header);
+        record.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        record.extend_from_slice(data);
+        record
+    }
+
+    fn build_bag() -> Vec<u8> {
+        let connection_header = bag_record(&[("type", b"sensor_msgs/PointCloud2")], &[]);
+        // The connection header is a bare field list without the record framing.
+        let connection_header = &connection_header[4..connection_header.len() - 4];
+        let mut chunk = bag_record(
+            &[
+                ("op", &[0x07]),
+                ("conn", &1u32.to_le_bytes()),
+                ("topic", b"/lidar"),
+            ],
+            connection_header,
+        );
+        chunk.extend(bag_record(
+            &[
+                ("op", &[0x07]),
+                ("conn", &2u32.to_le_bytes()),
+                ("topic", b"/radar"),
+            ],
+            connection_header,
+        ));
+        for (i, conn) in [1u32, 2, 1, 1].iter().enumerate() {
+            let mut time = (100 + i as u32).to_le_bytes().to_vec();
+            time.extend_from_slice(&0u32.to_le_bytes());
+            let points = [
+                [i as f32, 0.0, 0.0],
+                [i as f32, 1.0, f32::NAN],
+                [i as f32, 2.0, 1.0],
+            ];
+            chunk.extend(bag_record(
+                &[
+                    ("op", &[0x02]),
+                    ("conn", &conn.to_le_bytes()),
+                    ("time", &time),
+                ],
+                &point_cloud2(&points, 100 + i as u32, false),
+            ));
+        }
+
+        let mut encoder = lz4_flex::frame::FrameEncoder::new(Vec::new());
+        encoder.write_all(&chunk).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut bag = b"#ROSBAG V2.0\n".to_vec();
+        bag.extend(bag_record(&[("op", &[0x03])], &[]));
+        bag.extend(bag_record(
+            &[
+                ("op", &[0x05]),
+                ("compression", b"lz4"),
+                ("size", &(chunk.len() as u32).to_le_bytes()),
+            ],
+            &compressed,
+        ));
+        bag
+    }
+
+    fn mcap_record(opcode: u8, content: &[u8]) -> Vec<u8> {
+        let mut record = vec![opcode];
+        record.extend_from_slice(&(content.len() as u64).to_le_bytes());
+        record.extend_from_slice(content);
+        record
+    }
+
+    fn mcap_string(s: &str) -> Vec<u8> {
+        let mut bytes = (s.len() as u32).to_le_bytes().to_vec();
+        bytes.extend_from_slice(s.as_bytes());
+        bytes
+    }
+
+    fn build_mcap() -> Vec<u8> {
+        let mut records = Vec::new();
+        let mut schema = 1u16.to_le_bytes().to_vec();
+        schema.extend(mcap_string("sensor_msgs/msg/PointCloud2"));
+        schema.extend(mcap_string("ros2msg"));
+        schema.extend_from_slice(&0u32.to_le_bytes());
+        records.extend(mcap_record(0x03, &schema));
+
+        let mut channel = 1u16.to_le_bytes().to_vec();
+        channel.extend_from_slice(&1u16.to_le_bytes());
+        channel.extend(mcap_string("/points"));
+        channel.extend(mcap_string("cdr"));
+        channel.extend_from_slice(&0u32.to_le_bytes());
+        records.extend(mcap_record(0x04, &channel));
+
+        for i in 0..3u64 {
+            let mut message = 1u16.to_le_bytes().to_vec();
+            message.extend_from_slice(&(i as u32).to_le_bytes());
+            message.extend_from_slice(&((10 + i) * 1_000_000_000).to_le_bytes());
+            message.extend_from_slice(&((10 + i) * 1_000_000_000).to_le_bytes());
+            message.extend(point_cloud2(
+                &[[0.0, i as f32, 5.0], [1.0, i as f32, 5.0]],
+                10 + i as u32,
+                true,
+            ));
+            records.extend(mcap_record(0x05, &message));
+        }
+
+        let mut mcap = b"\x89MCAP0\r\n".to_vec();
+        mcap.extend(mcap_record(
+            0x01,
+            &[mcap_string("ros2"), mcap_string("test")].concat(),
+        ));
+        let mut chunk = Vec::new();
+        chunk.extend_from_slice(&10_000_000_000u64.to_le_bytes());
+        chunk.extend_from_slice(&12_000_000_000u64.to_le_bytes());
+        chunk.extend_from_slice(&(records.len() as u64).to_le_bytes());
+        chunk.extend_from_slice(&0u32.to_le_bytes());
+        chunk.extend(mcap_string(""));
+        chunk.extend_from_slice(&(records.len() as u64).to_le_bytes());
+        chunk.extend_from_slice(&records);
+        mcap.extend(mcap_record(0x06, &chunk));
+        mcap.extend(mcap_record(0x0f, &0u32.to_le_bytes()));
+        mcap.extend(mcap_record(0x02, &[0; 20]));
+        mcap.extend_from_slice(b"\x89MCAP0\r\n");
+        mcap
+    }
+
+    #[test]
+    fn test_read_rosbag() -> Result<(), RosReadError> {
+        let bag = build_bag();
+        let potree = read_rosbag(&bag, &PointCloud2Options::new("/lidar"))?.into_potree();
+
+        // Three /lidar messages with one NaN point each.
+        assert_eq!(potree.size, 6);
+        let names: Vec<&str> = potree
+            .attributes
+            .list
+            .iter()
+            .map(|a| a.name.as_str())
+            .collect();
+        assert_eq!(names, ["ring", "frame_index", "timestamp"]);
+        let frame_index = potree.attributes.get("frame_index").unwrap();
+        assert_eq!((frame_index.min.x, frame_index.max.x), (0.0, 2.0));
+        let timestamp = potree.attributes.get("timestamp").unwrap();
+        assert_eq!((timestamp.min.x, timestamp.max.x), (100.5, 103.5));
+
+        let options = PointCloud2Options {
+            start: Some(101.0),
+            every_nth: 2,
+            ..PointCloud2Options::new("/lidar")
+        };
+        let potree = read_rosbag(&bag, &options)?.into_potree();
+        assert_eq!(potree.size, 2);
+        assert_eq!(potree.bounds.lx, 2.0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_mcap() -> Result<(), RosReadError> {
+        let mcap = build_mcap();
+        let options = PointCloud2Options {
+            end: Some(11.0),
+            ..PointCloud2Options::new("/points")
+        };
+        let potree = read_mcap(&mcap, &options)?.into_potree();
+
+        assert_eq!(potree.size, 4);
+        assert_eq!((potree.bounds.ly, potree.bounds.uy), (0.0, 1.0));
+        let ring = potree.attributes.get("ring").unwrap();
+        assert_eq!((ring.min.x, ring.max.x), (0.0, 1.0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_missing_topic() {
+        assert!(matches!(
+            read_mcap(&build_mcap(), &PointCloud2Options::new("/other")),
+            Err(RosReadError::TopicNotFound { .. })
+        ));
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::model::attributes::Attributes;
+use crate::model::point::Point;
+use crate::model::vector3::Vector3;
+
+/// Bytes of points buffered across all keys before they are appended to the spill files.
+const SPILL_THRESHOLD: usize = 64 << 20;
+
+/// Points grouped by a key in a file per key, so a group is only read back into memory
+/// when it is processed. The files are removed when it is dropped.
+pub(crate) struct Spill<K> {
+    dir: PathBuf,
+    /// Bytes of a point: its position as three doubles followed by its attributes.
+    record_size: usize,
+    /// File of each key and its points not yet appended to it.
+    buffers: BTreeMap<K, (PathBuf, Vec<u8>)>,
+    buffered: usize,
+    threshold: usize,
+}
+
+impl<K: Ord + Copy> Spill<K> {
+    pub(crate) fn new(dir: PathBuf, attributes: &Attributes) -> Result<Spill<K>, io::Error> {
+        // Files left by an aborted run would be appended to.
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir)?;
+        Ok(Spill {
+            dir,
+            record_size: 24 + attributes.bytes as usize,
+            buffers: BTreeMap::new(),
+            buffered: 0,
+            threshold: SPILL_THRESHOLD,
+        })
+    }
+
+    pub(crate) fn push(&mut self, key: K, point: &Point) -> Result<(), io::Error> {
+        let next = self.buffers.len();
+        let (_, buffer) = self
+            .buffers
+            .entry(key)
+            .or_insert_with(|| (self.dir.join(next.to_string()), Vec::new()));
+        for value in point.position.to_array() {
+            buffer.extend_from_slice(&value.to_le_bytes());
+        }
+        buffer.extend_from_slice(&point.attributes);
+        self.buffered += self.record_size;
+        if self.buffered >= self.threshold {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Appends the buffered points to the files of their keys, releasing the buffers.
+    pub(crate) fn flush(&mut self) -> Result<(), io::Error> {
+        for (path, buffer) in self.buffers.values_mut().filter(|(_, b)| !b.is_empty()) {
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            file.write_all(&std::mem::take(buffer))?;
+        }
+        self.buffered = 0;
+        Ok(())
+    }
+
+    /// Every key with points, in order.
+    pub(crate) fn keys(&self) -> Vec<K> {
+        self.buffers.keys().copied().collect()
+    }
+
+    /// Reads the points of `key` in the order they were pushed and removes their file. The
+    /// spill has to be flushed first.
+    pub(crate) fn read(&self, key: K) -> Result<Vec<Point>, io::Error> {
+        let path = match self.buffers.get(&key) {
+            Some((path, _)) => path,
+            None => return Ok(Vec::new()),
+        };
+        let buf = fs::read(path)?;
+        let points = buf
+            .chunks_exact(self.record_size)
+            .map(|record| {
+                let position = Vector3 {
+                    x: LittleEndian::read_f64(&record[0..8]),
+                    y: LittleEndian::read_f64(&record[8..16]),
+                    z: LittleEndian::read_f64(&record[16..24]),
+                };
+                Point::with_attributes(position, record[24..].to_vec())
+            })
+            .collect();
+        fs::remove_file(path)?;
+        Ok(points)
+    }
+}
+
+impl<K> Drop for Spill<K> {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::attributes::{Attribute, AttributeType};
+
+    #[test]
+    fn test_spill() -> Result<(), io::Error> {
+        let attributes =
+            Attributes::from_attributes(vec![Attribute::new("value", AttributeType::UINT8, 1)]);
+        let dir = std::env::temp_dir().join("potree-spill-test");
+        let mut spill = Spill::new(dir.clone(), &attributes)?;
+        spill.threshold = 100;
+        for i in 0..50 {
+            let position = Vector3 {
+                x: i as f64,
+                y: 0.5,
+                z: -1.0,
+            };
+            spill.push((i % 2, 0), &Point::with_attributes(position, vec![i as u8]))?;
+        }
+        spill.flush()?;
+        assert_eq!(spill.keys(), [(0, 0), (1, 0)]);
+
+        let odd = spill.read((1, 0))?;
+        assert_eq!(odd.len(), 25);
+        assert_eq!((odd[3].position.x, odd[3].attributes[0]), (7.0, 7));
+        assert_eq!((odd[3].position.y, odd[3].position.z), (0.5, -1.0));
+        drop(spill);
+        assert!(!dir.exists());
+        Ok(())
+    }
+}
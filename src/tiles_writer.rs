@@ -0,0 +1,450 @@
+use std::fs::{self, File};
+use std::io::Error;
+use std::path::Path;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+use serde_json::{json, Map, Value};
+
+use crate::model::attributes::{Attribute, AttributeType, Attributes};
+use crate::model::bounds::Bounds;
+use crate::model::node::Node;
+use crate::model::tileset::{Asset, BoundingVolume, Content, Tile, Tileset};
+use crate::potree::Potree;
+
+const PNTS_HEADER_SIZE: usize = 28;
+const WGS84_A: f64 = 6_378_137.0;
+const WGS84_F: f64 = 1.0 / 298.257_223_563;
+/// Meters per degree of latitude, to turn a spacing in degrees into a geometric error.
+const METERS_PER_DEGREE: f64 = 111_319.49;
+
+/// Whether a CRS names longitude, latitude and height coordinates rather than a projection
+/// or geocentric coordinates. WKT2 geodetic CRSs count only with an ellipsoidal coordinate
+/// system, as a Cartesian one makes them geocentric.
+pub(crate) fn is_geographic(crs: &str) -> bool {
+    let crs: String = crs
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_uppercase();
+    let geodetic = crs.starts_with("GEODCRS[") || crs.starts_with("GEODETICCRS[");
+    matches!(
+        crs.as_str(),
+        "EPSG:4326" | "EPSG:4979" | "OGC:CRS84" | "CRS84" | "WGS84"
+    ) || crs.starts_with("GEOGCS[")
+        || crs.starts_with("GEOGCRS[")
+        || crs.starts_with("GEOGRAPHICCRS[")
+        || (geodetic && crs.contains("CS[ELLIPSOIDAL,"))
+}
+
+/// Earth-centered, earth-fixed coordinates of a WGS84 longitude and latitude in degrees.
+fn to_ecef(lon: f64, lat: f64, height: f64) -> [f64; 3] {
+    let (lon, lat) = (lon.to_radians(), lat.to_radians());
+    let e2 = WGS84_F * (2.0 - WGS84_F);
+    let n = WGS84_A / (1.0 - e2 * lat.sin() * lat.sin()).sqrt();
+    [
+        (n + height) * lat.cos() * lon.cos(),
+        (n + height) * lat.cos() * lon.sin(),
+        (n * (1.0 - e2) + height) * lat.sin(),
+    ]
+}
+
+/// The 3D Tiles component type an attribute is stored as, 64 bit integers as doubles.
+fn component_type(r#type: AttributeType) -> (&'static str, AttributeType) {
+    match r#type {
+        AttributeType::INT8 => ("BYTE", r#type),
+        AttributeType::UINT8 => ("UNSIGNED_BYTE", r#type),
+        AttributeType::INT16 => ("SHORT", r#type),
+        AttributeType::UINT16 => ("UNSIGNED_SHORT", r#type),
+        AttributeType::INT32 => ("INT", r#type),
+        AttributeType::UINT32 => ("UNSIGNED_INT", r#type),
+        AttributeType::FLOAT => ("FLOAT", r#type),
+        _ => ("DOUBLE", AttributeType::DOUBLE),
+    }
+}
+
+fn element_type(num_elements: i32) -> &'static str {
+    match num_elements {
+        2 => "VEC2",
+        3 => "VEC3",
+        4 => "VEC4",
+        _ => "SCALAR",
+    }
+}
+
+fn pad(buf: &mut Vec<u8>, start: usize, alignment: usize, byte: u8) {
+    while !(start + buf.len()).is_multiple_of(alignment) {
+        buf.push(byte);
+    }
+}
+
+/// An attribute and where its values start in the packed point attributes.
+struct Source<'a> {
+    attribute: &'a Attribute,
+    offset: usize,
+}
+
+impl Source<'_> {
+    fn values<'p>(&self, attributes: &'p [u8]) -> impl Iterator<Item = f64> + 'p {
+        let r#type = self.attribute.r#type;
+        let size = r#type.size() as usize;
+        let start = self.offset;
+        (0..self.attribute.num_elements as usize).map(move |element| {
+            let begin = start + element * size;
+            r#type.read_f64(&attributes[begin..begin + size])
+        })
+    }
+}
+
+/// How the attributes of the octree map onto `.pnts` feature and batch table properties.
+struct Layout<'a> {
+    color: Option<(Source<'a>, f64)>,
+    normal: Option<Source<'a>>,
+    batch: Vec<Source<'a>>,
+    geographic: bool,
+}
+
+impl<'a> Layout<'a> {
+    fn new(attributes: &'a Attributes, geographic: bool) -> Layout<'a> {
+        let mut layout = Layout {
+            color: None,
+            normal: None,
+            batch: Vec::new(),
+            geographic,
+        };
+        for attribute in &attributes.list {
+            let source = Source {
+                attribute,
+                offset: attributes.get_offset(&attribute.name) as usize,
+            };
+            let name = attribute.name.to_lowercase();
+            if layout.color.is_none()
+                && ["rgb", "rgba", "color"].contains(&name.as_str())
+                && attribute.num_elements >= 3
+            {
+                let max = attribute.max.to_array().into_iter().fold(0.0, f64::max);
+                layout.color = Some((source, if max > 255.0 { 1.0 / 257.0 } else { 1.0 }));
+            } else if layout.normal.is_none()
+                && name == "normal"
+                && attribute.num_elements == 3
+                && attribute.r#type == AttributeType::FLOAT
+            {
+                layout.normal = Some(source);
+            } else {
+                layout.batch.push(source);
+            }
+        }
+        layout
+    }
+
+    fn position(&self, position: [f64; 3]) -> [f64; 3] {
+        if self.geographic {
+            to_ecef(position[0], position[1], position[2])
+        } else {
+            position
+        }
+    }
+
+    fn center(&self, bounds: &Bounds) -> [f64; 3] {
+        self.position([
+            (bounds.lx + bounds.ux) / 2.0,
+            (bounds.ly + bounds.uy) / 2.0,
+            (bounds.lz + bounds.uz) / 2.0,
+        ])
+    }
+
+    fn bounding_volume(&self, bounds: &Bounds) -> BoundingVolume {
+        if self.geographic {
+            BoundingVolume::Region([
+                bounds.lx.to_radians(),
+                bounds.ly.to_radians(),
+                bounds.ux.to_radians(),
+                bounds.uy.to_radians(),
+                bounds.lz,
+                bounds.uz,
+            ])
+        } else {
+            let [cx, cy, cz] = self.center(bounds);
+            let [hx, hy, hz] = [
+                bounds.size_x / 2.0,
+                bounds.size_y / 2.0,
+                bounds.size_z / 2.0,
+            ];
+            BoundingVolume::Box([cx, cy, cz, hx, 0.0, 0.0, 0.0, hy, 0.0, 0.0, 0.0, hz])
+        }
+    }
+
+    /// Encodes the points of a node as a `.pnts` tile with positions quantized relative to
+    /// the center of the node.
+    fn pnts(&self, node: &Node) -> Vec<u8> {
+        let points = node.points();
+        let center = self.center(&node.bounds);
+        let positions: Vec<[f64; 3]> = points
+            .iter()
+            .map(|point| {
+                let position = self.position(point.position.to_array());
+                [0, 1, 2].map(|axis| position[axis] - center[axis])
+            })
+            .collect();
+        let mut min = [f64::INFINITY; 3];
+        let mut max = [f64::NEG_INFINITY; 3];
+        for position in &positions {
+            min = [0, 1, 2].map(|axis| min[axis].min(position[axis]));
+            max = [0, 1, 2].map(|axis| max[axis].max(position[axis]));
+        }
+        let scale = [0, 1, 2].map(|axis| (max[axis] - min[axis]).max(f64::EPSILON));
+
+        let mut feature_table = Map::new();
+        feature_table.insert("POINTS_LENGTH".to_string(), json!(points.len()));
+        feature_table.insert("RTC_CENTER".to_string(), json!(center));
+        feature_table.insert("QUANTIZED_VOLUME_OFFSET".to_string(), json!(min));
+        feature_table.insert("QUANTIZED_VOLUME_SCALE".to_string(), json!(scale));
+        feature_table.insert("POSITION_QUANTIZED".to_string(), json!({ "byteOffset": 0 }));
+        let mut feature_binary = Vec::new();
+        for position in &positions {
+            for axis in 0..3 {
+                let quantized = ((position[axis] - min[axis]) / scale[axis] * 65535.0).round();
+                AttributeType::UINT16.write_f64(quantized, &mut feature_binary);
+            }
+        }
+        if let Some((source, factor)) = &self.color {
+            feature_table.insert(
+                "RGB".to_string(),
+                json!({ "byteOffset": feature_binary.len() }),
+            );
+            for point in &points {
+                for channel in source.values(&point.attributes).take(3) {
+                    AttributeType::UINT8.write_f64((channel * factor).round(), &mut feature_binary);
+                }
+            }
+        }
+        if let Some(source) = &self.normal {
+            pad(&mut feature_binary, 0, 4, 0);
+            feature_table.insert(
+                "NORMAL".to_string(),
+                json!({ "byteOffset": feature_binary.len() }),
+            );
+            for point in &points {
+                let bytes = &point.attributes[source.offset..source.offset + 12];
+                feature_binary.extend_from_slice(bytes);
+            }
+        }
+
+        let mut batch_table = Map::new();
+        let mut batch_binary = Vec::new();
+        for source in &self.batch {
+            let (component, r#type) = component_type(source.attribute.r#type);
+            pad(&mut batch_binary, 0, r#type.size() as usize, 0);
+            batch_table.insert(
+                source.attribute.name.clone(),
+                json!({
+                    "byteOffset": batch_binary.len(),
+                    "componentType": component,
+                    "type": element_type(source.attribute.num_elements),
+                }),
+            );
+            for point in &points {
+                for value in source.values(&point.attributes) {
+                    r#type.write_f64(value, &mut batch_binary);
+                }
+            }
+        }
+
+        let mut feature_json = Value::Object(feature_table).to_string().into_bytes();
+        pad(&mut feature_json, PNTS_HEADER_SIZE, 8, b' ');
+        pad(&mut feature_binary, 0, 8, 0);
+        let mut batch_json = if batch_table.is_empty() {
+            Vec::new()
+        } else {
+            Value::Object(batch_table).to_string().into_bytes()
+        };
+        pad(&mut batch_json, 0, 8, b' ');
+        pad(&mut batch_binary, 0, 8, 0);
+
+        let lengths = [
+            feature_json.len(),
+            feature_binary.len(),
+            batch_json.len(),
+            batch_binary.len(),
+        ];
+        let byte_length = PNTS_HEADER_SIZE + lengths.iter().sum::<usize>();
+        let mut buf = Vec::with_capacity(byte_length);
+        buf.extend_from_slice(b"pnts");
+        buf.write_u32::<LittleEndian>(1).unwrap();
+        buf.write_u32::<LittleEndian>(byte_length as u32).unwrap();
+        for length in lengths {
+            buf.write_u32::<LittleEndian>(length as u32).unwrap();
+        }
+        for part in [feature_json, feature_binary, batch_json, batch_binary] {
+            buf.extend(part);
+        }
+        buf
+    }
+}
+
+fn non_empty_children(node: &Node) -> impl Iterator<Item = &Node> {
+    node.children
+        .iter()
+        .flatten()
+        .map(|child| child.as_ref())
+        .filter(|child| child.num_points() > 0)
+}
+
+fn write_tile(node: &Node, potree: &Potree, layout: &Layout, dir: &Path) -> Result<Tile, Error> {
+    let uri = format!("{}.pnts", node.name);
+    fs::write(dir.join(&uri), layout.pnts(node))?;
+
+    let children = non_empty_children(node)
+        .map(|child| write_tile(child, potree, layout, dir))
+        .collect::<Result<Vec<Tile>, Error>>()?;
+    let spacing = potree.spacing / 2f64.powi(node.level() as i32);
+    Ok(Tile {
+        bounding_volume: layout.bounding_volume(&node.bounds),
+        // Leaves hold every remaining point, so nothing is missing once they are shown.
+        geometric_error: if children.is_empty() {
+            0.0
+        } else if layout.geographic {
+            spacing * METERS_PER_DEGREE
+        } else {
+            spacing
+        },
+        refine: (node.level() == 0).then(|| "ADD".to_string()),
+        content: Content { uri },
+        children,
+    })
+}
+
+/// Writes the octree as a 3D Tiles point cloud tileset: `tileset.json` and a `.pnts`
+/// tile per node, named after the node.
+///
+/// Tiles hold positions quantized around an RTC center, colors as `RGB`, float normals as
+/// `NORMAL` and all other attributes in the batch table. Geometric errors are the spacing of
+/// the node. With a geographic CRS, positions are read as longitude, latitude and height in
+/// degrees and meters, converted to earth-centered coordinates and bounded by regions;
+/// otherwise they are kept as they are and bounded by boxes.
+pub fn write_3d_tiles(potree: &Potree, dir: &Path) -> Result<(), Error> {
+    let geographic = potree.crs.as_deref().is_some_and(is_geographic);
+    let layout = Layout::new(&potree.attributes, geographic);
+    let root = write_tile(&potree.root, potree, &layout, dir)?;
+    let tileset = Tileset {
+        asset: Asset {
+            version: "1.0".to_string(),
+        },
+        geometric_error: if geographic {
+            potree.spacing * METERS_PER_DEGREE
+        } else {
+            potree.spacing
+        },
+        root,
+    };
+    let file = File::create(dir.join("tileset.json"))?;
+    serde_json::to_writer(file, &tileset)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::point::Point;
+    use crate::model::vector3::Vector3;
+
+    fn potree(origin: [f64; 3], step: f64) -> Potree {
+        let points = (0..500)
+            .map(|i| {
+                let position = Vector3 {
+                    x: origin[0] + (i % 10) as f64 * step,
+                    y: origin[1] + (i / 10 % 5) as f64 * step,
+                    z: origin[2] + (i % 3) as f64,
+                };
+                let mut attributes = vec![255, 255, 0, 0, 0, 128];
+                AttributeType::UINT64.write_f64(i as f64, &mut attributes);
+                Point::with_attributes(position, attributes)
+            })
+            .collect();
+        let attributes = Attributes::from_attributes(vec![
+            Attribute::new("rgb", AttributeType::UINT16, 3),
+            Attribute::new("id", AttributeType::UINT64, 1),
+        ]);
+        Potree::with_attributes(points, attributes, 100)
+    }
+
+    fn read_pnts(buf: &[u8]) -> (Value, Value) {
+        let length = |i: usize| u32::from_le_bytes(buf[i..i + 4].try_into().unwrap()) as usize;
+        assert_eq!(&buf[0..4], b"pnts");
+        assert_eq!(length(8), buf.len());
+        let (feature_json, feature_binary, batch_json) = (length(12), length(16), length(20));
+        let batch_start = PNTS_HEADER_SIZE + feature_json + feature_binary;
+        assert!(batch_start.is_multiple_of(8));
+        (
+            serde_json::from_slice(&buf[PNTS_HEADER_SIZE..PNTS_HEADER_SIZE + feature_json])
+                .unwrap(),
+            serde_json::from_slice(&buf[batch_start..batch_start + batch_json]).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_write_3d_tiles() -> Result<(), Box<dyn std::error::Error>> {
+        let potree = potree([1000.0, 2000.0, 0.0], 1.0);
+        let dir = std::env::temp_dir().join("potree-3d-tiles-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir)?;
+        write_3d_tiles(&potree, &dir)?;
+
+        let tileset: Tileset = serde_json::from_slice(&fs::read(dir.join("tileset.json"))?)?;
+        assert!(matches!(
+            tileset.root.bounding_volume,
+            BoundingVolume::Box(_)
+        ));
+        assert!(!tileset.root.children.is_empty());
+        assert_eq!(tileset.root.geometric_error, potree.spacing);
+
+        let (feature_table, batch_table) = read_pnts(&fs::read(dir.join("r.pnts"))?);
+        assert_eq!(feature_table["POINTS_LENGTH"], potree.root.num_points());
+        assert_eq!(feature_table["RTC_CENTER"][0], 1004.5);
+        assert!(feature_table.get("RGB").is_some());
+        assert_eq!(batch_table["id"]["componentType"], "DOUBLE");
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_geographic() {
+        assert!(is_geographic("epsg:4326"));
+        assert!(!is_geographic("EPSG:4978"));
+        assert!(!is_geographic("EPSG:25832"));
+        assert!(is_geographic(
+            r#"GEODCRS["WGS 84",DATUM["World Geodetic System 1984",ELLIPSOID["WGS 84",6378137,298.257223563]],
+            CS[ellipsoidal, 2],AXIS["latitude",north],AXIS["longitude",east],ANGLEUNIT["degree",0.0174532925199433]]"#
+        ));
+        assert!(!is_geographic(
+            r#"GEODCRS["WGS 84",DATUM["World Geodetic System 1984",ELLIPSOID["WGS 84",6378137,298.257223563]],
+            CS[Cartesian,3],AXIS["(X)",geocentricX],AXIS["(Y)",geocentricY],AXIS["(Z)",geocentricZ],LENGTHUNIT["metre",1]]"#
+        ));
+    }
+
+    #[test]
+    fn test_write_geographic_tiles() -> Result<(), Box<dyn std::error::Error>> {
+        let mut potree = potree([8.5, 47.3, 400.0], 0.0001);
+        potree.crs = Some("EPSG:4326".to_string());
+        let dir = std::env::temp_dir().join("potree-3d-tiles-geographic-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir)?;
+        write_3d_tiles(&potree, &dir)?;
+
+        let tileset: Tileset = serde_json::from_slice(&fs::read(dir.join("tileset.json"))?)?;
+        match tileset.root.bounding_volume {
+            BoundingVolume::Region(region) => {
+                assert!((region[0] - 8.5_f64.to_radians()).abs() < 1e-9)
+            }
+            BoundingVolume::Box(_) => panic!("expected a region"),
+        }
+        let (feature_table, _) = read_pnts(&fs::read(dir.join("r.pnts"))?);
+        let center: Vec<f64> = serde_json::from_value(feature_table["RTC_CENTER"].clone())?;
+        let radius = center.iter().map(|v| v * v).sum::<f64>().sqrt();
+        assert!((radius - 6_367_000.0).abs() < 10_000.0);
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,230 @@
+use core::fmt;
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+
+use rayon::prelude::*;
+
+use crate::model::attributes::{Attribute, Attributes};
+use crate::model::bounds::find_bounds;
+use crate::model::metadata::BoundingBox;
+use crate::model::point::Point;
+use crate::model::tile_index::{Tile, TileIndex};
+use crate::model::vector3::Vector3;
+use crate::point_reader::{PointReader, ReadError};
+use crate::potree::{BuildOptions, Potree};
+use crate::spill::Spill;
+use crate::writer::{write_potree_with_options, WriteOptions};
+
+#[derive(Debug)]
+pub enum TileError {
+    Read(ReadError),
+    Io(io::Error),
+    InvalidTileSize { size: f64 },
+}
+
+impl fmt::Display for TileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TileError::Read(error) => write!(f, "{}", error),
+            TileError::Io(error) => write!(f, "{}", error),
+            TileError::InvalidTileSize { size } => {
+                write!(f, "The tile size must be a positive number, not {}", size)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TileError {}
+
+impl From<ReadError> for TileError {
+    fn from(error: ReadError) -> TileError {
+        TileError::Read(error)
+    }
+}
+
+impl From<io::Error> for TileError {
+    fn from(error: io::Error) -> TileError {
+        TileError::Io(error)
+    }
+}
+
+/// How input is split into tiles and how each tile is converted.
+pub struct TileOptions {
+    /// Edge length of the square grid cells in units of the coordinate reference system.
+    /// Cells are aligned to its origin, so tiles of separate conversions line up.
+    pub tile_size: f64,
+    pub build: BuildOptions,
+    pub write: WriteOptions,
+}
+
+fn tile_name((column, row): (i64, i64)) -> String {
+    format!("tile_{}_{}", column, row)
+}
+
+/// Attributes of the tile with the ranges of its own points.
+fn tile_attributes(attributes: &Attributes, points: &[Point]) -> Attributes {
+    let list = attributes
+        .list
+        .iter()
+        .map(|attribute| Attribute {
+            min: Vector3::infinity(),
+            max: Vector3::infinity() * -1.0,
+            ..attribute.clone()
+        })
+        .collect();
+    let mut attributes = Attributes::from_attributes(list);
+    for point in points {
+        attributes.update_ranges(&point.attributes);
+    }
+    attributes
+}
+
+/// Splits the points of `reader` into a grid of square columns and converts every
+/// non-empty one into its own dataset below `dir`, in parallel. The tiles are listed in
+/// `index.json` next to them, which is also returned.
+///
+/// Points are spilled to a file per tile in `dir` while reading and a tile is only read
+/// back when it is converted, so memory holds the tiles being converted, one per thread,
+/// rather than the whole input.
+pub fn write_tiles(
+    reader: &mut dyn PointReader,
+    dir: &Path,
+    options: &TileOptions,
+) -> Result<TileIndex, TileError> {
+    let size = options.tile_size;
+    if !(size.is_finite() && size > 0.0) {
+        return Err(TileError::InvalidTileSize { size });
+    }
+    let header = reader.header().clone();
+    let crs = options.build.crs.clone().or(header.crs);
+
+    let mut spill = Spill::new(dir.join(".spill"), &header.attributes)?;
+    while let Some(batch) = reader.next_batch() {
+        for point in batch? {
+            let cell = (
+                (point.position.x / size).floor() as i64,
+                (point.position.y / size).floor() as i64,
+            );
+            spill.push(cell, &point)?;
+        }
+    }
+    spill.flush()?;
+    let cells = spill.keys();
+    if cells.is_empty() {
+        return Err(TileError::Read(ReadError::NoPoints));
+    }
+
+    let tiles = cells
+        .into_par_iter()
+        .map(|cell| {
+            let points = spill.read(cell)?;
+            let name = tile_name(cell);
+            let bounds = find_bounds(&points);
+            let attributes = tile_attributes(&header.attributes, &points);
+            let mut potree = Potree::with_attributes(
+                points,
+                attributes,
+                options.build.point_per_leaf_node_limit,
+            );
+            potree.crs = crs.clone();
+            let tile = Tile {
+                name: name.clone(),
+                bounds: BoundingBox {
+                    min: [bounds.lx, bounds.ly, bounds.lz],
+                    max: [bounds.ux, bounds.uy, bounds.uz],
+                },
+                points: potree.size as u64,
+            };
+
+            let tile_dir = dir.join(&name);
+            fs::create_dir_all(&tile_dir)?;
+            write_potree_with_options(potree, &tile_dir, &options.write)?;
+            Ok(tile)
+        })
+        .collect::<Result<Vec<Tile>, io::Error>>()?;
+
+    let index = TileIndex {
+        tile_size: size,
+        projection: crs.unwrap_or_default(),
+        tiles,
+    };
+    let file = File::create(dir.join("index.json"))?;
+    serde_json::to_writer(file, &index).map_err(io::Error::from)?;
+    Ok(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dataset::PotreeDataset;
+    use crate::model::attributes::AttributeType;
+    use crate::model::point_cloud::PointCloud;
+    use crate::point_reader::PointCloudReader;
+
+    #[test]
+    fn test_write_tiles() -> Result<(), Box<dyn std::error::Error>> {
+        let points = (0..3000)
+            .map(|i| {
+                let position = Vector3 {
+                    x: (i % 60) as f64 - 10.0,
+                    y: (i / 60) as f64,
+                    z: (i % 7) as f64,
+                };
+                Point::with_attributes(position, vec![(i % 256) as u8])
+            })
+            .collect();
+        let attributes =
+            Attributes::from_attributes(vec![Attribute::new("value", AttributeType::UINT8, 1)]);
+        let mut reader = PointCloudReader::new(PointCloud::new(points, attributes));
+
+        let dir = std::env::temp_dir().join("potree-tiling-test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir)?;
+        let options = TileOptions {
+            tile_size: 25.0,
+            build: BuildOptions::default(),
+            write: WriteOptions::default(),
+        };
+        let index = write_tiles(&mut reader, &dir, &options)?;
+
+        let names: Vec<&str> = index.tiles.iter().map(|tile| tile.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "tile_-1_0",
+                "tile_-1_1",
+                "tile_0_0",
+                "tile_0_1",
+                "tile_1_0",
+                "tile_1_1"
+            ]
+        );
+        assert_eq!(
+            index.tiles.iter().map(|tile| tile.points).sum::<u64>(),
+            3000
+        );
+        let first = &index.tiles[0];
+        assert_eq!(first.points, 10 * 25);
+        assert_eq!(
+            (first.bounds.min, first.bounds.max),
+            ([-10.0, 0.0, 0.0], [-1.0, 24.0, 6.0])
+        );
+
+        let written: TileIndex = serde_json::from_slice(&fs::read(dir.join("index.json"))?)?;
+        assert_eq!(written.tiles.len(), 6);
+        let dataset = PotreeDataset::open(&dir.join("tile_0_1"))?;
+        assert_eq!(dataset.metadata.points, index.tiles[3].points);
+        assert!(!dir.join(".spill").exists());
+
+        let zero = TileOptions {
+            tile_size: 0.0,
+            ..options
+        };
+        assert!(matches!(
+            write_tiles(&mut reader, &dir, &zero),
+            Err(TileError::InvalidTileSize { .. })
+        ));
+        Ok(())
+    }
+}
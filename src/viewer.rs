@@ -0,0 +1,147 @@
+use crate::model::metadata::Metadata;
+
+const TEMPLATE: &str = include_str!("viewer.html");
+
+/// Settings of the `index.html` viewer page written next to a dataset.
+pub struct ViewerOptions {
+    pub title: String,
+    /// Maximum number of points the viewer renders at once.
+    pub point_budget: u32,
+    /// Location of a Potree 1.8 checkout holding `build/potree` and `libs`, relative to
+    /// the page or as a URL.
+    pub potree_url: String,
+}
+
+impl Default for ViewerOptions {
+    fn default() -> ViewerOptions {
+        ViewerOptions {
+            title: "Point cloud".to_string(),
+            point_budget: 1_000_000,
+            potree_url: "potree".to_string(),
+        }
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// A JavaScript string literal that cannot close the surrounding script tag.
+fn js_string(text: &str) -> String {
+    serde_json::to_string(text).unwrap().replace("</", "<\\/")
+}
+
+/// The attribute the points are colored by: colors if present, otherwise intensity,
+/// classification or, without any of them, elevation.
+fn default_attribute(metadata: &Metadata) -> &'static str {
+    let has = |names: &[&str]| {
+        metadata
+            .attributes
+            .iter()
+            .any(|attribute| names.contains(&attribute.name.to_lowercase().as_str()))
+    };
+    if has(&["rgb", "rgba", "color"]) {
+        "rgba"
+    } else if has(&["intensity"]) {
+        "intensity"
+    } else if has(&["classification"]) {
+        "classification"
+    } else {
+        "elevation"
+    }
+}
+
+/// Renders the viewer page for a dataset, looking at the center of its bounding box from
+/// the south and above.
+pub fn viewer_html(metadata: &Metadata, options: &ViewerOptions) -> String {
+    let (min, max) = (metadata.bounding_box.min, metadata.bounding_box.max);
+    let center: Vec<f64> = min
+        .iter()
+        .zip(max)
+        .map(|(min, max)| (min + max) / 2.0)
+        .collect();
+    let diagonal = min
+        .iter()
+        .zip(max)
+        .map(|(min, max)| (max - min).powi(2))
+        .sum::<f64>()
+        .sqrt();
+    let position = [center[0], center[1] - diagonal, center[2] + diagonal / 2.0];
+    let join = |values: &[f64]| {
+        values
+            .iter()
+            .map(|value| value.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    TEMPLATE
+        .replace("{{TITLE}}", &escape_html(&options.title))
+        .replace("{{POTREE}}", &escape_html(&options.potree_url))
+        .replace("{{POINT_BUDGET}}", &options.point_budget.to_string())
+        .replace("{{NAME}}", &js_string(&options.title))
+        .replace("{{ATTRIBUTE}}", &js_string(default_attribute(metadata)))
+        .replace("{{CAMERA_POSITION}}", &join(&position))
+        .replace("{{CAMERA_TARGET}}", &join(&center))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::attributes::{Attribute, AttributeType, Attributes};
+    use crate::model::point::Point;
+    use crate::model::vector3::Vector3;
+    use crate::potree::Potree;
+    use crate::writer::write_potree_to_buffers;
+
+    fn metadata(attributes: Vec<Attribute>) -> Metadata {
+        let bytes: usize = attributes.iter().map(|a| a.size as usize).sum();
+        let points = (0..10)
+            .map(|i| {
+                let position = Vector3 {
+                    x: i as f64,
+                    y: 2.0 * i as f64,
+                    z: 0.0,
+                };
+                Point::with_attributes(position, vec![0; bytes])
+            })
+            .collect();
+        let potree = Potree::with_attributes(points, Attributes::from_attributes(attributes), 100);
+        write_potree_to_buffers(&potree).metadata
+    }
+
+    #[test]
+    fn test_viewer_html() {
+        let metadata = metadata(vec![
+            Attribute::new("intensity", AttributeType::UINT16, 1),
+            Attribute::new("rgb", AttributeType::UINT16, 3),
+        ]);
+        let options = ViewerOptions {
+            title: "Drive </script> 1".to_string(),
+            point_budget: 2_000_000,
+            ..ViewerOptions::default()
+        };
+        let html = viewer_html(&metadata, &options);
+
+        assert!(!html.contains("{{"));
+        assert!(html.contains("<title>Drive &lt;/script&gt; 1</title>"));
+        assert!(html.contains("viewer.setPointBudget(2000000);"));
+        assert!(html.contains(r#"Potree.loadPointCloud("metadata.json", "Drive <\/script> 1","#));
+        assert!(html.contains(r#"material.activeAttributeName = "rgba";"#));
+        assert!(html.contains("new THREE.Vector3(4.5, 9, 0)"));
+    }
+
+    #[test]
+    fn test_default_attribute() {
+        let classified = metadata(vec![Attribute::new(
+            "classification",
+            AttributeType::UINT8,
+            1,
+        )]);
+        assert_eq!(default_attribute(&classified), "classification");
+        assert_eq!(default_attribute(&metadata(Vec::new())), "elevation");
+    }
+}
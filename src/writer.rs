@@ -1,3 +1,4 @@
+use crate::archive::{write_archive, Compression};
 use crate::model::hierarchy::create_hierarchy;
 use crate::model::hierarchy::Hierarchy;
 use crate::model::metadata::Attribute;
@@ -7,14 +8,16 @@ use crate::model::options::{Encoding, Options};
 use crate::model::vector3::Vector3;
 use crate::model::State;
 use crate::potree::Potree;
+use crate::viewer::{viewer_html, ViewerOptions};
 use byteorder::{LittleEndian, WriteBytesExt};
-use std::collections::HashMap;
-use std::fs::File;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
 use std::io::Error;
 use std::io::Write;
+use std::io::Seek;
 use std::path::Path;
 
-const HRC_STEP_SIZE: usize = 5; // must be 2 or more
+pub(crate) const HRC_STEP_SIZE: usize = 5; // must be 2 or more
 
 #[derive(Debug)]
 pub enum WriteError {
@@ -28,20 +31,99 @@ impl fmt::Display for WriteError {
 }
 
 
+/// Options for writing a dataset to a directory.
+#[derive(Default)]
+pub struct WriteOptions {
+    /// Writes an `index.html` viewer page next to the dataset.
+    pub viewer: Option<ViewerOptions>,
+}
+
 pub fn write_potree(potree: Potree, dir: &Path) -> Result<(), Error> {
+    write_potree_with_options(potree, dir, &WriteOptions::default())
+}
+
+pub fn write_potree_with_options(
+    potree: Potree,
+    dir: &Path,
+    options: &WriteOptions,
+) -> Result<(), Error> {
     let mut f = File::create(dir.join("octree.bin")).expect("Unable to create file");
     let mut writer = Writer::new(&mut f);
     writer.write(&potree);
-    let hierarchy = create_hierarchy(&potree.root, writer.node_hierarchy);
+    write_index_files(&potree, writer.node_hierarchy, dir, options)
+}
+
+/// Writes `hierarchy.bin` and `metadata.json`, and the viewer page if asked for, for an
+/// octree whose nodes were written to `octree.bin` as recorded in `node_hierarchy`.
+pub(crate) fn write_index_files(
+    potree: &Potree,
+    node_hierarchy: BTreeMap<String, (u32, u32, u32)>,
+    dir: &Path,
+    options: &WriteOptions,
+) -> Result<(), Error> {
+    let hierarchy = create_hierarchy(&potree.root, node_hierarchy);
 
     write_hierarchy(&hierarchy, dir).unwrap();
 
-    let metadata = create_metadata(&potree, &hierarchy);
+    let metadata = create_metadata(potree, &hierarchy);
+    if let Some(viewer) = &options.viewer {
+        fs::write(dir.join("index.html"), viewer_html(&metadata, viewer))?;
+    }
     write_metadata(metadata, dir).unwrap();
 
     Ok(())
 }
 
+/// The three files making up a potree dataset, held in memory.
+pub struct PotreeFiles {
+    pub octree: Vec<u8>,
+    pub hierarchy: Vec<u8>,
+    pub metadata: Metadata,
+}
+
+impl PotreeFiles {
+    /// Packs `metadata.json`, `hierarchy.bin` and `octree.bin` into a ZIP archive written to `out`.
+    pub fn write_archive<W: Write + Seek>(
+        &self,
+        out: W,
+        compression: Compression,
+    ) -> Result<W, Error> {
+        let metadata = serde_json::to_vec(&self.metadata)?;
+        let files: [(&str, &[u8]); 3] = [
+            ("metadata.json", &metadata),
+            ("hierarchy.bin", &self.hierarchy),
+            ("octree.bin", &self.octree),
+        ];
+        let out = write_archive(out, &files, compression)?;
+
+        Ok(out)
+    }
+}
+
+pub fn write_potree_to_buffers(potree: &Potree) -> PotreeFiles {
+    let mut octree: Vec<u8> = Vec::new();
+    let mut writer = Writer::new(&mut octree);
+    writer.write(potree);
+    let hierarchy = create_hierarchy(&potree.root, writer.node_hierarchy);
+
+    let metadata = create_metadata(potree, &hierarchy);
+
+    PotreeFiles {
+        octree,
+        hierarchy: hierarchy.buffer,
+        metadata,
+    }
+}
+
+/// Writes the dataset as a single ZIP archive instead of a directory.
+pub fn write_potree_archive<W: Write + Seek>(
+    potree: Potree,
+    out: W,
+    compression: Compression,
+) -> Result<W, Error> {
+    write_potree_to_buffers(&potree).write_archive(out, compression)
+}
+
 fn write_hierarchy(hierarchy: &Hierarchy, dir: &Path) -> Result<(), Error> {
     let mut file = File::create(dir.join("hierarchy.bin"))?;
     file.write_all(&hierarchy.buffer)?;
@@ -49,19 +131,24 @@ fn write_hierarchy(hierarchy: &Hierarchy, dir: &Path) -> Result<(), Error> {
 }
 
 pub fn create_metadata(potree: &Potree, hierarchy: &Hierarchy) -> Metadata {
-    Metadata::create(
+    let mut attributes = vec![Attribute {
+        name: "position".to_string(),
+        description: "".to_string(),
+        size: 12,
+        num_elements: 3,
+        element_size: 4,
+        r#type: "int32".to_string(),
+
+        min: vec![potree.bounds.lx, potree.bounds.ly, potree.bounds.lz],
+        max: vec![potree.bounds.ux, potree.bounds.uy, potree.bounds.uz],
+    }];
+    for attribute in &potree.attributes.list {
+        attributes.push(Attribute::from_attribute(attribute));
+    }
+
+    let mut metadata = Metadata::create(
         &potree.root,
-        vec![Attribute {
-            name: "position".to_string(),
-            description: "".to_string(),
-            size: 12,
-            num_elements: 3,
-            element_size: 4,
-            r#type: "int32".to_string(),
-
-            min: vec![potree.bounds.lx, potree.bounds.ly, potree.bounds.lz],
-            max: vec![potree.bounds.ux, potree.bounds.uy, potree.bounds.uz],
-        }],
+        attributes,
         &Options {
             encoding: Encoding::DEFAULT,
             keep_chunks: false,
@@ -74,7 +161,10 @@ pub fn create_metadata(potree: &Potree, hierarchy: &Hierarchy) -> Metadata {
         hierarchy,
         potree.spacing,
         potree.scale,
-    )
+    );
+    metadata.extras = potree.extras.clone();
+    metadata.projection = potree.crs.clone().unwrap_or_default();
+    metadata
 }
 
 fn write_metadata(metadata: Metadata, dir: &Path) -> Result<(), Error> {
@@ -87,7 +177,7 @@ fn write_metadata(metadata: Metadata, dir: &Path) -> Result<(), Error> {
 pub struct Writer<'a, T: std::io::Write> {
     byte_offset: u32,
     bytes_per_point: u32,
-    pub node_hierarchy: HashMap<String, (u32, u32)>,
+    pub node_hierarchy: BTreeMap<String, (u32, u32, u32)>,
 	buf_writer: &'a mut T
 }
 
@@ -96,13 +186,29 @@ impl<T: std::io::Write> Writer<'_, T> {
         Writer {
             byte_offset: 0,
             bytes_per_point: 12,
-            node_hierarchy: HashMap::new(),
+            node_hierarchy: BTreeMap::new(),
 			buf_writer
         }
     }
 
+    /// Continues an `octree.bin` that already holds `byte_offset` bytes of records that are
+    /// `bytes_per_point` long.
+    pub(crate) fn appending<'a>(
+        buf_writer: &'a mut T,
+        byte_offset: u32,
+        bytes_per_point: u32,
+    ) -> Writer<'a, T> {
+        Writer {
+            byte_offset,
+            bytes_per_point,
+            node_hierarchy: BTreeMap::new(),
+            buf_writer,
+        }
+    }
+
     pub fn write(&mut self, potree: &Potree)
     {
+        self.bytes_per_point = 12 + potree.attributes.bytes as u32;
         let offset = Vector3 {
             x: potree.bounds.lx,
             y: potree.bounds.ly,
@@ -111,7 +217,8 @@ impl<T: std::io::Write> Writer<'_, T> {
         self.write_nodes(vec![&potree.root], potree.scale, &offset)
     }
 
-    fn write_nodes(
+    /// Writes the nodes and their descendants, level by level.
+    pub(crate) fn write_nodes(
         &mut self,
         nodes: Vec<&Node>,
         scale: f64,
@@ -143,7 +250,7 @@ impl<T: std::io::Write> Writer<'_, T> {
         }
     }
 
-    fn write_points(
+    pub(crate) fn write_points(
         &mut self,
         node: &Node,
         scale: f64,
@@ -152,18 +259,126 @@ impl<T: std::io::Write> Writer<'_, T> {
         let byte_size = node.num_points() as u32 * self.bytes_per_point;
         let byte_offset = self.byte_offset;
         for point in node.points() {
-            let cart_x = ((point.x - offset.x) / scale).round() as i32;
-            let cart_y = ((point.y - offset.y) / scale).round() as i32;
-            let cart_z = ((point.z - offset.z) / scale).round() as i32;
+            let cart_x = ((point.position.x - offset.x) / scale).round() as i32;
+            let cart_y = ((point.position.y - offset.y) / scale).round() as i32;
+            let cart_z = ((point.position.z - offset.z) / scale).round() as i32;
             self.buf_writer.write_i32::<LittleEndian>(cart_x).unwrap();
             self.buf_writer.write_i32::<LittleEndian>(cart_y).unwrap();
             self.buf_writer.write_i32::<LittleEndian>(cart_z).unwrap();
-            // Add more props here
+            self.buf_writer.write_all(&point.attributes).unwrap();
         }
 
         self.byte_offset += byte_size;
 
         self.node_hierarchy
-            .insert(node.name.to_string(), (byte_size, byte_offset));
+            .insert(node.name.to_string(), (byte_size, byte_offset, node.num_points() as u32));
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use crate::model::attributes::{Attribute, AttributeType, Attributes};
+    use crate::model::point::Point;
+    use crate::model::vector3::Vector3;
+    use crate::potree::Potree;
+    use crate::archive::Compression;
+    use crate::writer::{write_potree, write_potree_archive, write_potree_to_buffers};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use sha2::{Digest, Sha256};
+    use std::fs;
+    use std::io::{Cursor, Read};
+    use zip::ZipArchive;
+
+    #[test]
+    fn test_write_attributes() {
+        let points = (0..10)
+            .map(|i| {
+                let position = Vector3 { x: i as f64, y: 0.0, z: 1.0 };
+                Point::with_attributes(position, (i as u16).to_le_bytes().to_vec())
+            })
+            .collect();
+        let attributes = Attributes::from_attributes(vec![Attribute::new(
+            "intensity",
+            AttributeType::UINT16,
+            1,
+        )]);
+        let files = write_potree_to_buffers(&Potree::with_attributes(points, attributes, 20000));
+
+        assert_eq!(files.octree.len(), 10 * (12 + 2));
+        let intensity = &files.metadata.attributes[1];
+        assert_eq!(intensity.name, "intensity");
+        assert_eq!(intensity.r#type, "uint16");
+        assert_eq!((intensity.min.clone(), intensity.max.clone()), (vec![0.0], vec![9.0]));
+    }
+
+    #[test]
+    fn test_write_potree_archive() {
+        for compression in [Compression::Stored, Compression::Deflate] {
+            let points = (0..500)
+                .map(|i| Vector3 {
+                    x: (i % 50) as f64,
+                    y: (i / 50) as f64,
+                    z: (i % 7) as f64,
+                })
+                .collect();
+            let potree = Potree::new(points, 20000);
+            let files = write_potree_to_buffers(&potree);
+            let metadata = serde_json::to_vec(&files.metadata).unwrap();
+
+            let cursor =
+                write_potree_archive(potree, Cursor::new(Vec::new()), compression).unwrap();
+            let mut archive = ZipArchive::new(cursor).unwrap();
+            assert_eq!(archive.len(), 3);
+            for (name, expected) in [
+                ("metadata.json", &metadata),
+                ("hierarchy.bin", &files.hierarchy),
+                ("octree.bin", &files.octree),
+            ] {
+                let mut contents = Vec::new();
+                archive
+                    .by_name(name)
+                    .unwrap()
+                    .read_to_end(&mut contents)
+                    .unwrap();
+                assert_eq!(&contents, expected, "{}", name);
+            }
+        }
+    }
+
+    fn convert(dir: &str) -> Vec<u8> {
+        let mut rng = StdRng::seed_from_u64(42);
+        let points = (0..20000)
+            .map(|_| {
+                let position = Vector3 {
+                    x: rng.gen_range(0.0..100.0),
+                    y: rng.gen_range(0.0..50.0),
+                    z: rng.gen_range(0.0..10.0),
+                };
+                Point::with_attributes(position, rng.gen::<[u8; 2]>().to_vec())
+            })
+            .collect();
+        let attributes = Attributes::from_attributes(vec![Attribute::new(
+            "intensity",
+            AttributeType::UINT16,
+            1,
+        )]);
+        let dir = std::env::temp_dir().join(dir);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        write_potree(Potree::with_attributes(points, attributes, 100), &dir).unwrap();
+
+        let mut hasher = Sha256::new();
+        for file in ["octree.bin", "hierarchy.bin", "metadata.json"] {
+            hasher.update(fs::read(dir.join(file)).unwrap());
+        }
+        hasher.finalize().to_vec()
+    }
+
+    #[test]
+    fn test_deterministic_output() {
+        assert_eq!(
+            convert("potree-deterministic-a"),
+            convert("potree-deterministic-b")
+        );
+    }
+}
@@ -0,0 +1,259 @@
+use core::fmt;
+
+use crate::model::attributes::{Attribute, AttributeType, Attributes};
+use crate::model::point::Point;
+use crate::model::point_cloud::PointCloud;
+use crate::model::vector3::Vector3;
+
+const PTS_INTENSITY_MIN: f64 = -2048.0;
+const PTS_INTENSITY_MAX: f64 = 2047.0;
+
+#[derive(Debug)]
+pub enum XyzReadError {
+	NoPoints,
+	InvalidValue { line: usize, column: usize, value: String },
+	ColumnCount { line: usize, expected: usize, found: usize },
+}
+
+impl fmt::Display for XyzReadError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			XyzReadError::NoPoints => write!(f, "No points in file"),
+			XyzReadError::InvalidValue {
+				line,
+				column,
+				value,
+			} => write!(
+				f,
+				"Line {}: could not parse '{}' in column {} as a number",
+				line, value, column
+			),
+			XyzReadError::ColumnCount {
+				line,
+				expected,
+				found,
+			} => write!(f, "Line {}: expected {} columns, found {}", line, expected, found),
+		}
+	}
+}
+
+impl std::error::Error for XyzReadError {}
+
+/// Column layout detected from the first point line.
+enum Layout {
+	Xyz,
+	XyzIntensity,
+	XyzRgb,
+	XyzIntensityRgb,
+	/// Position followed by columns without a known meaning.
+	XyzExtra(usize),
+}
+
+impl Layout {
+	fn detect(columns: usize) -> Layout {
+		match columns {
+			3 => Layout::Xyz,
+			4 => Layout::XyzIntensity,
+			6 => Layout::XyzRgb,
+			7 => Layout::XyzIntensityRgb,
+			n => Layout::XyzExtra(n - 3),
+		}
+	}
+
+	/// Attributes of the layout. PTS intensities are mapped to uint16, others are kept as
+	/// doubles since their range is unknown.
+	fn attributes(&self, pts: bool) -> Vec<Attribute> {
+		let intensity = if pts { AttributeType::UINT16 } else { AttributeType::DOUBLE };
+		match self {
+			Layout::Xyz => Vec::new(),
+			Layout::XyzIntensity => vec![Attribute::new("intensity", intensity, 1)],
+			Layout::XyzRgb => vec![Attribute::new("rgb", AttributeType::UINT16, 3)],
+			Layout::XyzIntensityRgb => vec![
+				Attribute::new("intensity", intensity, 1),
+				Attribute::new("rgb", AttributeType::UINT16, 3),
+			],
+			Layout::XyzExtra(n) => (0..*n)
+				.map(|i| Attribute::new(&format!("column_{}", i + 3), AttributeType::DOUBLE, 1))
+				.collect(),
+		}
+	}
+
+	fn write_attributes(&self, values: &[f64], pts: bool, buf: &mut Vec<u8>) {
+		let (intensity, rgb): (Option<f64>, &[f64]) = match self {
+			Layout::Xyz => (None, &[]),
+			Layout::XyzIntensity => (Some(values[3]), &[]),
+			Layout::XyzRgb => (None, &values[3..6]),
+			Layout::XyzIntensityRgb => (Some(values[3]), &values[4..7]),
+			Layout::XyzExtra(_) => {
+				for value in &values[3..] {
+					AttributeType::DOUBLE.write_f64(*value, buf);
+				}
+				return;
+			}
+		};
+		match intensity {
+			Some(intensity) if pts => {
+				AttributeType::UINT16.write_f64(pts_intensity_to_u16(intensity), buf)
+			}
+			Some(intensity) => AttributeType::DOUBLE.write_f64(intensity, buf),
+			None => {}
+		}
+		for channel in rgb {
+			AttributeType::UINT16.write_f64(*channel, buf);
+		}
+	}
+}
+
+/// Maps the PTS intensity range -2048..2047 onto the full uint16 range.
+fn pts_intensity_to_u16(intensity: f64) -> f64 {
+	let clamped = intensity.clamp(PTS_INTENSITY_MIN, PTS_INTENSITY_MAX);
+	((clamped - PTS_INTENSITY_MIN) / (PTS_INTENSITY_MAX - PTS_INTENSITY_MIN) * 65535.0).round()
+}
+
+fn is_count_line(fields: &[&str]) -> bool {
+	fields.len() == 1 && fields[0].parse::<u64>().is_ok()
+}
+
+/// Reads whitespace separated XYZ/TXT exports and Leica PTS files.
+///
+/// Lines holding a single integer are treated as PTS point count lines and skipped, so
+/// files with several scans each prefixed by their count are read as one point cloud.
+/// The column layout is detected from the first point line: `x y z`, `x y z intensity`,
+/// `x y z r g b` or `x y z intensity r g b`. A file starting with a count line is read
+/// as PTS, with intensity in the range -2048..2047 mapped onto uint16; otherwise the
+/// intensity is kept as is. Any other count keeps the extra columns as double attributes
+/// named after their index.
+pub fn read_xyz(buf: &[u8]) -> Result<PointCloud, XyzReadError> {
+	read_text(buf, false)
+}
+
+/// Reads a Leica PTS file, mapping its intensities onto uint16 even without count lines.
+pub fn read_pts(buf: &[u8]) -> Result<PointCloud, XyzReadError> {
+	read_text(buf, true)
+}
+
+fn read_text(buf: &[u8], mut pts: bool) -> Result<PointCloud, XyzReadError> {
+	let text = String::from_utf8_lossy(buf);
+	let mut layout: Option<(Layout, usize)> = None;
+	let mut points: Vec<Point> = Vec::new();
+	let mut values: Vec<f64> = Vec::new();
+
+	for (i, line) in text.lines().enumerate() {
+		let line_number = i + 1;
+		let fields: Vec<&str> = line
+			.split(|c: char| c.is_whitespace() || c == ',' || c == ';')
+			.filter(|field| !field.is_empty())
+			.collect();
+		if fields.is_empty() || line.trim_start().starts_with('#') {
+			continue;
+		}
+		if is_count_line(&fields) {
+			pts |= layout.is_none();
+			continue;
+		}
+
+		if fields.len() < 3 {
+			return Err(XyzReadError::ColumnCount {
+				line: line_number,
+				expected: 3,
+				found: fields.len(),
+			});
+		}
+		let (layout, columns) =
+			layout.get_or_insert_with(|| (Layout::detect(fields.len()), fields.len()));
+		if fields.len() != *columns {
+			return Err(XyzReadError::ColumnCount {
+				line: line_number,
+				expected: *columns,
+				found: fields.len(),
+			});
+		}
+
+		values.clear();
+		for (column, field) in fields.iter().enumerate() {
+			let value = field.parse::<f64>().map_err(|_| XyzReadError::InvalidValue {
+				line: line_number,
+				column,
+				value: field.to_string(),
+			})?;
+			values.push(value);
+		}
+
+		let mut attributes = Vec::new();
+		layout.write_attributes(&values, pts, &mut attributes);
+		points.push(Point::with_attributes(
+			Vector3 {
+				x: values[0],
+				y: values[1],
+				z: values[2],
+			},
+			attributes,
+		));
+	}
+
+	let attribute_list = match layout {
+		Some((layout, _)) if !points.is_empty() => layout.attributes(pts),
+		_ => return Err(XyzReadError::NoPoints),
+	};
+	Ok(PointCloud::new(points, Attributes::from_attributes(attribute_list)))
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs;
+
+	use crate::model::attributes::AttributeType;
+	use crate::xyz_reader::{read_pts, read_xyz, XyzReadError};
+
+	#[test]
+	fn test_read_pts() -> Result<(), Box<dyn std::error::Error>> {
+		let buffer = fs::read("resources/points.pts")?;
+		let potree = read_xyz(&buffer)?.into_potree();
+
+		assert_eq!(potree.size, 5);
+		let intensity = potree.attributes.get("intensity").unwrap();
+		assert_eq!((intensity.min.x, intensity.max.x), (0.0, 65535.0));
+		let rgb = potree.attributes.get("rgb").unwrap();
+		assert_eq!(rgb.max.to_array(), [255.0, 200.0, 255.0]);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_read_xyz() -> Result<(), Box<dyn std::error::Error>> {
+		let potree = read_xyz(b"1.0 2.0 3.0\n\n4.0\t5.0 6.0\n")?.into_potree();
+
+		assert_eq!(potree.size, 2);
+		assert_eq!(potree.attributes.list.len(), 0);
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_read_xyz_intensity() -> Result<(), Box<dyn std::error::Error>> {
+		let buffer = b"1.0 2.0 3.0 0\n4.0 5.0 6.0 40000\n7.0 8.0 9.0 65535\n";
+		let cloud = read_xyz(buffer)?;
+		let intensity = cloud.attributes.get("intensity").unwrap();
+		assert_eq!(intensity.r#type, AttributeType::DOUBLE);
+		let potree = cloud.into_potree();
+		let intensity = potree.attributes.get("intensity").unwrap();
+		assert_eq!((intensity.min.x, intensity.max.x), (0.0, 65535.0));
+
+		let potree = read_pts(b"1.0 2.0 3.0 -2048\n4.0 5.0 6.0 2047\n")?.into_potree();
+		let intensity = potree.attributes.get("intensity").unwrap();
+		assert_eq!(intensity.r#type, AttributeType::UINT16);
+		assert_eq!((intensity.min.x, intensity.max.x), (0.0, 65535.0));
+
+		Ok(())
+	}
+
+	#[test]
+	fn test_read_xyz_column_count() {
+		match read_xyz(b"1.0 2.0 3.0\n4.0 5.0 6.0 7.0\n") {
+			Err(XyzReadError::ColumnCount { line, expected, found }) => {
+				assert_eq!((line, expected, found), (2, 3, 4));
+			}
+			_ => panic!("Expected column count error"),
+		}
+	}
+}